@@ -0,0 +1,102 @@
+use crate::crawlers::{evm_crawler, starknet_crawler};
+use crate::models::balance_model::BalanceWatch;
+use crate::models::network_model::Network;
+use crate::services::db::Database;
+use crate::services::mailer::notify_balance_crossing;
+use crate::services::rpc::{RpcClient, RpcRegistry};
+use alloy::primitives::U256;
+use dotenv::dotenv;
+use std::env;
+use tokio::time::{sleep, Duration};
+
+const DEFAULT_CHECK_INTERVAL_SECS: u64 = 300;
+
+/// Checks every balance watch each `BALANCE_CHECK_INTERVAL_SECS` seconds and notifies the
+/// user when a balance crosses one of their thresholds.
+pub async fn run_balance_checker(db: Database, rpcs: RpcRegistry) {
+    dotenv().ok();
+    let interval = env::var("BALANCE_CHECK_INTERVAL_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(DEFAULT_CHECK_INTERVAL_SECS);
+
+    loop {
+        // A panic in one round, such as a failed mail, must not stop the checker.
+        if let Err(err) = tokio::spawn(check_balances(db.clone(), rpcs.clone())).await {
+            println!("Balance check failed: {err}");
+        }
+        sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+async fn check_balances(db: Database, rpcs: RpcRegistry) {
+    let networks = match db.get_all_networks().await {
+        Ok(networks) => networks,
+        Err(err) => {
+            println!("Failed to load networks, skipping balance check: {:?}", err);
+            return;
+        }
+    };
+
+    for network_manager in networks {
+        let network = network_manager.network_type.clone();
+        let rpc = match rpcs.client_for(&network_manager) {
+            Ok(rpc) => rpc,
+            Err(err) => {
+                println!("Skipping {network:?} balance check: {err}");
+                continue;
+            }
+        };
+        let users = match db.find_users_with_balance_watches(network.clone()).await {
+            Ok(users) => users,
+            Err(err) => {
+                println!("Error finding {network:?} balance watches: {:?}", err);
+                continue;
+            }
+        };
+
+        for user in users {
+            for watch in user.balance_watches.iter() {
+                if watch.network != network {
+                    continue;
+                }
+                let balance = match fetch_balance(watch, &rpc).await {
+                    Ok(balance) => balance,
+                    Err(err) => {
+                        println!(
+                            "Failed to fetch balance of {} for {}: {err}",
+                            watch.token_address, watch.wallet_address
+                        );
+                        continue;
+                    }
+                };
+
+                if let Some(crossing) = watch.crossing(balance) {
+                    notify_balance_crossing(&db, &user, watch, balance, crossing).await;
+                }
+                if let Err(err) = db
+                    .update_last_balance(
+                        user.user_uuid.clone(),
+                        watch.watch_id.clone(),
+                        balance.to_string(),
+                    )
+                    .await
+                {
+                    println!("Error recording balance: {:?}", err);
+                }
+            }
+        }
+    }
+}
+
+async fn fetch_balance(watch: &BalanceWatch, rpc: &RpcClient) -> Result<U256, String> {
+    match watch.network {
+        Network::Starknet => {
+            starknet_crawler::fetch_token_balance(&watch.token_address, &watch.wallet_address, rpc)
+                .await
+        }
+        Network::Ethereum | Network::Base | Network::Optimism => {
+            evm_crawler::fetch_token_balance(&watch.token_address, &watch.wallet_address, rpc).await
+        }
+    }
+}
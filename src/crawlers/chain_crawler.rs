@@ -0,0 +1,692 @@
+use crate::models::activity_model::{Activity, ExecutionDetails};
+use crate::models::network_model::{Network, FINALITY_STATUSES};
+use crate::services::db::{Database, DatabaseResponse};
+use crate::services::mailer::{
+    notify_dropped_transactions, process_pending_transactions_to_mail,
+    process_transactions_to_mail, resolve_pre_confirmed,
+};
+use crate::services::subscription::subscribe_new_heads;
+use async_trait::async_trait;
+use futures_util::stream::{self, Stream, StreamExt};
+use mongodb::bson::DateTime;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout, Duration};
+
+const RETRY_BASE_DELAY_MS: u64 = 500;
+const RETRY_MAX_DELAY_MS: u64 = 30_000;
+// 500ms << 6 already passes the cap; larger shifts would only risk overflowing.
+const RETRY_MAX_EXPONENT: u32 = 6;
+// How long a pre-confirmed transaction may stay out of scanned blocks before it counts as dropped.
+const DROPPED_AFTER_SECS: i64 = 1800;
+
+/// The chain specific half of a crawler. Everything else (cursor handling, catch-up,
+/// notifications) is shared and lives in this module.
+#[async_trait]
+pub trait ChainCrawler: Send + Sync + 'static {
+    type Block: Send + Sync + 'static;
+
+    fn network(&self) -> Network;
+
+    /// Seconds to sleep between two scans.
+    fn poll_interval(&self) -> u64;
+
+    /// JSON-RPC method and params subscribing to new block headers over WebSocket.
+    fn new_heads_subscription(&self) -> (&'static str, serde_json::Value);
+
+    async fn latest_block(&self) -> Result<u128, String>;
+
+    async fn fetch_block(&self, block_number: u128) -> Result<Self::Block, String>;
+
+    /// Fetches several blocks, in order. Crawlers whose RPC supports batching should
+    /// override this to use a single round trip.
+    async fn fetch_blocks(&self, block_numbers: &[u128]) -> Result<Vec<Self::Block>, String> {
+        let mut blocks = Vec::with_capacity(block_numbers.len());
+        for block_number in block_numbers {
+            blocks.push(self.fetch_block(*block_number).await?);
+        }
+        Ok(blocks)
+    }
+
+    /// The block currently being built, for early alerts. `None` when the chain or RPC
+    /// doesn't expose one.
+    async fn fetch_pending_block(&self) -> Result<Option<Self::Block>, String> {
+        Ok(None)
+    }
+
+    fn block_header(&self, block: &Self::Block) -> BlockHeader;
+
+    /// Returns the activity in `block` that involves any of `wallets` (lowercased addresses).
+    fn extract_activity(&self, block: &Self::Block, wallets: &[String]) -> Vec<Activity>;
+
+    /// Returns calls to, and events emitted by, any of `contracts` (lowercased addresses).
+    fn extract_contract_activity(&self, block: &Self::Block, contracts: &[String])
+        -> Vec<Activity>;
+
+    /// The outcome of the transaction `transaction_hash`, `None` when it isn't in `block`.
+    fn transaction_execution(
+        &self,
+        block: &Self::Block,
+        transaction_hash: &str,
+    ) -> Option<ExecutionDetails>;
+
+    /// Adds chain specific detail, such as decoded calls, to activity found in `block`.
+    async fn describe_activity(&self, _block: &Self::Block, _activities: &mut [Activity]) {}
+}
+
+pub struct BlockHeader {
+    pub block_hash: String,
+    pub parent_hash: String,
+    /// Finality status for chains that report one (Starknet), `None` otherwise.
+    pub status: Option<String>,
+    /// Unix time in seconds.
+    pub timestamp: u64,
+}
+
+#[derive(Debug)]
+pub enum ScanError {
+    Fetch(String),
+    Database(String),
+    /// The block hasn't reached the network's required finality yet.
+    NotFinal(String),
+    /// The parent of this block no longer matches what was scanned; the cursor was rolled back.
+    Reorg(u128),
+    /// The cursor was moved while the block was scanned, e.g. rewound by an admin.
+    CursorMoved,
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScanError::Fetch(err) => write!(f, "failed to fetch block: {err}"),
+            ScanError::Database(err) => write!(f, "database error: {err}"),
+            ScanError::NotFinal(status) => write!(f, "block is only {status}"),
+            ScanError::Reorg(block_number) => write!(f, "reorg detected at block {block_number}"),
+            ScanError::CursorMoved => write!(f, "the scan cursor was moved"),
+        }
+    }
+}
+
+impl Error for ScanError {}
+
+fn is_final_enough(status: &str, required: &str) -> bool {
+    let rank = |status: &str| FINALITY_STATUSES.iter().position(|s| *s == status);
+    match (rank(status), rank(required)) {
+        (Some(status), Some(required)) => status >= required,
+        _ => false,
+    }
+}
+
+/// Scans every `poll_interval` seconds. With a `ws_endpoint` the crawler also subscribes
+/// to new heads and scans as soon as one arrives, polling only while the socket is down.
+pub async fn run_crawler<C: ChainCrawler>(
+    crawler: Arc<C>,
+    db: Database,
+    ws_endpoint: Option<String>,
+) {
+    let network = crawler.network();
+    let interval = crawler.poll_interval();
+    let new_head = Arc::new(Notify::new());
+
+    let subscription = async {
+        if let Some(ws_endpoint) = ws_endpoint {
+            let (method, params) = crawler.new_heads_subscription();
+            subscribe_new_heads(ws_endpoint, method, params, new_head.clone()).await;
+        }
+    };
+
+    let polling = async {
+        loop {
+            let caught_up = check_new_block(crawler.clone(), db.clone()).await;
+            check_pending_block(crawler.clone(), db.clone(), caught_up).await;
+            println!(
+                "{network:?} block checking completed... Going to sleep for {interval} seconds"
+            );
+            let _ = timeout(Duration::from_secs(interval), new_head.notified()).await;
+            println!("Awake and scanning for new {network:?} transactions...");
+        }
+    };
+
+    tokio::join!(subscription, polling);
+}
+
+/// Scans up to the confirmed tip. Returns whether the cursor reached it.
+pub async fn check_new_block<C: ChainCrawler>(crawler: Arc<C>, db: Database) -> bool {
+    let network = crawler.network();
+    println!("{network:?} block checking started...");
+
+    loop {
+        let network_manager = match db.get_network_via_name(network.clone()).await {
+            Ok(network_manager) => network_manager,
+            Err(err) => {
+                println!("{network:?} is not registered, skipping: {:?}", err);
+                return false;
+            }
+        };
+        let last_scanned_block = network_manager.last_scanned_block;
+
+        let block_number = match crawler.latest_block().await {
+            Ok(block_number) => network_manager.confirmed_tip(block_number),
+            Err(err) => {
+                println!("Failed to fetch latest {network:?} block: {err}");
+                return false;
+            }
+        };
+
+        // A freshly registered network has no cursor yet; start from the tip instead of genesis.
+        let retries = network_manager.block_retries;
+        let result = if last_scanned_block == 0 {
+            handle_new_block(crawler.clone(), block_number, retries, db.clone()).await
+        } else if block_number > last_scanned_block {
+            if block_number - last_scanned_block > 1 {
+                let settings = BackfillSettings {
+                    workers: network_manager.backfill_workers,
+                    batch_size: network_manager.rpc_batch_size,
+                    retries,
+                };
+                check_and_handle_skipped_bocks(
+                    crawler.clone(),
+                    last_scanned_block,
+                    block_number,
+                    settings,
+                    db.clone(),
+                )
+                .await
+            } else {
+                handle_new_block(crawler.clone(), block_number, retries, db.clone()).await
+            }
+        } else {
+            println!("Already scanned {network:?} block number: {}", block_number);
+            Ok(())
+        };
+
+        match result {
+            Err(ScanError::Reorg(block_number)) => {
+                println!("{network:?} reorg detected at block {block_number}, rescanning...");
+            }
+            Err(ScanError::CursorMoved) => {
+                println!("{network:?} scan cursor was moved, resuming from it...");
+            }
+            Err(err) => {
+                println!("Stopping {network:?} scan: {err}");
+                return false;
+            }
+            Ok(()) => return true,
+        }
+    }
+}
+
+pub async fn handle_new_block<C: ChainCrawler>(
+    crawler: Arc<C>,
+    block_number: u128,
+    retries: u32,
+    db: Database,
+) -> Result<(), ScanError> {
+    let block = fetch_block_with_retry(crawler.clone(), block_number, retries).await?;
+    process_block(crawler, block_number, block, db).await
+}
+
+async fn fetch_block_with_retry<C: ChainCrawler>(
+    crawler: Arc<C>,
+    block_number: u128,
+    retries: u32,
+) -> Result<C::Block, ScanError> {
+    let description = format!("{:?} block {block_number}", crawler.network());
+    with_retry(description, retries, || crawler.fetch_block(block_number)).await
+}
+
+async fn fetch_blocks_with_retry<C: ChainCrawler>(
+    crawler: Arc<C>,
+    block_numbers: Vec<u128>,
+    retries: u32,
+) -> Result<Vec<C::Block>, ScanError> {
+    let description = format!("{:?} blocks {:?}", crawler.network(), block_numbers);
+    with_retry(description, retries, || {
+        crawler.fetch_blocks(&block_numbers)
+    })
+    .await
+}
+
+async fn with_retry<T, F, Fut>(description: String, retries: u32, fetch: F) -> Result<T, ScanError>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, String>>,
+{
+    let mut attempt: u32 = 0;
+    loop {
+        match fetch().await {
+            Ok(value) => return Ok(value),
+            Err(err) if attempt < retries => {
+                let delay = RETRY_BASE_DELAY_MS
+                    .saturating_mul(1 << attempt.min(RETRY_MAX_EXPONENT))
+                    .min(RETRY_MAX_DELAY_MS);
+                attempt += 1;
+                println!(
+                    "Fetching {description} failed ({err}), retry {attempt}/{retries} in {delay}ms"
+                );
+                sleep(Duration::from_millis(delay)).await;
+            }
+            Err(err) => return Err(ScanError::Fetch(err)),
+        }
+    }
+}
+
+/// Checks `block` against the scanned chain, notifies users and advances the cursor to it.
+async fn process_block<C: ChainCrawler>(
+    crawler: Arc<C>,
+    block_number: u128,
+    block: C::Block,
+    db: Database,
+) -> Result<(), ScanError> {
+    let network = crawler.network();
+    let header = crawler.block_header(&block);
+
+    let mut network_manager = db
+        .get_network_via_name(network.clone())
+        .await
+        .map_err(|err| ScanError::Database(err.message))?;
+
+    if let (Some(required), Some(status)) = (&network_manager.required_finality, &header.status) {
+        if !is_final_enough(status, required) {
+            return Err(ScanError::NotFinal(status.clone()));
+        }
+    }
+
+    if network_manager.is_reorg(block_number, &header.parent_hash) {
+        // Step back one block; the next pass compares that block against its own parent.
+        let expected_last_block = network_manager.last_scanned_block;
+        network_manager.rollback_to(block_number.saturating_sub(2));
+        db.update_scan_cursor(
+            network,
+            expected_last_block,
+            network_manager.last_scanned_block,
+            &network_manager.recent_blocks,
+        )
+        .await
+        .map_err(|err| ScanError::Database(err.message))?;
+        return Err(ScanError::Reorg(block_number));
+    }
+
+    println!("LOG:: Searching {network:?} block {block_number} for registered wallets...");
+    let mut activities = find_activity(crawler.as_ref(), &block, &db)
+        .await
+        .map_err(|err| ScanError::Database(err.message))?;
+    if activities.is_empty() {
+        println!("No relevant transactions found.");
+    } else {
+        crawler.describe_activity(&block, &mut activities).await;
+        println!("LOG:: {}, Relevant transactions found:", activities.len());
+        db.record_activities(&activities, header.timestamp, false)
+            .await
+            .map_err(|err| ScanError::Database(err.message))?;
+        process_transactions_to_mail(activities, db.clone()).await;
+    }
+    if network_manager.pending_alerts {
+        resolve_pre_confirmed(
+            network.clone(),
+            block_number,
+            |transaction_hash| crawler.transaction_execution(&block, transaction_hash),
+            &db,
+        )
+        .await;
+    }
+
+    let expected_last_block = network_manager.last_scanned_block;
+    network_manager.record_scanned_block(block_number, header.block_hash);
+    let advanced = db
+        .update_scan_cursor(
+            network,
+            expected_last_block,
+            network_manager.last_scanned_block,
+            &network_manager.recent_blocks,
+        )
+        .await
+        .map_err(|err| ScanError::Database(err.message))?;
+    if !advanced {
+        return Err(ScanError::CursorMoved);
+    }
+    Ok(())
+}
+
+/// Activity in `block` involving the network's registered wallets or watched contracts.
+async fn find_activity<C: ChainCrawler>(
+    crawler: &C,
+    block: &C::Block,
+    db: &Database,
+) -> Result<Vec<Activity>, DatabaseResponse> {
+    let network = crawler.network();
+    let wallets: Vec<String> = db
+        .get_all_wallets_via_network(network.clone())
+        .await?
+        .into_iter()
+        .map(|wallet| wallet.wallet_address.to_lowercase())
+        .collect();
+    let contracts: Vec<String> = db
+        .get_all_watched_contracts_via_network(network)
+        .await?
+        .into_iter()
+        .map(|contract| contract.contract_address.to_lowercase())
+        .collect();
+
+    let mut activities = crawler.extract_activity(block, &wallets);
+    if !contracts.is_empty() {
+        activities.extend(crawler.extract_contract_activity(block, &contracts));
+    }
+    Ok(activities)
+}
+
+/// When the network has pending alerts on, alerts on activity in the pending block and
+/// follows up on earlier alerts whose transaction never landed. Transactions are only
+/// declared dropped while the scan is `caught_up`, so a lagging cursor can't mistake a
+/// confirmed transaction for a dropped one.
+pub async fn check_pending_block<C: ChainCrawler>(crawler: Arc<C>, db: Database, caught_up: bool) {
+    let network = crawler.network();
+    match db.get_network_via_name(network.clone()).await {
+        Ok(network_manager) if network_manager.pending_alerts => {}
+        _ => return,
+    }
+
+    match crawler.fetch_pending_block().await {
+        Ok(Some(block)) => match find_activity(crawler.as_ref(), &block, &db).await {
+            Ok(mut activities) => {
+                if !activities.is_empty() {
+                    crawler.describe_activity(&block, &mut activities).await;
+                    process_pending_transactions_to_mail(activities, db.clone()).await;
+                }
+            }
+            Err(err) => println!("Failed to load {network:?} watch list: {:?}", err),
+        },
+        Ok(None) => {}
+        Err(err) => println!("Failed to fetch pending {network:?} block: {err}"),
+    }
+
+    if caught_up {
+        let before =
+            DateTime::from_millis(DateTime::now().timestamp_millis() - DROPPED_AFTER_SECS * 1000);
+        notify_dropped_transactions(network, before, &db).await;
+    }
+}
+
+pub struct BackfillSettings {
+    pub workers: u32,
+    pub batch_size: u32,
+    pub retries: u32,
+}
+
+pub async fn check_and_handle_skipped_bocks<C: ChainCrawler>(
+    crawler: Arc<C>,
+    last_scanned_block: u128,
+    current_block: u128,
+    settings: BackfillSettings,
+    db: Database,
+) -> Result<(), ScanError> {
+    let skipped_blocks = current_block.saturating_sub(last_scanned_block);
+    println!("BLOCKS SKIPPED: {:?}", skipped_blocks);
+
+    scan_range(
+        crawler.clone(),
+        last_scanned_block + 1,
+        current_block,
+        &settings,
+        |block_number, block| process_block(crawler.clone(), block_number, block, db.clone()),
+    )
+    .await?;
+    println!(
+        "Processed {:?} blocks {} to {}",
+        crawler.network(),
+        last_scanned_block + 1,
+        current_block
+    );
+    Ok(())
+}
+
+/// Hands blocks `first..=last` to `process` in block order. Processing stops at the first
+/// failure, so the cursor only ever advances past contiguously processed blocks.
+async fn scan_range<C, F, Fut>(
+    crawler: Arc<C>,
+    first: u128,
+    last: u128,
+    settings: &BackfillSettings,
+    mut process: F,
+) -> Result<(), ScanError>
+where
+    C: ChainCrawler,
+    F: FnMut(u128, C::Block) -> Fut,
+    Fut: Future<Output = Result<(), ScanError>>,
+{
+    let mut blocks = fetch_range(crawler, first, last, settings);
+    while let Some((block_numbers, blocks)) = blocks.next().await {
+        for (block_number, block) in block_numbers.into_iter().zip(blocks?) {
+            process(block_number, block).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Aborts the spawned task when dropped, so batches still in flight stop once the range
+/// is abandoned.
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Fetches blocks `first..=last` in batches. Up to `workers` batches are fetched concurrently
+/// in their own tasks, but `buffered` yields them in block order. Dropping the stream, as
+/// callers do on the first failed batch, aborts the batches still being fetched.
+fn fetch_range<C: ChainCrawler>(
+    crawler: Arc<C>,
+    first: u128,
+    last: u128,
+    settings: &BackfillSettings,
+) -> impl Stream<Item = (Vec<u128>, Result<Vec<C::Block>, ScanError>)> + Unpin {
+    let batch_size: u128 = settings.batch_size.max(1).into();
+    let retries = settings.retries;
+    let batches = (first..=last)
+        .step_by(batch_size as usize)
+        .map(move |start| (start..=(start + batch_size - 1).min(last)).collect());
+
+    stream::iter(batches)
+        .map(move |block_numbers: Vec<u128>| {
+            let mut fetch = AbortOnDrop(tokio::spawn(fetch_blocks_with_retry(
+                crawler.clone(),
+                block_numbers.clone(),
+                retries,
+            )));
+            async move {
+                let blocks = (&mut fetch.0)
+                    .await
+                    .map_err(|err| ScanError::Fetch(err.to_string()))
+                    .and_then(|blocks| blocks);
+                (block_numbers, blocks)
+            }
+        })
+        .buffered(settings.workers.max(1) as usize)
+}
+
+/// Scans the `block_count` blocks up to the network's cursor for a newly added wallet and
+/// stores what it finds as the wallet's history. Nobody is notified; blocks after the
+/// cursor are left to the running crawler.
+pub async fn backfill_wallet<C: ChainCrawler>(
+    crawler: Arc<C>,
+    wallet_address: String,
+    block_count: u64,
+    db: Database,
+) -> Result<(), ScanError> {
+    let network = crawler.network();
+    let network_manager = db
+        .get_network_via_name(network.clone())
+        .await
+        .map_err(|err| ScanError::Database(err.message))?;
+    let last = network_manager.last_scanned_block;
+    if last == 0 || block_count == 0 {
+        return Ok(());
+    }
+    let first = last.saturating_sub(u128::from(block_count) - 1).max(1);
+    println!("Backfilling {network:?} wallet {wallet_address} from block {first} to {last}");
+
+    let settings = BackfillSettings {
+        workers: network_manager.backfill_workers,
+        batch_size: network_manager.rpc_batch_size,
+        retries: network_manager.block_retries,
+    };
+    let wallets = vec![wallet_address.to_lowercase()];
+    let mut found = 0;
+    let mut blocks = fetch_range(crawler.clone(), first, last, &settings);
+    while let Some((_, blocks)) = blocks.next().await {
+        for block in blocks? {
+            let mut activities = crawler.extract_activity(&block, &wallets);
+            if activities.is_empty() {
+                continue;
+            }
+            crawler.describe_activity(&block, &mut activities).await;
+            found += activities.len();
+            let timestamp = crawler.block_header(&block).timestamp;
+            db.record_activities(&activities, timestamp, true)
+                .await
+                .map_err(|err| ScanError::Database(err.message))?;
+        }
+    }
+    println!("Backfilled {found} transactions for {network:?} wallet {wallet_address}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    /// Serves block numbers as blocks, after a per-block delay.
+    struct FakeCrawler {
+        delay_ms: fn(u128) -> u64,
+        failing_block: Option<u128>,
+        fetched: Mutex<Vec<u128>>,
+    }
+
+    impl FakeCrawler {
+        fn new(delay_ms: fn(u128) -> u64, failing_block: Option<u128>) -> Arc<Self> {
+            Arc::new(FakeCrawler {
+                delay_ms,
+                failing_block,
+                fetched: Mutex::new(Vec::new()),
+            })
+        }
+
+        fn fetched(&self) -> Vec<u128> {
+            self.fetched.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl ChainCrawler for FakeCrawler {
+        type Block = u128;
+
+        fn network(&self) -> Network {
+            Network::Ethereum
+        }
+
+        fn poll_interval(&self) -> u64 {
+            1
+        }
+
+        fn new_heads_subscription(&self) -> (&'static str, serde_json::Value) {
+            ("eth_subscribe", json!(["newHeads"]))
+        }
+
+        async fn latest_block(&self) -> Result<u128, String> {
+            Ok(0)
+        }
+
+        async fn fetch_block(&self, block_number: u128) -> Result<u128, String> {
+            sleep(Duration::from_millis((self.delay_ms)(block_number))).await;
+            if self.failing_block == Some(block_number) {
+                return Err(format!("block {block_number} is unavailable"));
+            }
+            self.fetched.lock().unwrap().push(block_number);
+            Ok(block_number)
+        }
+
+        fn block_header(&self, block: &u128) -> BlockHeader {
+            BlockHeader {
+                block_hash: format!("{block:#x}"),
+                parent_hash: format!("{:#x}", block.saturating_sub(1)),
+                status: None,
+                timestamp: 0,
+            }
+        }
+
+        fn extract_activity(&self, _block: &u128, _wallets: &[String]) -> Vec<Activity> {
+            Vec::new()
+        }
+
+        fn extract_contract_activity(&self, _block: &u128, _contracts: &[String]) -> Vec<Activity> {
+            Vec::new()
+        }
+
+        fn transaction_execution(&self, _block: &u128, _hash: &str) -> Option<ExecutionDetails> {
+            None
+        }
+    }
+
+    fn settings() -> BackfillSettings {
+        BackfillSettings {
+            workers: 3,
+            batch_size: 2,
+            retries: 0,
+        }
+    }
+
+    /// Scans `first..=last`, returning the result and the cursor it left behind.
+    async fn scan(
+        crawler: Arc<FakeCrawler>,
+        first: u128,
+        last: u128,
+    ) -> (Result<(), ScanError>, u128) {
+        let cursor = Mutex::new(first - 1);
+        let result = scan_range(crawler, first, last, &settings(), |block_number, block| {
+            assert_eq!(block_number, block);
+            let mut cursor = cursor.lock().unwrap();
+            assert_eq!(
+                block_number,
+                *cursor + 1,
+                "blocks must be processed in order"
+            );
+            *cursor = block_number;
+            async { Ok(()) }
+        })
+        .await;
+        let cursor = *cursor.lock().unwrap();
+        (result, cursor)
+    }
+
+    #[actix_web::test]
+    async fn batches_finishing_out_of_order_are_processed_in_order() {
+        // The first batch is the slowest, the last one the fastest.
+        let crawler = FakeCrawler::new(|block| 90 - 15 * block as u64, None);
+        let (result, cursor) = scan(crawler.clone(), 1, 6).await;
+
+        assert!(result.is_ok());
+        assert_eq!(cursor, 6);
+        assert_eq!(crawler.fetched()[..2], [5, 6]);
+    }
+
+    #[actix_web::test]
+    async fn a_failing_block_stops_the_cursor_and_in_flight_batches() {
+        // Blocks 5 and later are still being fetched when block 3 fails.
+        let crawler = FakeCrawler::new(|block| if block < 5 { 10 } else { 200 }, Some(3));
+        let (result, cursor) = scan(crawler.clone(), 1, 8).await;
+
+        assert!(matches!(result, Err(ScanError::Fetch(_))));
+        assert_eq!(cursor, 2);
+
+        sleep(Duration::from_millis(500)).await;
+        assert_eq!(crawler.fetched(), [1, 2]);
+    }
+}
@@ -0,0 +1,566 @@
+use crate::crawlers::chain_crawler::{BlockHeader, ChainCrawler};
+use crate::models::activity_model::{
+    Activity, ContractInteraction, DecodedCall, Direction, ExecutionDetails, InteractionKind,
+};
+use crate::models::evm_model::{parse_hex_quantity, EvmBlock, EvmReceipt, EvmTransaction};
+use crate::models::network_model::Network;
+use crate::services::rpc::{is_method_not_found, RpcClient};
+use crate::utils::approval_detector::{approval_from_evm_input, risky_approval};
+use crate::utils::calldata_decoder::short_felt;
+use alloy::primitives::U256;
+use async_trait::async_trait;
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// `balanceOf(address)`
+const BALANCE_OF_SELECTOR: &str = "70a08231";
+// Transaction receipts requested per batch when a block's receipts are fetched one by one.
+const RECEIPT_BATCH_SIZE: usize = 100;
+
+pub struct EvmCrawler {
+    network: Network,
+    rpc: RpcClient,
+    /// Cleared once the provider turns out not to support `eth_getBlockReceipts`.
+    block_receipts: AtomicBool,
+}
+
+impl EvmCrawler {
+    pub fn new(network: Network, rpc: RpcClient) -> Self {
+        EvmCrawler {
+            network,
+            rpc,
+            block_receipts: AtomicBool::new(true),
+        }
+    }
+}
+
+#[async_trait]
+impl ChainCrawler for EvmCrawler {
+    type Block = EvmBlock;
+
+    fn network(&self) -> Network {
+        self.network.clone()
+    }
+
+    fn poll_interval(&self) -> u64 {
+        15
+    }
+
+    fn new_heads_subscription(&self) -> (&'static str, serde_json::Value) {
+        ("eth_subscribe", json!(["newHeads"]))
+    }
+
+    async fn latest_block(&self) -> Result<u128, String> {
+        get_latest_block(&self.rpc).await
+    }
+
+    async fn fetch_block(&self, block_number: u128) -> Result<Self::Block, String> {
+        fetch_block(block_number, &self.rpc, &self.block_receipts).await
+    }
+
+    async fn fetch_blocks(&self, block_numbers: &[u128]) -> Result<Vec<Self::Block>, String> {
+        fetch_blocks(block_numbers, &self.rpc, &self.block_receipts).await
+    }
+
+    async fn fetch_pending_block(&self) -> Result<Option<Self::Block>, String> {
+        fetch_pending_block(&self.rpc).await
+    }
+
+    fn block_header(&self, block: &Self::Block) -> BlockHeader {
+        BlockHeader {
+            block_hash: block.hash.clone().unwrap_or_default(),
+            parent_hash: block.parent_hash.clone(),
+            status: None,
+            timestamp: block.timestamp(),
+        }
+    }
+
+    fn extract_activity(&self, block: &Self::Block, wallets: &[String]) -> Vec<Activity> {
+        process_transactions(&self.network, block, wallets)
+    }
+
+    fn extract_contract_activity(
+        &self,
+        block: &Self::Block,
+        contracts: &[String],
+    ) -> Vec<Activity> {
+        process_contract_interactions(&self.network, block, contracts)
+    }
+
+    fn transaction_execution(
+        &self,
+        block: &Self::Block,
+        transaction_hash: &str,
+    ) -> Option<ExecutionDetails> {
+        block
+            .receipt(transaction_hash)
+            .map(EvmReceipt::execution_details)
+    }
+}
+
+pub async fn get_latest_block(rpc: &RpcClient) -> Result<u128, String> {
+    let block_number: String = rpc.request("eth_blockNumber", json!([])).await?;
+    parse_hex_quantity(&block_number)
+}
+
+fn block_params(block_number: u128) -> serde_json::Value {
+    json!([format!("0x{:x}", block_number), true])
+}
+
+fn receipts_params(block_number: u128) -> serde_json::Value {
+    json!([format!("0x{:x}", block_number)])
+}
+
+pub async fn fetch_block(
+    block_number: u128,
+    rpc: &RpcClient,
+    block_receipts: &AtomicBool,
+) -> Result<EvmBlock, String> {
+    let block: Option<EvmBlock> = rpc
+        .request("eth_getBlockByNumber", block_params(block_number))
+        .await?;
+    let mut blocks = vec![block.ok_or(format!("Block {block_number} not found"))?];
+    attach_receipts(&mut blocks, rpc, block_receipts).await?;
+    Ok(blocks.remove(0))
+}
+
+/// The node's pending block, i.e. the transactions it would include next. Providers that
+/// don't expose their mempool answer `null`.
+pub async fn fetch_pending_block(rpc: &RpcClient) -> Result<Option<EvmBlock>, String> {
+    rpc.request("eth_getBlockByNumber", json!(["pending", true]))
+        .await
+}
+
+/// The wallet's balance of an ERC-20 token, through `balanceOf(address)`.
+pub async fn fetch_token_balance(
+    token_address: &str,
+    wallet_address: &str,
+    rpc: &RpcClient,
+) -> Result<U256, String> {
+    let wallet = wallet_address.trim_start_matches("0x").to_lowercase();
+    let result: String = rpc
+        .request(
+            "eth_call",
+            json!([
+                {
+                    "to": token_address,
+                    "data": format!("0x{BALANCE_OF_SELECTOR}{wallet:0>64}"),
+                },
+                "latest"
+            ]),
+        )
+        .await?;
+    U256::from_str_radix(result.trim_start_matches("0x"), 16)
+        .map_err(|e| format!("Invalid balanceOf result {result}: {e}"))
+}
+
+pub async fn fetch_blocks(
+    block_numbers: &[u128],
+    rpc: &RpcClient,
+    block_receipts: &AtomicBool,
+) -> Result<Vec<EvmBlock>, String> {
+    let params_list = block_numbers.iter().map(|n| block_params(*n)).collect();
+    let blocks: Vec<Option<EvmBlock>> = rpc
+        .batch_request("eth_getBlockByNumber", params_list)
+        .await?;
+    let mut blocks = blocks
+        .into_iter()
+        .zip(block_numbers)
+        .map(|(block, block_number)| block.ok_or(format!("Block {block_number} not found")))
+        .collect::<Result<Vec<EvmBlock>, String>>()?;
+    attach_receipts(&mut blocks, rpc, block_receipts).await?;
+    Ok(blocks)
+}
+
+/// Fills in the blocks' receipts with `eth_getBlockReceipts`, or with one
+/// `eth_getTransactionReceipt` per transaction on providers without it.
+async fn attach_receipts(
+    blocks: &mut [EvmBlock],
+    rpc: &RpcClient,
+    block_receipts: &AtomicBool,
+) -> Result<(), String> {
+    if blocks.is_empty() {
+        return Ok(());
+    }
+
+    if block_receipts.load(Ordering::Relaxed) {
+        let params_list = blocks
+            .iter()
+            .map(|block| receipts_params(block.block_number()))
+            .collect();
+        let receipts: Result<Vec<Option<Vec<EvmReceipt>>>, String> =
+            rpc.batch_request("eth_getBlockReceipts", params_list).await;
+        match receipts {
+            Ok(receipts) => {
+                for (block, receipts) in blocks.iter_mut().zip(receipts) {
+                    block.receipts = receipts.ok_or(format!(
+                        "Receipts for block {} not found",
+                        block.block_number()
+                    ))?;
+                }
+                return Ok(());
+            }
+            Err(err) if is_method_not_found(&err) => {
+                println!(
+                    "eth_getBlockReceipts is not supported, fetching receipts per transaction"
+                );
+                block_receipts.store(false, Ordering::Relaxed);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    for block in blocks.iter_mut() {
+        let hashes: Vec<String> = block
+            .transactions
+            .iter()
+            .map(|transaction| transaction.hash.clone())
+            .collect();
+        let mut receipts = Vec::new();
+        for chunk in hashes.chunks(RECEIPT_BATCH_SIZE) {
+            let params_list = chunk.iter().map(|hash| json!([hash])).collect();
+            let fetched: Vec<Option<EvmReceipt>> = rpc
+                .batch_request("eth_getTransactionReceipt", params_list)
+                .await?;
+            for (hash, receipt) in chunk.iter().zip(fetched) {
+                receipts.push(receipt.ok_or(format!("Receipt for transaction {hash} not found"))?);
+            }
+        }
+        block.receipts = receipts;
+    }
+    Ok(())
+}
+
+/// Matches transactions sent by or to a registered wallet. Risky allowances granted by a
+/// wallet are matched from `Approval` logs, or from its approve call when no log is known
+/// (reverted or pending transactions).
+pub fn process_transactions(
+    network: &Network,
+    block: &EvmBlock,
+    wallets: &[String],
+) -> Vec<Activity> {
+    let block_number = block.block_number();
+
+    let mut activities: Vec<Activity> = Vec::new();
+    for transaction in block.transactions.iter() {
+        let sender = transaction.from.to_lowercase();
+        let receipt = block.receipt(&transaction.hash);
+        let execution = receipt.map(|receipt| receipt.execution_details());
+        let mut sender_approved = false;
+
+        let logs = receipt
+            .map(|receipt| receipt.logs.as_slice())
+            .unwrap_or_default();
+        for (log_index, log) in logs.iter().enumerate() {
+            let approval = match log.as_approval() {
+                Some(approval) => approval,
+                None => continue,
+            };
+            let risky = risky_approval(&approval.token_address, &approval.spender, approval.amount);
+            if let (true, Some(risky)) = (wallets.contains(&approval.owner), risky) {
+                sender_approved |= approval.owner == sender;
+                let mut activity = Activity::new(
+                    network.clone(),
+                    block_number,
+                    transaction.hash.clone(),
+                    approval.owner,
+                    Direction::Outgoing,
+                )
+                .with_approval_event(log_index as u32, risky);
+                if let Some(execution) = &execution {
+                    activity = activity.with_execution(execution.clone());
+                }
+                activities.push(activity);
+            }
+        }
+
+        for participant in transaction.participants() {
+            if wallets.contains(&participant) {
+                let direction = if participant == sender {
+                    Direction::Outgoing
+                } else {
+                    Direction::Incoming
+                };
+                let call_approval = match (&direction, &transaction.to) {
+                    (Direction::Outgoing, Some(to)) if !sender_approved => {
+                        approval_from_evm_input(&to.to_lowercase(), &transaction.input)
+                    }
+                    _ => None,
+                };
+
+                let mut activity = Activity::new(
+                    network.clone(),
+                    block_number,
+                    transaction.hash.clone(),
+                    participant,
+                    direction,
+                );
+                if let Some(execution) = &execution {
+                    activity = activity.with_execution(execution.clone());
+                }
+                if let Some(approval) = call_approval {
+                    activity = activity.with_approval(approval);
+                }
+                if activity.direction == Direction::Outgoing {
+                    activity.calls = contract_call(transaction).into_iter().collect();
+                }
+                activities.push(activity);
+            }
+        }
+    }
+    activities
+}
+
+/// The contract call a transaction makes, if its input carries a function selector.
+fn contract_call(transaction: &EvmTransaction) -> Option<DecodedCall> {
+    let contract_address = transaction.to.as_ref()?.to_lowercase();
+    let selector = transaction.input.get(..10)?.to_string();
+    Some(DecodedCall {
+        summary: format!("call {} on {}", selector, short_felt(&contract_address)),
+        contract_address,
+        selector,
+        function_name: None,
+        calldata: Vec::new(),
+    })
+}
+
+/// Matches calls to, and logs emitted by, watched contracts.
+pub fn process_contract_interactions(
+    network: &Network,
+    block: &EvmBlock,
+    contracts: &[String],
+) -> Vec<Activity> {
+    let block_number = block.block_number();
+
+    let mut activities: Vec<Activity> = Vec::new();
+    for transaction in block.transactions.iter() {
+        let caller = Some(transaction.from.to_lowercase());
+        let receipt = block.receipt(&transaction.hash);
+        let execution = receipt.map(|receipt| receipt.execution_details());
+        let with_execution = |activity: Activity| match &execution {
+            Some(execution) => activity.with_execution(execution.clone()),
+            None => activity,
+        };
+
+        if let Some(to) = transaction.to.as_ref().map(|to| to.to_lowercase()) {
+            if contracts.contains(&to) {
+                let selector = transaction.input.get(..10).unwrap_or_default().to_string();
+                activities.push(with_execution(
+                    Activity::new(
+                        network.clone(),
+                        block_number,
+                        transaction.hash.clone(),
+                        to,
+                        Direction::Incoming,
+                    )
+                    .with_interaction(
+                        None,
+                        ContractInteraction {
+                            kind: InteractionKind::Call,
+                            selector,
+                            caller: caller.clone(),
+                        },
+                    ),
+                ));
+            }
+        }
+
+        let logs = receipt
+            .map(|receipt| receipt.logs.as_slice())
+            .unwrap_or_default();
+        for (log_index, log) in logs.iter().enumerate() {
+            let contract_address = log.address.to_lowercase();
+            if !contracts.contains(&contract_address) {
+                continue;
+            }
+            activities.push(with_execution(
+                Activity::new(
+                    network.clone(),
+                    block_number,
+                    transaction.hash.clone(),
+                    contract_address,
+                    Direction::Incoming,
+                )
+                .with_interaction(
+                    Some(log_index as u32),
+                    ContractInteraction {
+                        kind: InteractionKind::Event,
+                        selector: log.topics.first().cloned().unwrap_or_default(),
+                        caller: caller.clone(),
+                    },
+                ),
+            ));
+        }
+    }
+    activities
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::network_model::RpcEndpoint;
+    use crate::utils::http_stub::HttpStub;
+    use serde_json::Value;
+
+    const WALLET: &str = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+    const OTHER: &str = "0xfb6916095ca1df60bb79ce92ce3ea74c37c5d359";
+
+    fn transaction_hash(block_number: u64, index: u64) -> String {
+        format!("0x{block_number:x}{index:063x}")
+    }
+
+    /// Blocks 0x10 and 0x11, each with a payment from the wallet and a reverted one to it.
+    fn block(block_number: u64) -> Value {
+        json!({
+            "number": format!("0x{block_number:x}"),
+            "hash": format!("0xb{block_number:x}"),
+            "parentHash": format!("0xb{:x}", block_number - 1),
+            "timestamp": "0x6553f100",
+            "transactions": [
+                {"hash": transaction_hash(block_number, 0), "from": WALLET, "to": OTHER, "value": "0x1", "input": "0x"},
+                {"hash": transaction_hash(block_number, 1), "from": OTHER, "to": WALLET, "value": "0x1", "input": "0x"},
+            ],
+        })
+    }
+
+    fn receipt(hash: &str) -> Value {
+        let status = if hash.ends_with('1') { "0x0" } else { "0x1" };
+        json!({
+            "transactionHash": hash,
+            "status": status,
+            "gasUsed": "0x5208",
+            "effectiveGasPrice": "0x3b9aca00",
+            "logs": [],
+        })
+    }
+
+    /// A node at block 0x11. Without `block_receipts` it rejects `eth_getBlockReceipts`
+    /// like providers that don't offer it.
+    fn node(block_receipts: bool) -> HttpStub {
+        HttpStub::json_rpc(move |method, params| {
+            let block_number =
+                || u64::from_str_radix(&params[0].as_str().unwrap()[2..], 16).unwrap();
+            match method {
+                "eth_blockNumber" => Ok(json!("0x11")),
+                "eth_getBlockByNumber" if block_number() <= 0x11 => Ok(block(block_number())),
+                "eth_getBlockByNumber" => Ok(Value::Null),
+                "eth_getBlockReceipts" if block_receipts => Ok(json!([
+                    receipt(&transaction_hash(block_number(), 0)),
+                    receipt(&transaction_hash(block_number(), 1)),
+                ])),
+                "eth_getTransactionReceipt" => Ok(receipt(params[0].as_str().unwrap())),
+                _ => Err(json!({"code": -32601, "message": "the method does not exist"})),
+            }
+        })
+    }
+
+    fn crawler(stub: &HttpStub) -> EvmCrawler {
+        let rpc = RpcClient::new(vec![RpcEndpoint::new(stub.url.clone())]);
+        EvmCrawler::new(Network::Ethereum, rpc)
+    }
+
+    fn methods(stub: &HttpStub) -> Vec<String> {
+        stub.received()
+            .iter()
+            .map(|request| {
+                let body: Value = serde_json::from_str(&request.body).unwrap();
+                match body {
+                    Value::Array(calls) => format!("{}[{}]", calls[0]["method"], calls.len()),
+                    call => call["method"].to_string(),
+                }
+                .replace('"', "")
+            })
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn blocks_are_scanned_from_the_node() {
+        let stub = node(true);
+        let crawler = crawler(&stub);
+
+        assert_eq!(crawler.latest_block().await, Ok(0x11));
+        let block = crawler.fetch_block(0x10).await.unwrap();
+        assert_eq!(block.block_number(), 0x10);
+        assert_eq!(block.receipts.len(), 2);
+        assert_eq!(crawler.block_header(&block).parent_hash, "0xbf");
+
+        let activities = crawler.extract_activity(&block, &[WALLET.to_string()]);
+        assert_eq!(activities.len(), 2);
+        assert_eq!(activities[0].direction, Direction::Outgoing);
+        assert!(!activities[0].is_reverted());
+        assert_eq!(activities[1].direction, Direction::Incoming);
+        assert!(activities[1].is_reverted());
+
+        let reverted =
+            crawler.transaction_execution(&block, &transaction_hash(0x10, 1).to_uppercase());
+        assert_eq!(reverted.unwrap().execution_status, "REVERTED");
+        assert!(crawler
+            .transaction_execution(&block, &transaction_hash(0x11, 0))
+            .is_none());
+        assert_eq!(
+            methods(&stub),
+            [
+                "eth_blockNumber",
+                "eth_getBlockByNumber",
+                "eth_getBlockReceipts[1]"
+            ]
+        );
+    }
+
+    #[actix_web::test]
+    async fn ranges_are_fetched_in_two_batches() {
+        let stub = node(true);
+        let blocks = crawler(&stub).fetch_blocks(&[0x10, 0x11]).await.unwrap();
+
+        assert_eq!(blocks.len(), 2);
+        for (block, block_number) in blocks.iter().zip([0x10, 0x11]) {
+            assert_eq!(block.block_number(), block_number);
+            let hash = &block.transactions[1].hash;
+            assert_eq!(block.receipt(hash).unwrap().status.as_deref(), Some("0x0"));
+        }
+        assert_eq!(
+            methods(&stub),
+            ["eth_getBlockByNumber[2]", "eth_getBlockReceipts[2]"]
+        );
+    }
+
+    #[actix_web::test]
+    async fn receipts_fall_back_to_one_call_per_transaction() {
+        let stub = node(false);
+        let crawler = crawler(&stub);
+
+        let blocks = crawler.fetch_blocks(&[0x10, 0x11]).await.unwrap();
+        for block in blocks.iter() {
+            for transaction in block.transactions.iter() {
+                assert!(block.receipt(&transaction.hash).is_some());
+            }
+        }
+        assert_eq!(
+            methods(&stub),
+            [
+                "eth_getBlockByNumber[2]",
+                "eth_getBlockReceipts[2]",
+                "eth_getTransactionReceipt[2]",
+                "eth_getTransactionReceipt[2]",
+            ]
+        );
+
+        // The unsupported method isn't tried again.
+        let block = crawler.fetch_block(0x11).await.unwrap();
+        assert_eq!(block.receipts.len(), 2);
+        assert_eq!(
+            methods(&stub),
+            ["eth_getBlockByNumber", "eth_getTransactionReceipt[2]"]
+        );
+    }
+
+    #[actix_web::test]
+    async fn blocks_past_the_tip_are_not_found() {
+        let stub = node(true);
+        let err = crawler(&stub)
+            .fetch_blocks(&[0x11, 0x12])
+            .await
+            .unwrap_err();
+        assert_eq!(err, "Block 18 not found");
+    }
+}
@@ -1 +1,5 @@
+pub mod balance_checker;
+pub mod chain_crawler;
+pub mod evm_crawler;
 pub mod starknet_crawler;
+pub mod supervisor;
@@ -1,215 +1,486 @@
+use crate::crawlers::chain_crawler::{BlockHeader, ChainCrawler};
+use crate::models::activity_model::{
+    Activity, ContractInteraction, Direction, ExecutionDetails, InteractionKind, TokenTransfer,
+};
+use crate::models::address_model::StarknetAddress;
 use crate::models::network_model::Network;
-use crate::models::wallet_model::Wallet;
-use crate::services::db::Database;
-use crate::services::mailer::process_transactions_to_mail;
-use dotenv::dotenv;
-use reqwest::Client;
+use crate::models::starknet_model::{
+    abi_function_names, felt_to_u256, starknet_keccak, StarknetBlock,
+};
+use crate::services::rpc::RpcClient;
+use crate::utils::approval_detector::{approval_from_call, risky_approval};
+use crate::utils::calldata_decoder::{decode_call, known_function_name};
+use alloy::primitives::U256;
+use async_trait::async_trait;
 use serde_json::json;
-use std::env;
-use std::error::Error;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use tokio::task;
-use tokio::time::{sleep, Duration};
-
-const BKNETWORK: Network = Network::Starknet;
-const MAX_THREADS: u128 = 3;
-
-pub async fn crawl_starknet(db: Database, interval: u64) {
-    dotenv().ok();
-    let rpc_url = env::var("RPC").expect("DATABASE_URL not found");
-
-    loop {
-        check_new_block(rpc_url.clone(), db.clone()).await;
-        println!("Block checking completed... Going to sleep for {interval} seconds");
-        sleep(Duration::from_secs(interval)).await;
-        println!("Awake and scanning for new transactions...");
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+pub struct StarknetCrawler {
+    network: Network,
+    rpc: RpcClient,
+    // Function names by selector, per class hash.
+    abis: Mutex<HashMap<String, Arc<HashMap<U256, String>>>>,
+}
+
+impl StarknetCrawler {
+    pub fn new(network: Network, rpc: RpcClient) -> Self {
+        StarknetCrawler {
+            network,
+            rpc,
+            abis: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Resolves `selector` through the ABI of the class `contract_address` currently runs.
+    /// ABIs are fetched once per class, so contracts sharing one (accounts, tokens) share
+    /// the fetch and an upgraded contract picks up its new class.
+    async fn abi_function_name(&self, contract_address: &str, selector: &str) -> Option<String> {
+        let selector = felt_to_u256(selector)?;
+        let contract_address = StarknetAddress::normalize(contract_address);
+        let class_hash = match fetch_class_hash(&contract_address, &self.rpc).await {
+            Ok(class_hash) => class_hash,
+            Err(err) => {
+                println!("Failed to fetch class of {contract_address}: {err}");
+                return None;
+            }
+        };
+
+        let cached = self.abis.lock().unwrap().get(&class_hash).cloned();
+        let functions = match cached {
+            Some(functions) => functions,
+            None => match fetch_abi_function_names(&class_hash, &self.rpc).await {
+                Ok(functions) => {
+                    let functions = Arc::new(functions);
+                    self.abis
+                        .lock()
+                        .unwrap()
+                        .insert(class_hash, functions.clone());
+                    functions
+                }
+                Err(err) => {
+                    println!("Failed to fetch ABI of class {class_hash}: {err}");
+                    return None;
+                }
+            },
+        };
+        functions.get(&selector).cloned()
     }
 }
 
-pub async fn check_new_block(rpc_url: String, db: Database) {
-    println!("Block checking started...");
-    let last_scanned_block: u128 = db.get_last_scanned_block(BKNETWORK).await.unwrap_or(0);
+#[async_trait]
+impl ChainCrawler for StarknetCrawler {
+    type Block = StarknetBlock;
 
-    let latest_block = get_latest_block(rpc_url.clone()).await;
+    fn network(&self) -> Network {
+        self.network.clone()
+    }
 
-    if let Ok(block_number) = latest_block {
-        if block_number > last_scanned_block {
-            if block_number - last_scanned_block > 1 {
-                let _result =
-                    check_and_handle_skipped_bocks(last_scanned_block, block_number, rpc_url, db)
-                        .await;
-            } else {
-                handle_new_block(block_number, rpc_url, db).await;
-            }
-        } else {
-            println!("Already scanned block number: {}", block_number);
+    fn poll_interval(&self) -> u64 {
+        60
+    }
+
+    fn new_heads_subscription(&self) -> (&'static str, serde_json::Value) {
+        ("starknet_subscribeNewHeads", json!({}))
+    }
+
+    async fn latest_block(&self) -> Result<u128, String> {
+        get_latest_block(&self.rpc).await
+    }
+
+    async fn fetch_block(&self, block_number: u128) -> Result<Self::Block, String> {
+        fetch_transactions(block_number, &self.rpc).await
+    }
+
+    async fn fetch_blocks(&self, block_numbers: &[u128]) -> Result<Vec<Self::Block>, String> {
+        fetch_transactions_batch(block_numbers, &self.rpc).await
+    }
+
+    async fn fetch_pending_block(&self) -> Result<Option<Self::Block>, String> {
+        fetch_pending_transactions(&self.rpc).await.map(Some)
+    }
+
+    fn block_header(&self, block: &Self::Block) -> BlockHeader {
+        BlockHeader {
+            block_hash: block.block_hash.clone(),
+            parent_hash: block.parent_hash.clone(),
+            status: Some(block.status.clone()),
+            timestamp: block.timestamp,
         }
-    } else {
-        println!("Failed to fetch latest starknet block");
     }
-}
 
-pub async fn handle_new_block(block_number: u128, rpc_url: String, db: Database) {
-    let transactions = fetch_transactions(block_number, rpc_url.clone()).await;
-    if let Ok(transactions) = transactions {
-        println!("New transactions detected");
-        process_transactions(transactions, db.clone()).await;
-        let mut _network_data = db.update_last_scanned_block(BKNETWORK, block_number).await;
-    } else {
-        println!("Failed to fetch transactions from block");
+    fn extract_activity(&self, block: &Self::Block, wallets: &[String]) -> Vec<Activity> {
+        process_transactions(&self.network, block, wallets)
     }
-}
 
-pub async fn handle_new_block_arc(block_number: u128, rpc_url: Arc<String>, db: Arc<Database>) {
-    let rpc_url_str = rpc_url.to_string();
-    let db_str = (*db).clone();
-    handle_new_block(block_number, rpc_url_str, db_str).await;
-}
+    fn extract_contract_activity(
+        &self,
+        block: &Self::Block,
+        contracts: &[String],
+    ) -> Vec<Activity> {
+        process_contract_interactions(&self.network, block, contracts)
+    }
 
-pub async fn get_latest_block(rpc_url: String) -> Result<u128, String> {
-    // Create a JSON-RPC request body
-    let request_body = json!({
-        "jsonrpc": "2.0",
-        "method": "starknet_blockNumber",
-        "params": [],
-        "id": 0
-    });
+    fn transaction_execution(
+        &self,
+        block: &Self::Block,
+        transaction_hash: &str,
+    ) -> Option<ExecutionDetails> {
+        block
+            .transactions
+            .iter()
+            .find(|tx| tx.receipt.transaction_hash == transaction_hash)
+            .map(|tx| tx.receipt.execution_details())
+    }
 
-    // Log message
+    /// Decodes the calls of transactions the wallet sent itself.
+    async fn describe_activity(&self, block: &Self::Block, activities: &mut [Activity]) {
+        for activity in activities.iter_mut() {
+            let tx = block
+                .transactions
+                .iter()
+                .find(|tx| tx.receipt.transaction_hash == activity.transaction_hash);
+            let sent_by_wallet = tx.is_some_and(|tx| {
+                tx.transaction.sender_address().is_some_and(|sender| {
+                    StarknetAddress::normalize(sender) == activity.wallet_address
+                })
+            });
+            let tx = match tx {
+                Some(tx) if sent_by_wallet => tx,
+                _ => continue,
+            };
+
+            let mut calls = Vec::new();
+            for call in tx.transaction.calls() {
+                let function_name = match known_function_name(&call.selector) {
+                    Some(name) => Some(name.to_string()),
+                    None => self.abi_function_name(&call.to, &call.selector).await,
+                };
+                calls.push(decode_call(&call, function_name));
+            }
+            activity.calls = calls;
+        }
+    }
+}
+
+pub async fn get_latest_block(rpc: &RpcClient) -> Result<u128, String> {
     println!("LOG:: Fetching latest starknet block");
+    let block_number: u64 = rpc.request("starknet_blockNumber", json!([])).await?;
+    println!("LOG: Block number query response data: {:?}", block_number);
+    Ok(block_number.into())
+}
 
-    // Send the POST request
-    let client = Client::new();
-    let response = client
-        .post(rpc_url)
-        .json(&request_body)
-        .header("Content-Type", "application/json")
-        .send()
-        .await
-        .unwrap();
-
-    // Check if the request was successful
-    if response.status().is_success() {
-        let response_json: serde_json::Value = response.json().await.unwrap();
-        let block: &serde_json::Value = &response_json["result"];
-        println!("LOG: Block number query response data: {:?}", block);
-        let block_number: u128 = block.as_u64().unwrap().into();
-        return Ok(block_number);
-        // Log the result
-    } else {
-        println!("Error occurred: {:?}", response.status());
-        return Err("Failed to fetch latest starknet block".to_string());
-    }
+fn block_params(block_number: u128) -> serde_json::Value {
+    json!({
+        "block_id": {
+            "block_number": block_number,
+        }
+    })
 }
 
-pub async fn fetch_transactions(block_number: u128, rpc_url: String) -> Result<String, String> {
+pub async fn fetch_transactions(
+    block_number: u128,
+    rpc: &RpcClient,
+) -> Result<StarknetBlock, String> {
     println!("LOG:: Fetched transaction for block number: {block_number}");
+    rpc.request("starknet_getBlockWithReceipts", block_params(block_number))
+        .await
+}
 
-    // Create a JSON-RPC request body
-    let request_body = json!({
-        "jsonrpc": "2.0",
-        "method": "starknet_getBlockWithTxs",
-        "params": {
-            "block_id": {
-                "block_number": block_number,
+pub async fn fetch_class_hash(contract_address: &str, rpc: &RpcClient) -> Result<String, String> {
+    let class_hash: String = rpc
+        .request(
+            "starknet_getClassHashAt",
+            json!({
+                "block_id": "latest",
+                "contract_address": contract_address,
+            }),
+        )
+        .await?;
+    Ok(StarknetAddress::normalize(&class_hash))
+}
 
-            }
-        },
-        "id": 0
-    });
-
-    // Send the POST request
-    let client = Client::new();
-    let response = client
-        .post(rpc_url)
-        .json(&request_body)
-        .header("Content-Type", "application/json")
-        .send()
-        .await
-        .unwrap();
-
-    // Check if the request was successful
-    if response.status().is_success() {
-        let response_json: serde_json::Value = response.json().await.unwrap();
-        println!("{}", response_json.is_object());
-
-        let transactions = &response_json["result"]["transactions"];
-        return Ok(transactions.to_string());
-        // Log the result
-    } else {
-        println!("Error occurred: {:?}", response.status());
-        return Err("Failed to fetch latest starknet block".to_string());
-    }
+pub async fn fetch_abi_function_names(
+    class_hash: &str,
+    rpc: &RpcClient,
+) -> Result<HashMap<U256, String>, String> {
+    let class: serde_json::Value = rpc
+        .request(
+            "starknet_getClass",
+            json!({
+                "block_id": "latest",
+                "class_hash": class_hash,
+            }),
+        )
+        .await?;
+    Ok(abi_function_names(&class["abi"]))
 }
 
-pub async fn process_transactions(transactions: String, db: Database) {
-    let wallets: Vec<Wallet> = match db.get_all_wallets_via_network(BKNETWORK).await {
-        Ok(wallets) => wallets,
-        Err(err) => {
-            println!("Failed to get wallets: {:?}", err);
-            return;
+/// The wallet's balance of an ERC-20 token. Tokens return a u256 as (low, high) felts,
+/// legacy ones a single felt.
+pub async fn fetch_token_balance(
+    token_address: &str,
+    wallet_address: &str,
+    rpc: &RpcClient,
+) -> Result<U256, String> {
+    let result: Vec<String> = rpc
+        .request(
+            "starknet_call",
+            json!({
+                "request": {
+                    "contract_address": token_address,
+                    "entry_point_selector": format!("0x{:x}", starknet_keccak("balanceOf")),
+                    "calldata": [wallet_address],
+                },
+                "block_id": "latest",
+            }),
+        )
+        .await?;
+
+    let felt = |index: usize| -> Result<U256, String> {
+        match result.get(index) {
+            Some(felt) => felt_to_u256(felt).ok_or(format!("Invalid balance felt: {felt}")),
+            None => Ok(U256::ZERO),
         }
     };
+    if result.is_empty() {
+        return Err(format!("balanceOf on {token_address} returned nothing"));
+    }
+    Ok(felt(0)? + (felt(1)? << 128))
+}
+
+pub async fn fetch_transactions_batch(
+    block_numbers: &[u128],
+    rpc: &RpcClient,
+) -> Result<Vec<StarknetBlock>, String> {
+    let params_list = block_numbers.iter().map(|n| block_params(*n)).collect();
+    rpc.batch_request("starknet_getBlockWithReceipts", params_list)
+        .await
+}
+
+pub async fn fetch_pending_transactions(rpc: &RpcClient) -> Result<StarknetBlock, String> {
+    rpc.request(
+        "starknet_getBlockWithReceipts",
+        json!({ "block_id": "pending" }),
+    )
+    .await
+}
 
+/// Matches both transactions sent by a registered wallet and ERC-20 transfers into or out
+/// of one. A sent transaction that moved tokens out of the sender is reported through its
+/// transfers; fee payments to the sequencer are ignored. Risky allowances granted by a
+/// wallet are matched from `Approval` events, or from its approve calls when no event
+/// was emitted (reverted or pending transactions).
+pub fn process_transactions(
+    network: &Network,
+    block: &StarknetBlock,
+    wallets: &[String],
+) -> Vec<Activity> {
     println!("LOG:: Searching for transactions from registered wallets...");
 
-    let transactions_json: serde_json::Value = serde_json::from_str(&transactions).unwrap();
+    // _print_addresses(block, wallets);
+
+    let sequencer_address = StarknetAddress::normalize(&block.sequencer_address);
+    let mut activities: Vec<Activity> = Vec::new();
+
+    for tx in block.transactions.iter() {
+        let transaction_hash = tx.receipt.transaction_hash.clone();
+        let execution = tx.receipt.execution_details();
+        let sender_address = tx
+            .transaction
+            .sender_address()
+            .map(StarknetAddress::normalize);
+        let mut sender_transferred = false;
+        let mut sender_approved = false;
+
+        for (event_index, event) in tx.receipt.events.iter().enumerate() {
+            if let Some(approval) = event.as_approval() {
+                let owner = StarknetAddress::normalize(&approval.owner);
+                let risky = risky_approval(
+                    &StarknetAddress::normalize(&approval.token_address),
+                    &StarknetAddress::normalize(&approval.spender),
+                    approval.amount,
+                );
+                if let (true, Some(risky)) = (wallets.contains(&owner), risky) {
+                    sender_approved |= sender_address.as_ref() == Some(&owner);
+                    activities.push(
+                        Activity::new(
+                            network.clone(),
+                            block.block_number.into(),
+                            transaction_hash.clone(),
+                            owner,
+                            Direction::Outgoing,
+                        )
+                        .with_approval_event(event_index as u32, risky)
+                        .with_execution(execution.clone()),
+                    );
+                }
+                continue;
+            }
 
-    let same_network_wallets: Vec<String> = filter_networks(wallets, BKNETWORK)
-        .into_iter()
-        .map(|x| x.to_lowercase())
-        .collect();
+            let transfer = match event.as_transfer() {
+                Some(transfer) => transfer,
+                None => continue,
+            };
+            let from = StarknetAddress::normalize(&transfer.from);
+            let to = StarknetAddress::normalize(&transfer.to);
+            if to == sequencer_address {
+                continue;
+            }
 
-    // _print_addresses(transactions_json.clone(), same_network_wallets.clone());
-
-    let relevant_tx: Vec<&serde_json::Value> = transactions_json
-        .as_array()
-        .expect("transactions is not json")
-        .into_iter()
-        .filter(|tx| {
-            same_network_wallets.contains(&build_address(
-                tx["sender_address"]
-                    .as_str()
-                    .unwrap_or("0x0000000000000000000000")
-                    .to_string()
-                    .to_lowercase(),
-            ))
-        })
-        .collect();
+            let token_address = StarknetAddress::normalize(&transfer.token_address);
+            let amount = transfer.amount.to_string();
+            if wallets.contains(&from) {
+                sender_transferred |= sender_address.as_ref() == Some(&from);
+                activities.push(
+                    Activity::new(
+                        network.clone(),
+                        block.block_number.into(),
+                        transaction_hash.clone(),
+                        from.clone(),
+                        Direction::Outgoing,
+                    )
+                    .with_transfer(
+                        event_index as u32,
+                        TokenTransfer {
+                            token_address: token_address.clone(),
+                            amount: amount.clone(),
+                            counterparty: to.clone(),
+                        },
+                    )
+                    .with_execution(execution.clone()),
+                );
+            }
+            // A transfer to oneself is reported once, as sent.
+            if wallets.contains(&to) && to != from {
+                activities.push(
+                    Activity::new(
+                        network.clone(),
+                        block.block_number.into(),
+                        transaction_hash.clone(),
+                        to,
+                        Direction::Incoming,
+                    )
+                    .with_transfer(
+                        event_index as u32,
+                        TokenTransfer {
+                            token_address,
+                            amount,
+                            counterparty: from,
+                        },
+                    )
+                    .with_execution(execution.clone()),
+                );
+            }
+        }
 
-    if relevant_tx.is_empty() {
-        println!("No relevant transactions found.");
-        return;
-    } else {
-        println!("LOG:: {}, Relevant transactions found:", relevant_tx.len());
-        process_transactions_to_mail(relevant_tx, BKNETWORK, db).await;
+        if let Some(sender_address) = sender_address {
+            if wallets.contains(&sender_address) {
+                let call_approval = if sender_approved {
+                    None
+                } else {
+                    tx.transaction.calls().iter().find_map(approval_from_call)
+                };
+                if !sender_transferred || call_approval.is_some() {
+                    let mut activity = Activity::new(
+                        network.clone(),
+                        block.block_number.into(),
+                        transaction_hash,
+                        sender_address,
+                        Direction::Outgoing,
+                    )
+                    .with_execution(execution);
+                    if let Some(approval) = call_approval {
+                        activity = activity.with_approval(approval);
+                    }
+                    activities.push(activity);
+                }
+            }
+        }
     }
+
+    activities
 }
 
-pub fn filter_networks(wallets: Vec<Wallet>, network: Network) -> Vec<String> {
-    let mut same_network_wallets: Vec<String> = Vec::new();
-    for wallet in wallets {
-        if wallet.network == network {
-            same_network_wallets.push(wallet.wallet_address);
+/// Matches calls to, and events emitted by, watched contracts. A transaction calling the
+/// same contract several times is reported once.
+pub fn process_contract_interactions(
+    network: &Network,
+    block: &StarknetBlock,
+    contracts: &[String],
+) -> Vec<Activity> {
+    let mut activities: Vec<Activity> = Vec::new();
+
+    for tx in block.transactions.iter() {
+        let transaction_hash = tx.receipt.transaction_hash.clone();
+        let execution = tx.receipt.execution_details();
+        let caller = tx
+            .transaction
+            .sender_address()
+            .map(StarknetAddress::normalize);
+
+        let mut called: Vec<String> = Vec::new();
+        for call in tx.transaction.calls() {
+            let contract_address = StarknetAddress::normalize(&call.to);
+            if !contracts.contains(&contract_address) || called.contains(&contract_address) {
+                continue;
+            }
+            called.push(contract_address.clone());
+            activities.push(
+                Activity::new(
+                    network.clone(),
+                    block.block_number.into(),
+                    transaction_hash.clone(),
+                    contract_address,
+                    Direction::Incoming,
+                )
+                .with_interaction(
+                    None,
+                    ContractInteraction {
+                        kind: InteractionKind::Call,
+                        selector: call.selector,
+                        caller: caller.clone(),
+                    },
+                )
+                .with_execution(execution.clone()),
+            );
+        }
+
+        for (event_index, event) in tx.receipt.events.iter().enumerate() {
+            let contract_address = StarknetAddress::normalize(&event.from_address);
+            if !contracts.contains(&contract_address) {
+                continue;
+            }
+            activities.push(
+                Activity::new(
+                    network.clone(),
+                    block.block_number.into(),
+                    transaction_hash.clone(),
+                    contract_address,
+                    Direction::Incoming,
+                )
+                .with_interaction(
+                    Some(event_index as u32),
+                    ContractInteraction {
+                        kind: InteractionKind::Event,
+                        selector: event.keys.first().cloned().unwrap_or_default(),
+                        caller: caller.clone(),
+                    },
+                )
+                .with_execution(execution.clone()),
+            );
         }
     }
-    same_network_wallets
+
+    activities
 }
 
-pub fn _print_addresses(transactions_json: serde_json::Value, same_network_wallets: Vec<String>) {
-    let transaction_senders: Vec<String> = transactions_json
-        .as_array()
-        .expect("transactions_json is not an array")
+pub fn _print_addresses(block: &StarknetBlock, same_network_wallets: &[String]) {
+    let transaction_senders: Vec<String> = block
+        .transactions
         .iter()
-        .map(|tx| {
-            build_address(
-                tx["sender_address"]
-                    .as_str()
-                    .unwrap_or("0x0000000000000000000000")
-                    .to_string(),
-            )
-        })
+        .filter_map(|tx| tx.transaction.sender_address())
+        .map(StarknetAddress::normalize)
         .collect();
 
     println!(
@@ -219,71 +490,251 @@ pub fn _print_addresses(transactions_json: serde_json::Value, same_network_walle
     println!("ADDRESSES IN DATABASE: {:?}", same_network_wallets);
 }
 
-pub fn build_address(address: String) -> String {
-    let mut address_parts: String = address.split_at(2).1.to_string();
-    // println!("ADDRESS: {:?}", address_parts);
-    // println!("{}", address_parts.len());
-    let address_len_diff = 64 as usize - address_parts.len();
+// 0x056c4315aeb9253549dccde747faf17d682e3b46e4b33a1e2dec6fa0f01481c
+// 0x0785088ef651fa463df0f597d7e0ad9421b0a0303315015d63d3a3f47748a5c9
 
-    for _ in 0..address_len_diff {
-        address_parts.insert(0, '0');
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::activity_model::AllowanceRisk;
+    use crate::models::network_model::RpcEndpoint;
+    use crate::models::starknet_model::{APPROVAL_EVENT_SELECTOR, TRANSFER_EVENT_SELECTOR};
+    use crate::utils::http_stub::HttpStub;
+    use serde_json::Value;
+
+    const SEQUENCER: &str = "0x1176a1bd84444c89232ec27754698e5d2e7e1a7f1539f12027f28b23ec9f3d8";
+    const ETH: &str = "0x49d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7";
+    const ALICE: &str = "0xa11ce";
+    const BOB: &str = "0xb0b";
+    const SPENDER: &str = "0x5e11";
+
+    fn address(address: &str) -> String {
+        StarknetAddress::normalize(address)
     }
 
-    let final_address = format!("0x{}", address_parts);
-    return final_address;
-}
+    /// Cairo 0 tokens (ETH, STRK) put every field of the event in `data`.
+    fn cairo0_event(selector: &str, first: &str, second: &str, amount: &str) -> Value {
+        json!({"from_address": ETH, "keys": [selector], "data": [first, second, amount, "0x0"]})
+    }
 
-pub async fn check_and_handle_skipped_bocks(
-    last_scanned_block: u128,
-    current_block: u128,
-    rpc_url: String,
-    db: Database,
-) -> Result<(), Box<dyn Error>> {
-    let skipped_blocks = current_block.saturating_sub(last_scanned_block);
-    println!("BLOCKS SKIPPED: {:?}", skipped_blocks);
-
-    if skipped_blocks <= 3 {
-        for block_number in (last_scanned_block + 1)..=current_block {
-            handle_new_block(block_number, rpc_url.clone(), db.clone()).await;
-        }
-        Ok(())
-    } else {
-        let rpc_url = Arc::new(rpc_url);
-        let db = Arc::new(db);
-        // For more than 5 skipped blocks, create a thread pool of up to 3 threads
-        let blocks_per_thread: u128 = ((skipped_blocks as f64 / MAX_THREADS as f64).ceil() as u64)
-            .max(1)
-            .into();
-        let thread_pool = Arc::new(Mutex::new(Vec::new()));
-
-        for thread_index in 0..MAX_THREADS {
-            let thread_pool = Arc::clone(&thread_pool);
-            let start_block = last_scanned_block + 1 + thread_index * blocks_per_thread;
-            let end_block = (start_block + blocks_per_thread - 1).min(current_block);
-            let rpc_url = Arc::clone(&rpc_url);
-            let db = Arc::clone(&db);
-
-            if start_block > current_block {
-                break;
-            }
+    /// Cairo 1 OpenZeppelin tokens mark both addresses as keys.
+    fn cairo1_event(selector: &str, first: &str, second: &str, amount: &str) -> Value {
+        json!({"from_address": ETH, "keys": [selector, first, second], "data": [amount, "0x0"]})
+    }
 
-            task::spawn(async move {
-                let mut thread = thread_pool.lock().await;
-                thread.push(thread_index);
+    fn fee(sender: &str) -> Value {
+        cairo0_event(TRANSFER_EVENT_SELECTOR, sender, SEQUENCER, "0x1f4")
+    }
 
-                for block_number in start_block..=end_block {
-                    handle_new_block_arc(block_number, rpc_url.clone(), db.clone()).await;
-                }
-                println!(
-                    "Thread {} completed processing from {} to {}",
-                    thread_index, start_block, end_block
-                );
+    /// A block with one INVOKE v1 sent by `sender`, whose receipt holds `events`.
+    fn block(sender: &str, status: &str, events: Vec<Value>) -> StarknetBlock {
+        serde_json::from_value(json!({
+            "block_number": 7,
+            "block_hash": "0xb7",
+            "parent_hash": "0xb6",
+            "status": "ACCEPTED_ON_L2",
+            "timestamp": 1_730_060_896,
+            "sequencer_address": SEQUENCER,
+            "transactions": [{
+                "transaction": {
+                    "type": "INVOKE",
+                    "version": "0x1",
+                    "sender_address": sender,
+                    "calldata": ["0x0"],
+                    "max_fee": "0x10",
+                    "nonce": "0x1",
+                    "signature": [],
+                },
+                "receipt": {
+                    "transaction_hash": "0x7a",
+                    "actual_fee": {"amount": "0x1f4", "unit": "WEI"},
+                    "execution_status": status,
+                    "finality_status": "ACCEPTED_ON_L2",
+                    "events": events,
+                },
+            }],
+        }))
+        .unwrap()
+    }
+
+    fn activities(block: &StarknetBlock, wallets: &[&str]) -> Vec<Activity> {
+        let wallets: Vec<String> = wallets.iter().map(|wallet| address(wallet)).collect();
+        process_transactions(&Network::Starknet, block, &wallets)
+    }
+
+    #[test]
+    fn incoming_cairo0_transfers_are_matched_on_the_recipient() {
+        let block = block(
+            ALICE,
+            "SUCCEEDED",
+            vec![
+                cairo0_event(TRANSFER_EVENT_SELECTOR, ALICE, BOB, "0x64"),
+                fee(ALICE),
+            ],
+        );
+        let found = activities(&block, &[BOB]);
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].wallet_address, address(BOB));
+        assert_eq!(found[0].direction, Direction::Incoming);
+        assert_eq!(found[0].event_index, Some(0));
+        assert_eq!(
+            found[0].transfer,
+            Some(TokenTransfer {
+                token_address: address(ETH),
+                amount: "100".to_string(),
+                counterparty: address(ALICE),
             })
-            .await?;
+        );
+    }
+
+    #[test]
+    fn sent_cairo1_transfers_replace_the_plain_transaction() {
+        let block = block(
+            ALICE,
+            "SUCCEEDED",
+            vec![
+                cairo1_event(TRANSFER_EVENT_SELECTOR, ALICE, BOB, "0x64"),
+                fee(ALICE),
+            ],
+        );
+        let found = activities(&block, &[ALICE, BOB]);
+
+        let directions: Vec<(String, Direction)> = found
+            .iter()
+            .map(|activity| (activity.wallet_address.clone(), activity.direction.clone()))
+            .collect();
+        assert_eq!(
+            directions,
+            [
+                (address(ALICE), Direction::Outgoing),
+                (address(BOB), Direction::Incoming),
+            ]
+        );
+        assert!(found.iter().all(|activity| activity.transfer.is_some()));
+    }
+
+    #[test]
+    fn fee_payments_alone_report_the_sent_transaction() {
+        let block = block(ALICE, "REVERTED", vec![fee(ALICE)]);
+        let found = activities(&block, &[ALICE]);
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].direction, Direction::Outgoing);
+        assert_eq!(found[0].transfer, None);
+        assert!(found[0].is_reverted());
+    }
+
+    #[test]
+    fn self_transfers_are_reported_once() {
+        let block = block(
+            ALICE,
+            "SUCCEEDED",
+            vec![
+                cairo0_event(TRANSFER_EVENT_SELECTOR, ALICE, ALICE, "0x64"),
+                fee(ALICE),
+            ],
+        );
+        let found = activities(&block, &[ALICE]);
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].direction, Direction::Outgoing);
+        assert_eq!(
+            found[0].transfer.as_ref().unwrap().counterparty,
+            address(ALICE)
+        );
+    }
+
+    #[test]
+    fn unlimited_approvals_are_matched_in_both_layouts() {
+        let unlimited = format!("{:#x}", u128::MAX);
+        for event in [cairo0_event, cairo1_event] {
+            let block = block(
+                ALICE,
+                "SUCCEEDED",
+                vec![
+                    event(APPROVAL_EVENT_SELECTOR, ALICE, SPENDER, &unlimited),
+                    fee(ALICE),
+                ],
+            );
+            let found = activities(&block, &[ALICE]);
+
+            // The approval stands for the transaction, which moved no tokens.
+            assert_eq!(found.len(), 2);
+            let approval = found[0].approval.as_ref().unwrap();
+            assert_eq!(approval.spender, address(SPENDER));
+            assert_eq!(approval.risk, AllowanceRisk::Unlimited);
+            assert_eq!(found[0].event_index, Some(0));
+            assert_eq!(found[1].approval, None);
         }
-        Ok(())
     }
-}
 
-// 0x056c4315aeb9253549dccde747faf17d682e3b46e4b33a1e2dec6fa0f01481c
-// 0x0785088ef651fa463df0f597d7e0ad9421b0a0303315015d63d3a3f47748a5c9
+    #[test]
+    fn unrelated_wallets_match_nothing() {
+        let block = block(
+            ALICE,
+            "SUCCEEDED",
+            vec![
+                cairo0_event(TRANSFER_EVENT_SELECTOR, ALICE, BOB, "0x64"),
+                fee(ALICE),
+            ],
+        );
+        assert!(activities(&block, &["0xca401"]).is_empty());
+    }
+
+    #[actix_web::test]
+    async fn abis_are_fetched_once_per_class() {
+        let node = HttpStub::json_rpc(|method, params| match method {
+            "starknet_getClassHashAt" => match params["contract_address"].as_str() {
+                Some(address) if address == StarknetAddress::normalize(BOB) => Ok(json!("0xc2")),
+                _ => Ok(json!("0x00c1")),
+            },
+            "starknet_getClass" => {
+                let name = match params["class_hash"].as_str() {
+                    Some(class_hash) if class_hash == StarknetAddress::normalize("0xc1") => {
+                        "set_greeting"
+                    }
+                    _ => "set_farewell",
+                };
+                let abi = json!([{"type": "function", "name": name}]);
+                Ok(json!({"abi": abi.to_string()}))
+            }
+            _ => Err(json!({"code": -32601, "message": "Method not found"})),
+        });
+        let crawler = StarknetCrawler::new(
+            Network::Starknet,
+            RpcClient::new(vec![RpcEndpoint::new(node.url.clone())]),
+        );
+        let selector = |name| format!("{:#x}", starknet_keccak(name));
+
+        for contract in [ALICE, SPENDER] {
+            let name = crawler
+                .abi_function_name(contract, &selector("set_greeting"))
+                .await;
+            assert_eq!(name.as_deref(), Some("set_greeting"));
+        }
+        let name = crawler
+            .abi_function_name(BOB, &selector("set_farewell"))
+            .await;
+        assert_eq!(name.as_deref(), Some("set_farewell"));
+
+        let methods: Vec<String> = node
+            .received()
+            .iter()
+            .map(|request| {
+                let call: Value = serde_json::from_str(&request.body).unwrap();
+                call["method"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(
+            methods,
+            [
+                "starknet_getClassHashAt",
+                "starknet_getClass",
+                "starknet_getClassHashAt",
+                "starknet_getClassHashAt",
+                "starknet_getClass",
+            ]
+        );
+    }
+}
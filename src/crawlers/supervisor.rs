@@ -0,0 +1,225 @@
+use crate::crawlers::chain_crawler::{backfill_wallet, run_crawler};
+use crate::crawlers::evm_crawler::EvmCrawler;
+use crate::crawlers::starknet_crawler::StarknetCrawler;
+use crate::models::network_model::{Network, NetworkManager};
+use crate::models::wallet_model::Wallet;
+use crate::services::db::Database;
+use crate::services::rpc::{RpcClient, RpcRegistry};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::task::{AbortHandle, JoinHandle};
+use tokio::time::{sleep, Duration};
+
+const RESTART_DELAY_SECS: u64 = 10;
+
+/// A supervised network. Each `start` gets a new generation, so a supervisor loop left
+/// over from before a restart sees it is stale and exits instead of spawning a second
+/// crawler.
+struct SupervisedCrawler {
+    generation: u64,
+    /// The running crawler task.
+    handle: Option<AbortHandle>,
+}
+
+/// Runs one crawler task per registered network and restarts it if it panics.
+#[derive(Clone)]
+pub struct CrawlerSupervisor {
+    db: Database,
+    rpcs: RpcRegistry,
+    // A network is present while it is supervised.
+    crawlers: Arc<Mutex<HashMap<Network, SupervisedCrawler>>>,
+    next_generation: Arc<AtomicU64>,
+}
+
+impl CrawlerSupervisor {
+    pub fn new(db: Database, rpcs: RpcRegistry) -> Self {
+        CrawlerSupervisor {
+            db,
+            rpcs,
+            crawlers: Arc::new(Mutex::new(HashMap::new())),
+            next_generation: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn is_current(&self, network: &Network, generation: u64) -> bool {
+        self.crawlers
+            .lock()
+            .unwrap()
+            .get(network)
+            .is_some_and(|crawler| crawler.generation == generation)
+    }
+
+    /// Forgets the network, unless a newer generation has taken it over.
+    fn release(&self, network: &Network, generation: u64) {
+        let mut crawlers = self.crawlers.lock().unwrap();
+        if crawlers
+            .get(network)
+            .is_some_and(|crawler| crawler.generation == generation)
+        {
+            crawlers.remove(network);
+        }
+    }
+
+    pub async fn start_all(&self) {
+        match self.db.get_all_networks().await {
+            Ok(networks) => {
+                for network in networks {
+                    self.start(network.network_type);
+                }
+            }
+            Err(err) => println!("Failed to load networks, no crawler started: {:?}", err),
+        }
+    }
+
+    pub fn start(&self, network: Network) {
+        let generation = self.next_generation.fetch_add(1, Ordering::SeqCst);
+        {
+            let mut crawlers = self.crawlers.lock().unwrap();
+            if crawlers.contains_key(&network) {
+                println!("{network:?} crawler is already running");
+                return;
+            }
+            crawlers.insert(
+                network.clone(),
+                SupervisedCrawler {
+                    generation,
+                    handle: None,
+                },
+            );
+        }
+
+        let supervisor = self.clone();
+        tokio::spawn(async move { supervisor.supervise(network, generation).await });
+    }
+
+    pub fn stop(&self, network: &Network) {
+        let removed = self.crawlers.lock().unwrap().remove(network);
+        if let Some(SupervisedCrawler {
+            handle: Some(handle),
+            ..
+        }) = removed
+        {
+            handle.abort();
+            println!("{network:?} crawler stopped");
+        }
+    }
+
+    /// Restarts the crawler so it picks up changed network settings such as RPC endpoints.
+    pub fn restart(&self, network: Network) {
+        self.stop(&network);
+        self.start(network);
+    }
+
+    /// Stores the recent history of a newly added wallet in the background.
+    pub fn backfill(&self, wallet: Wallet, block_count: u64) {
+        let supervisor = self.clone();
+        tokio::spawn(async move {
+            let network = wallet.network.clone();
+            let (_, rpc) = match supervisor.load_network(&network).await {
+                Ok(loaded) => loaded,
+                Err(err) => {
+                    println!("{network:?} wallet backfill will not start: {err}");
+                    return;
+                }
+            };
+            let db = supervisor.db.clone();
+            let address = wallet.wallet_address;
+            let result = match network {
+                Network::Starknet => {
+                    let crawler = Arc::new(StarknetCrawler::new(network.clone(), rpc));
+                    backfill_wallet(crawler, address, block_count, db).await
+                }
+                Network::Ethereum | Network::Base | Network::Optimism => {
+                    let crawler = Arc::new(EvmCrawler::new(network.clone(), rpc));
+                    backfill_wallet(crawler, address, block_count, db).await
+                }
+            };
+            if let Err(err) = result {
+                println!("{network:?} wallet backfill failed: {err}");
+            }
+        });
+    }
+
+    async fn supervise(&self, network: Network, generation: u64) {
+        loop {
+            let handle = match self.spawn_crawler(&network).await {
+                Some(handle) => handle,
+                None => {
+                    self.release(&network, generation);
+                    return;
+                }
+            };
+
+            {
+                let mut crawlers = self.crawlers.lock().unwrap();
+                match crawlers.get_mut(&network) {
+                    Some(crawler) if crawler.generation == generation => {
+                        crawler.handle = Some(handle.abort_handle())
+                    }
+                    // Stopped or restarted while we were spawning.
+                    _ => {
+                        handle.abort();
+                        return;
+                    }
+                }
+            }
+
+            match handle.await {
+                Err(err) if err.is_panic() => {
+                    println!(
+                        "{network:?} crawler panicked, restarting in {RESTART_DELAY_SECS} seconds"
+                    );
+                    sleep(Duration::from_secs(RESTART_DELAY_SECS)).await;
+                }
+                // Aborted through `stop`, which already removed the entry.
+                Err(_) => return,
+                Ok(()) => {
+                    self.release(&network, generation);
+                    return;
+                }
+            }
+
+            if !self.is_current(&network, generation) {
+                return;
+            }
+        }
+    }
+
+    async fn spawn_crawler(&self, network: &Network) -> Option<JoinHandle<()>> {
+        let (network_manager, rpc) = match self.load_network(network).await {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                println!("{network:?} crawler will not start: {err}");
+                return None;
+            }
+        };
+
+        let db = self.db.clone();
+        let ws_endpoint = network_manager.ws_endpoint;
+        let handle = match network {
+            Network::Starknet => tokio::spawn(run_crawler(
+                Arc::new(StarknetCrawler::new(network.clone(), rpc)),
+                db,
+                ws_endpoint,
+            )),
+            Network::Ethereum | Network::Base | Network::Optimism => tokio::spawn(run_crawler(
+                Arc::new(EvmCrawler::new(network.clone(), rpc)),
+                db,
+                ws_endpoint,
+            )),
+        };
+        println!("{network:?} crawler started");
+        Some(handle)
+    }
+
+    async fn load_network(&self, network: &Network) -> Result<(NetworkManager, RpcClient), String> {
+        let network_manager = self
+            .db
+            .get_network_via_name(network.clone())
+            .await
+            .map_err(|err| format!("{:?}", err))?;
+        let rpc = self.rpcs.client_for(&network_manager)?;
+        Ok((network_manager, rpc))
+    }
+}
@@ -4,24 +4,31 @@ mod routes;
 mod services;
 mod utils;
 use actix_cors::Cors;
-use actix_web::{
-    get, http, middleware::Logger, web, web::Data, App, HttpResponse, HttpServer, Responder,
-};
-use crawlers::starknet_crawler::crawl_starknet;
+use actix_web::{http, middleware::Logger, web::Data, App, HttpServer};
+use crawlers::balance_checker::run_balance_checker;
+use crawlers::supervisor::CrawlerSupervisor;
 use std::env;
 
 use routes::admin_routes::{
-    create_network, delete_network, get_all_network, get_last_scanned_block,
-    set_last_scanned_block, update_network_chain_id,
+    create_network, delete_network, get_all_network, get_dead_letters, get_last_scanned_block,
+    get_mail_queue, preview_mail_template, replay_dead_letter, set_backfill, set_confirmation,
+    set_last_scanned_block, set_pending_alerts, set_rpc_endpoints, set_ws_endpoint,
+    update_network_chain_id,
 };
 use routes::health_route::health_check;
 use routes::user_route::{
-    add_wallet, create_user, delete_wallet, get_all_users, get_all_users_via_network,
-    get_all_wallets_via_network, get_profile, get_user_via_email, get_users_via_wallet,
-    get_wallets, update_user_email, update_wallets,
+    add_balance_watch, add_contract, add_rule, add_wallet, create_user, delete_balance_watch,
+    delete_contract, delete_rule, delete_wallet, get_activity, get_all_users,
+    get_all_users_via_network, get_all_wallets_via_network, get_balance_watches, get_channels,
+    get_contracts, get_profile, get_rules, get_user_via_email, get_users_via_wallet, get_wallets,
+    update_channels, update_language, update_notify_on, update_rule, update_user_email,
+    update_wallets,
 };
 use services::db::Database;
-use tokio::time::{interval, Duration};
+use services::mail_queue::start_mail_workers;
+use services::mailer::run_notification_retries;
+use services::rpc::RpcRegistry;
+use services::webhook::start_webhook_workers;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -35,12 +42,20 @@ async fn main() -> std::io::Result<()> {
         .parse()
         .expect("Not a valid port");
 
-    tokio::spawn(crawl_starknet(db.clone(), 60));
+    let rpcs = RpcRegistry::new();
+    let supervisor = CrawlerSupervisor::new(db.clone(), rpcs.clone());
+    supervisor.start_all().await;
+    tokio::spawn(run_balance_checker(db.clone(), rpcs));
+    start_mail_workers(db.clone()).await;
+    start_webhook_workers(db.clone());
+    tokio::spawn(run_notification_retries(db.clone()));
     let db_data = Data::new(db);
+    let supervisor_data = Data::new(supervisor);
     HttpServer::new(move || {
         let logger = Logger::default();
         App::new()
             .app_data(db_data.clone())
+            .app_data(supervisor_data.clone())
             .wrap(logger)
             .wrap(
                 Cors::default()
@@ -57,18 +72,42 @@ async fn main() -> std::io::Result<()> {
             .service(create_user)
             .service(add_wallet)
             .service(delete_wallet)
+            .service(add_contract)
+            .service(delete_contract)
+            .service(get_contracts)
+            .service(add_rule)
+            .service(update_rule)
+            .service(delete_rule)
+            .service(get_rules)
+            .service(add_balance_watch)
+            .service(delete_balance_watch)
+            .service(get_balance_watches)
+            .service(get_activity)
             .service(get_profile)
             .service(get_wallets)
             .service(update_wallets)
             .service(get_user_via_email)
             .service(get_all_users)
             .service(update_user_email)
+            .service(update_notify_on)
+            .service(update_language)
+            .service(update_channels)
+            .service(get_channels)
             .service(create_network)
             .service(update_network_chain_id)
             .service(get_all_network)
             .service(delete_network)
             .service(get_last_scanned_block)
             .service(set_last_scanned_block)
+            .service(set_confirmation)
+            .service(set_backfill)
+            .service(set_rpc_endpoints)
+            .service(set_ws_endpoint)
+            .service(set_pending_alerts)
+            .service(get_dead_letters)
+            .service(replay_dead_letter)
+            .service(get_mail_queue)
+            .service(preview_mail_template)
             .service(get_all_wallets_via_network)
             .service(get_users_via_wallet)
             .service(get_all_users_via_network)
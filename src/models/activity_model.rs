@@ -0,0 +1,249 @@
+use super::network_model::Network;
+use crate::utils::calldata_decoder::summarize_activity;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Direction {
+    Outgoing,
+    Incoming,
+}
+
+/// A token transfer (ERC-20 `Transfer` event or native value) seen from the watched wallet.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TokenTransfer {
+    pub token_address: String,
+    /// Raw amount in the token's smallest unit, as a decimal string.
+    pub amount: String,
+    pub counterparty: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Fee {
+    /// Raw amount in the smallest unit of `unit`, as a decimal string.
+    pub amount: String,
+    /// `WEI` or `FRI` (STRK).
+    pub unit: String,
+}
+
+/// The outcome of a transaction, taken from its receipt.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExecutionDetails {
+    /// `SUCCEEDED` or `REVERTED`.
+    pub execution_status: String,
+    /// Finality status for chains that report one (Starknet), `None` otherwise.
+    pub finality_status: Option<String>,
+    pub actual_fee: Option<Fee>,
+    pub revert_reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AllowanceRisk {
+    /// Effectively infinite, e.g. `u256::MAX` or `u128::MAX`.
+    Unlimited,
+    /// Above the large allowance threshold of a known token.
+    Large,
+}
+
+/// An allowance granted by the watched wallet that is worth a security alert.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Approval {
+    pub token_address: String,
+    pub spender: String,
+    /// Raw amount in the token's smallest unit, as a decimal string.
+    pub amount: String,
+    pub risk: AllowanceRisk,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum InteractionKind {
+    Call,
+    Event,
+}
+
+/// How a watched contract was involved in a transaction.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ContractInteraction {
+    pub kind: InteractionKind,
+    /// The called function's selector, or the event's selector (its first key or topic).
+    pub selector: String,
+    /// The account that sent the transaction, when known.
+    pub caller: Option<String>,
+}
+
+/// One call made by the transaction, decoded for display.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DecodedCall {
+    pub contract_address: String,
+    pub selector: String,
+    /// Resolved from the well-known selectors or the contract's ABI.
+    pub function_name: Option<String>,
+    pub calldata: Vec<String>,
+    /// Human-readable form, e.g. "approve 100 USDC to 0x0123…cdef".
+    pub summary: String,
+}
+
+/// What an activity matched, part of its ledger and history key so two matches in one
+/// transaction, e.g. a sent transaction and a contract call, are kept apart.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum ActivityKind {
+    #[default]
+    Transaction,
+    Transfer,
+    Approval,
+    Interaction,
+}
+
+impl ActivityKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActivityKind::Transaction => "Transaction",
+            ActivityKind::Transfer => "Transfer",
+            ActivityKind::Approval => "Approval",
+            ActivityKind::Interaction => "Interaction",
+        }
+    }
+}
+
+/// A transaction in a scanned block that involves one of the registered wallets, or one of
+/// the watched contracts when `interaction` is set.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Activity {
+    pub network: Network,
+    #[serde(with = "serde_with::rust::display_fromstr")]
+    pub block_number: u128,
+    pub transaction_hash: String,
+    /// Position of the matched event in the transaction receipt, if the match came from one.
+    pub event_index: Option<u32>,
+    pub wallet_address: String,
+    pub direction: Direction,
+    pub transfer: Option<TokenTransfer>,
+    /// `None` when the receipt isn't known, as for transactions in a pending EVM block.
+    #[serde(default)]
+    pub execution: Option<ExecutionDetails>,
+    /// Calls made by the wallet, when it sent the transaction and the chain's calldata can be decoded.
+    #[serde(default)]
+    pub calls: Vec<DecodedCall>,
+    /// A risky allowance granted in this transaction; makes the alert high priority.
+    #[serde(default)]
+    pub approval: Option<Approval>,
+    /// Set when `wallet_address` is a watched contract rather than a wallet.
+    #[serde(default)]
+    pub interaction: Option<ContractInteraction>,
+}
+
+impl Activity {
+    pub fn new(
+        network: Network,
+        block_number: u128,
+        transaction_hash: String,
+        wallet_address: String,
+        direction: Direction,
+    ) -> Self {
+        Activity {
+            network,
+            block_number,
+            transaction_hash,
+            event_index: None,
+            wallet_address,
+            direction,
+            transfer: None,
+            execution: None,
+            calls: Vec::new(),
+            approval: None,
+            interaction: None,
+        }
+    }
+
+    pub fn with_transfer(mut self, event_index: u32, transfer: TokenTransfer) -> Self {
+        self.event_index = Some(event_index);
+        self.transfer = Some(transfer);
+        self
+    }
+
+    /// An allowance matched from an `Approval` event at `event_index`.
+    pub fn with_approval_event(mut self, event_index: u32, approval: Approval) -> Self {
+        self.event_index = Some(event_index);
+        self.approval = Some(approval);
+        self
+    }
+
+    pub fn with_approval(mut self, approval: Approval) -> Self {
+        self.approval = Some(approval);
+        self
+    }
+
+    /// A call to, or event from, the watched contract `wallet_address`. Events keep their
+    /// index so each one is notified once.
+    pub fn with_interaction(
+        mut self,
+        event_index: Option<u32>,
+        interaction: ContractInteraction,
+    ) -> Self {
+        self.event_index = event_index;
+        self.interaction = Some(interaction);
+        self
+    }
+
+    pub fn with_execution(mut self, execution: ExecutionDetails) -> Self {
+        self.execution = Some(execution);
+        self
+    }
+
+    pub fn kind(&self) -> ActivityKind {
+        if self.interaction.is_some() {
+            ActivityKind::Interaction
+        } else if self.transfer.is_some() {
+            ActivityKind::Transfer
+        } else if self.approval.is_some() {
+            ActivityKind::Approval
+        } else {
+            ActivityKind::Transaction
+        }
+    }
+
+    pub fn is_reverted(&self) -> bool {
+        self.execution
+            .as_ref()
+            .is_some_and(|execution| execution.execution_status == "REVERTED")
+    }
+}
+
+/// An activity kept in the `activity` collection as the wallet's history.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ActivityRecord {
+    #[serde(flatten)]
+    pub activity: Activity,
+    #[serde(default)]
+    pub kind: ActivityKind,
+    /// Unix time in seconds of the block the activity was found in.
+    #[serde(default)]
+    pub block_timestamp: u64,
+    /// One line description for activity feeds.
+    #[serde(default)]
+    pub summary: String,
+    /// The receipt's execution status, `None` when it isn't known.
+    #[serde(default)]
+    pub status: Option<String>,
+    pub recorded_at: DateTime,
+    /// Found by scanning past blocks when the wallet was added, rather than live.
+    #[serde(default)]
+    pub backfilled: bool,
+}
+
+impl ActivityRecord {
+    pub fn new(activity: Activity, block_timestamp: u64, backfilled: bool) -> Self {
+        ActivityRecord {
+            summary: summarize_activity(&activity),
+            status: activity
+                .execution
+                .as_ref()
+                .map(|execution| execution.execution_status.clone()),
+            kind: activity.kind(),
+            activity,
+            block_timestamp,
+            recorded_at: DateTime::now(),
+            backfilled,
+        }
+    }
+}
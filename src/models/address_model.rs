@@ -0,0 +1,245 @@
+use super::network_model::Network;
+use alloy::primitives::{Address, U256};
+use std::fmt;
+use std::str::FromStr;
+
+/// A Starknet contract address, kept as `0x` followed by 64 lowercase hex digits.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StarknetAddress(String);
+
+impl StarknetAddress {
+    /// Accepts any felt below 2^251 written in hex, with or without leading zeros.
+    pub fn parse(address: &str) -> Result<Self, String> {
+        let digits = hex_digits(address)
+            .filter(|digits| digits.len() <= 64)
+            .ok_or(format!("Invalid Starknet address: {}", address))?;
+        let value = U256::from_str_radix(digits, 16)
+            .map_err(|_| format!("Invalid Starknet address: {}", address))?;
+        if value >= U256::from(1) << 251 {
+            return Err(format!("Starknet address out of range: {}", address));
+        }
+        Ok(StarknetAddress(format!("0x{:0>64}", digits.to_lowercase())))
+    }
+
+    /// The canonical form of an address read from the chain, or the address lowercased when
+    /// it isn't a valid one. Never panics on malformed RPC data.
+    pub fn normalize(address: &str) -> String {
+        match StarknetAddress::parse(address) {
+            Ok(address) => address.0,
+            Err(_) => address.to_lowercase(),
+        }
+    }
+}
+
+impl fmt::Display for StarknetAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A 20 byte EVM address. Displays with its EIP-55 checksum.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EvmAddress(Address);
+
+impl EvmAddress {
+    /// Accepts all lowercase or all uppercase hex, or mixed case carrying a valid EIP-55
+    /// checksum.
+    pub fn parse(address: &str) -> Result<Self, String> {
+        let digits = hex_digits(address)
+            .filter(|digits| digits.len() == 40)
+            .ok_or(format!("Invalid EVM address: {}", address))?;
+        let parsed =
+            Address::from_str(digits).map_err(|_| format!("Invalid EVM address: {}", address))?;
+
+        let mixed_case = digits.chars().any(|c| c.is_ascii_uppercase())
+            && digits.chars().any(|c| c.is_ascii_lowercase());
+        if mixed_case && parsed.to_checksum(None)[2..] != *digits {
+            return Err(format!("Invalid EIP-55 checksum: {}", address));
+        }
+        Ok(EvmAddress(parsed))
+    }
+}
+
+impl fmt::Display for EvmAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0.to_checksum(None))
+    }
+}
+
+fn hex_digits(address: &str) -> Option<&str> {
+    let digits = address
+        .strip_prefix("0x")
+        .or_else(|| address.strip_prefix("0X"))?;
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(digits)
+}
+
+/// Validates `address` for `network` and returns the form it is stored and shown in:
+/// zero padded lowercase on Starknet, EIP-55 checksummed on EVM chains.
+pub fn normalize_address(network: &Network, address: &str) -> Result<String, String> {
+    match network {
+        Network::Starknet => StarknetAddress::parse(address).map(|address| address.to_string()),
+        Network::Ethereum | Network::Base | Network::Optimism => {
+            EvmAddress::parse(address).map(|address| address.to_string())
+        }
+    }
+}
+
+/// Normalizes an address whose network isn't known: 40 hex digits are read as an EVM
+/// address, anything else as a Starknet one.
+pub fn normalize_any_address(address: &str) -> Result<String, String> {
+    match hex_digits(address) {
+        Some(digits) if digits.len() == 40 => {
+            EvmAddress::parse(address).map(|address| address.to_string())
+        }
+        _ => StarknetAddress::parse(address).map(|address| address.to_string()),
+    }
+}
+
+/// Whether two addresses are the same, ignoring case and leading zeros.
+pub fn same_address(a: &str, b: &str) -> bool {
+    match (hex_digits(a), hex_digits(b)) {
+        (Some(a), Some(b)) => a
+            .trim_start_matches('0')
+            .eq_ignore_ascii_case(b.trim_start_matches('0')),
+        _ => a.eq_ignore_ascii_case(b),
+    }
+}
+
+/// An address as shown in mails: normalized for its network when valid, shortened to
+/// `0x0123.....cdef`.
+pub fn display_address(network: &Network, address: &str) -> String {
+    let address = normalize_address(network, address).unwrap_or(address.to_string());
+    match (
+        address.get(..7),
+        address.get(address.len().saturating_sub(7)..),
+    ) {
+        (Some(start), Some(end)) if address.len() > 14 => format!("{}.....{}", start, end),
+        _ => address,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STARKNET: &str = "0x07b649b20453b7efd8168056287540fbae24da819348689a7592e2ea55d0680d";
+    const EVM_CHECKSUMMED: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+    #[test]
+    fn starknet_addresses_are_padded_and_lowercased() {
+        let short = "0x7B649B20453B7EFD8168056287540FBAE24DA819348689A7592E2EA55D0680D";
+        assert_eq!(StarknetAddress::parse(short).unwrap().to_string(), STARKNET);
+        assert_eq!(
+            StarknetAddress::parse("0x1").unwrap().to_string(),
+            format!("0x{:0>64}", "1")
+        );
+    }
+
+    #[test]
+    fn starknet_addresses_must_be_felts() {
+        assert!(StarknetAddress::parse("7b649b20").is_err());
+        assert!(StarknetAddress::parse("0x").is_err());
+        assert!(StarknetAddress::parse("0xg1").is_err());
+        assert!(StarknetAddress::parse(&format!("0x{}", "1".repeat(65))).is_err());
+        // 2^251 is the first value outside the address range.
+        assert!(StarknetAddress::parse(&format!("0x8{}", "0".repeat(62))).is_err());
+        assert!(StarknetAddress::parse(&format!("0x7{}", "f".repeat(62))).is_ok());
+    }
+
+    #[test]
+    fn normalize_keeps_malformed_chain_data() {
+        assert_eq!(
+            StarknetAddress::normalize("0x7B649B20"),
+            format!("0x{:0>64}", "7b649b20")
+        );
+        assert_eq!(
+            StarknetAddress::normalize("NOT_AN_ADDRESS"),
+            "not_an_address"
+        );
+    }
+
+    #[test]
+    fn evm_addresses_are_checksummed() {
+        let lowercase = EVM_CHECKSUMMED.to_lowercase();
+        assert_eq!(
+            EvmAddress::parse(&lowercase).unwrap().to_string(),
+            EVM_CHECKSUMMED
+        );
+        assert_eq!(
+            EvmAddress::parse(EVM_CHECKSUMMED).unwrap().to_string(),
+            EVM_CHECKSUMMED
+        );
+        let uppercase = format!("0x{}", &EVM_CHECKSUMMED[2..].to_uppercase());
+        assert_eq!(
+            EvmAddress::parse(&uppercase).unwrap().to_string(),
+            EVM_CHECKSUMMED
+        );
+    }
+
+    #[test]
+    fn evm_addresses_reject_bad_checksums_and_lengths() {
+        let bad_checksum = EVM_CHECKSUMMED.replacen("aA", "Aa", 1);
+        assert!(EvmAddress::parse(&bad_checksum).is_err());
+        assert!(EvmAddress::parse(&EVM_CHECKSUMMED[..41]).is_err());
+        assert!(EvmAddress::parse(&EVM_CHECKSUMMED[2..]).is_err());
+    }
+
+    #[test]
+    fn normalize_address_follows_the_network() {
+        let lowercase = EVM_CHECKSUMMED.to_lowercase();
+        assert_eq!(
+            normalize_address(&Network::Base, &lowercase).unwrap(),
+            EVM_CHECKSUMMED
+        );
+        assert!(normalize_address(&Network::Ethereum, STARKNET).is_err());
+        // A 40 digit felt is still a valid Starknet address.
+        assert_eq!(
+            normalize_address(&Network::Starknet, &lowercase).unwrap(),
+            format!("0x{:0>64}", &lowercase[2..])
+        );
+    }
+
+    #[test]
+    fn normalize_any_address_guesses_from_the_length() {
+        assert_eq!(
+            normalize_any_address(&EVM_CHECKSUMMED.to_lowercase()).unwrap(),
+            EVM_CHECKSUMMED
+        );
+        assert_eq!(
+            normalize_any_address(&STARKNET.replacen("0x0", "0x", 1)).unwrap(),
+            STARKNET
+        );
+        assert!(normalize_any_address("wallet").is_err());
+    }
+
+    #[test]
+    fn same_address_ignores_case_and_leading_zeros() {
+        assert!(same_address(
+            STARKNET,
+            &STARKNET.replacen("0x0", "0X", 1).to_uppercase()
+        ));
+        assert!(same_address(
+            EVM_CHECKSUMMED,
+            &EVM_CHECKSUMMED.to_lowercase()
+        ));
+        assert!(same_address("0x00ab", "0xAB"));
+        assert!(!same_address("0xab", "0xabc"));
+        assert!(same_address("Label", "label"));
+    }
+
+    #[test]
+    fn display_address_shortens_normalized_addresses() {
+        assert_eq!(
+            display_address(&Network::Ethereum, &EVM_CHECKSUMMED.to_lowercase()),
+            "0x5aAeb.....f1BeAed"
+        );
+        assert_eq!(
+            display_address(&Network::Starknet, &STARKNET.replacen("0x0", "0x", 1)),
+            "0x07b64.....5d0680d"
+        );
+        assert_eq!(display_address(&Network::Starknet, "short"), "short");
+    }
+}
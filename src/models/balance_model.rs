@@ -0,0 +1,156 @@
+use super::address_model::normalize_address;
+use super::network_model::Network;
+use super::rule_model::parse_amount;
+use alloy::primitives::U256;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A threshold a balance just moved across.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BalanceCrossing {
+    FellBelow(U256),
+    RoseAbove(U256),
+}
+
+/// A wallet's balance of one token, checked periodically against the user's thresholds.
+/// Thresholds and balances are raw token units as decimal strings.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BalanceWatch {
+    pub watch_id: String,
+    pub wallet_address: String,
+    pub network: Network,
+    pub token_address: String,
+    /// Alert when the balance drops below this amount.
+    pub below: Option<String>,
+    /// Alert when the balance rises above this amount.
+    pub above: Option<String>,
+    /// Balance seen by the last check, `None` until the first one.
+    #[serde(default)]
+    pub last_balance: Option<String>,
+}
+
+impl BalanceWatch {
+    pub fn new(
+        wallet_address: String,
+        network: String,
+        token_address: String,
+        below: Option<String>,
+        above: Option<String>,
+    ) -> Result<Self, String> {
+        let network = Network::from_str(network)?;
+        let watch = BalanceWatch {
+            watch_id: Uuid::new_v4().to_string(),
+            wallet_address: normalize_address(&network, &wallet_address)?,
+            token_address: normalize_address(&network, &token_address)?,
+            network,
+            below,
+            above,
+            last_balance: None,
+        };
+
+        let below = watch.threshold(&watch.below)?;
+        let above = watch.threshold(&watch.above)?;
+        match (below, above) {
+            (None, None) => Err("A balance watch needs a below or above threshold".to_string()),
+            (Some(below), Some(above)) if below > above => {
+                Err("The below threshold can't be higher than the above threshold".to_string())
+            }
+            _ => Ok(watch),
+        }
+    }
+
+    fn threshold(&self, threshold: &Option<String>) -> Result<Option<U256>, String> {
+        match threshold {
+            Some(amount) => parse_amount(amount)
+                .map(Some)
+                .ok_or(format!("Invalid amount: {}", amount)),
+            None => Ok(None),
+        }
+    }
+
+    /// The threshold `balance` crossed since the last check. The first check only records
+    /// a baseline.
+    pub fn crossing(&self, balance: U256) -> Option<BalanceCrossing> {
+        let previous = parse_amount(self.last_balance.as_deref()?)?;
+        let below = self.below.as_deref().and_then(parse_amount);
+        let above = self.above.as_deref().and_then(parse_amount);
+
+        if let Some(below) = below {
+            if previous >= below && balance < below {
+                return Some(BalanceCrossing::FellBelow(below));
+            }
+        }
+        if let Some(above) = above {
+            if previous <= above && balance > above {
+                return Some(BalanceCrossing::RoseAbove(above));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watch(last_balance: Option<&str>) -> BalanceWatch {
+        BalanceWatch {
+            watch_id: "watch".to_string(),
+            wallet_address: "0x00000000000000000000000000000000000000aa".to_string(),
+            network: Network::Ethereum,
+            token_address: "0x00000000000000000000000000000000000000bb".to_string(),
+            below: Some("100".to_string()),
+            above: Some("1000".to_string()),
+            last_balance: last_balance.map(str::to_string),
+        }
+    }
+
+    fn crossing(last_balance: &str, balance: u64) -> Option<BalanceCrossing> {
+        watch(Some(last_balance)).crossing(U256::from(balance))
+    }
+
+    #[test]
+    fn falling_below_the_threshold_is_a_crossing() {
+        assert_eq!(
+            crossing("500", 99),
+            Some(BalanceCrossing::FellBelow(U256::from(100)))
+        );
+        // Staying below doesn't alert again.
+        assert_eq!(crossing("99", 50), None);
+    }
+
+    #[test]
+    fn rising_above_the_threshold_is_a_crossing() {
+        assert_eq!(
+            crossing("500", 1001),
+            Some(BalanceCrossing::RoseAbove(U256::from(1000)))
+        );
+        assert_eq!(crossing("1001", 2000), None);
+        // A jump over both thresholds reports the one it rose above.
+        assert_eq!(
+            crossing("50", 5000),
+            Some(BalanceCrossing::RoseAbove(U256::from(1000)))
+        );
+    }
+
+    #[test]
+    fn the_first_check_only_records_a_baseline() {
+        assert_eq!(watch(None).crossing(U256::from(0)), None);
+        assert_eq!(watch(None).crossing(U256::from(5000)), None);
+    }
+
+    #[test]
+    fn reaching_a_threshold_exactly_is_not_a_crossing() {
+        assert_eq!(crossing("500", 100), None);
+        assert_eq!(crossing("500", 1000), None);
+        // Leaving the threshold is.
+        assert_eq!(
+            crossing("100", 99),
+            Some(BalanceCrossing::FellBelow(U256::from(100)))
+        );
+        assert_eq!(
+            crossing("1000", 1001),
+            Some(BalanceCrossing::RoseAbove(U256::from(1000)))
+        );
+    }
+}
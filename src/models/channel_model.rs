@@ -0,0 +1,239 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+const REDACTED: &str = "****";
+
+/// Where a user receives notifications.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum NotificationChannel {
+    /// The user's account email.
+    Email,
+    /// Any HTTPS endpoint, sent the notification as JSON signed with `secret`.
+    Webhook {
+        url: String,
+        /// HMAC-SHA256 key for the `X-Kharon-Signature` header, generated when not given.
+        #[serde(default)]
+        secret: Option<String>,
+    },
+    Telegram {
+        bot_token: String,
+        chat_id: String,
+    },
+    /// A Discord incoming webhook.
+    Discord {
+        webhook_url: String,
+    },
+    /// A Slack incoming webhook.
+    Slack {
+        webhook_url: String,
+    },
+}
+
+impl NotificationChannel {
+    /// The channel's name, safe to log unlike its urls and tokens.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            NotificationChannel::Email => "Email",
+            NotificationChannel::Webhook { .. } => "Webhook",
+            NotificationChannel::Telegram { .. } => "Telegram",
+            NotificationChannel::Discord { .. } => "Discord",
+            NotificationChannel::Slack { .. } => "Slack",
+        }
+    }
+
+    /// Identifies the channel in delivery records without storing its urls or tokens.
+    pub fn id(&self) -> String {
+        let target = match self {
+            NotificationChannel::Email => return self.kind().to_string(),
+            NotificationChannel::Webhook { url, .. } => url.clone(),
+            NotificationChannel::Telegram { bot_token, chat_id } => {
+                format!("{bot_token}:{chat_id}")
+            }
+            NotificationChannel::Discord { webhook_url }
+            | NotificationChannel::Slack { webhook_url } => webhook_url.clone(),
+        };
+        let digest = hex::encode(Sha256::digest(target.as_bytes()));
+        format!("{}:{}", self.kind(), &digest[..16])
+    }
+
+    /// The channel with its webhook secret, bot token and the token in Discord and Slack
+    /// urls masked, for showing it back to the user.
+    pub fn redacted(&self) -> Self {
+        match self {
+            NotificationChannel::Email => NotificationChannel::Email,
+            NotificationChannel::Webhook { url, secret } => NotificationChannel::Webhook {
+                url: url.clone(),
+                secret: secret.as_deref().map(redact),
+            },
+            NotificationChannel::Telegram { bot_token, chat_id } => NotificationChannel::Telegram {
+                bot_token: redact(bot_token),
+                chat_id: chat_id.clone(),
+            },
+            NotificationChannel::Discord { webhook_url } => NotificationChannel::Discord {
+                webhook_url: redact_url(webhook_url),
+            },
+            NotificationChannel::Slack { webhook_url } => NotificationChannel::Slack {
+                webhook_url: redact_url(webhook_url),
+            },
+        }
+    }
+
+    /// Gives a webhook without a secret a random one.
+    pub fn with_secret(self) -> Self {
+        match self {
+            NotificationChannel::Webhook { url, secret: None } => NotificationChannel::Webhook {
+                url,
+                secret: Some(format!(
+                    "whsec_{}{}",
+                    Uuid::new_v4().simple(),
+                    Uuid::new_v4().simple()
+                )),
+            },
+            channel => channel,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            NotificationChannel::Email => Ok(()),
+            NotificationChannel::Webhook { url, .. } => validate_url(url),
+            NotificationChannel::Telegram { bot_token, chat_id } => {
+                if bot_token.is_empty() || chat_id.is_empty() {
+                    return Err("Telegram needs a bot_token and a chat_id".to_string());
+                }
+                Ok(())
+            }
+            NotificationChannel::Discord { webhook_url }
+            | NotificationChannel::Slack { webhook_url } => validate_url(webhook_url),
+        }
+    }
+}
+
+/// Keeps the last four characters of long secrets, enough to tell them apart.
+fn redact(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    if chars.len() < 12 {
+        return REDACTED.to_string();
+    }
+    let visible: String = chars[chars.len() - 4..].iter().collect();
+    format!("{REDACTED}{visible}")
+}
+
+// Discord and Slack put the webhook's token in the last path segment.
+fn redact_url(url: &str) -> String {
+    match url.rsplit_once('/') {
+        Some((base, token)) => format!("{base}/{}", redact(token)),
+        None => redact(url),
+    }
+}
+
+/// Endpoints must use HTTPS; plain HTTP is only accepted on the local machine.
+fn validate_url(url: &str) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|_| format!("Invalid url: {}", url))?;
+    let local = matches!(parsed.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"));
+    match parsed.scheme() {
+        "https" => Ok(()),
+        "http" if local => Ok(()),
+        _ => Err(format!("Url must use https: {}", url)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "whsec_0123456789abcdef";
+    const BOT_TOKEN: &str = "123456:ABC-DEF1234ghIkl-zyx57W2v1u123ew11";
+    const DISCORD: &str = "https://discord.com/api/webhooks/123/tokenpart1234";
+
+    fn webhook(secret: Option<&str>) -> NotificationChannel {
+        NotificationChannel::Webhook {
+            url: "https://example.com/hook".to_string(),
+            secret: secret.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn redacted_channels_hide_secrets_and_tokens() {
+        assert_eq!(webhook(Some(SECRET)).redacted(), webhook(Some("****cdef")));
+        assert_eq!(webhook(None).redacted(), webhook(None));
+
+        let telegram = NotificationChannel::Telegram {
+            bot_token: BOT_TOKEN.to_string(),
+            chat_id: "42".to_string(),
+        };
+        assert_eq!(
+            telegram.redacted(),
+            NotificationChannel::Telegram {
+                bot_token: "****ew11".to_string(),
+                chat_id: "42".to_string(),
+            }
+        );
+
+        let discord = NotificationChannel::Discord {
+            webhook_url: DISCORD.to_string(),
+        };
+        assert_eq!(
+            discord.redacted(),
+            NotificationChannel::Discord {
+                webhook_url: "https://discord.com/api/webhooks/123/****1234".to_string(),
+            }
+        );
+        assert!(discord.redacted().validate().is_ok());
+    }
+
+    #[test]
+    fn short_secrets_are_fully_masked() {
+        assert_eq!(webhook(Some("abc")).redacted(), webhook(Some("****")));
+    }
+
+    #[test]
+    fn ids_tell_channels_apart_without_revealing_them() {
+        let telegram = |chat_id: &str| NotificationChannel::Telegram {
+            bot_token: BOT_TOKEN.to_string(),
+            chat_id: chat_id.to_string(),
+        };
+        assert_eq!(NotificationChannel::Email.id(), "Email");
+        assert_eq!(telegram("1").id(), telegram("1").id());
+        assert_ne!(telegram("1").id(), telegram("2").id());
+        assert!(telegram("1").id().starts_with("Telegram:"));
+        assert!(!telegram("1").id().contains(BOT_TOKEN));
+        // Rotating a webhook's secret keeps its delivery records.
+        assert_eq!(webhook(Some(SECRET)).id(), webhook(None).id());
+    }
+
+    #[test]
+    fn with_secret_only_fills_missing_webhook_secrets() {
+        match webhook(None).with_secret() {
+            NotificationChannel::Webhook {
+                secret: Some(secret),
+                ..
+            } => assert!(secret.starts_with("whsec_") && secret.len() > 40),
+            channel => panic!("expected a webhook with a secret, got {:?}", channel),
+        }
+        assert_eq!(webhook(Some(SECRET)).with_secret(), webhook(Some(SECRET)));
+    }
+
+    #[test]
+    fn urls_must_use_https_outside_the_local_machine() {
+        let slack = |url: &str| NotificationChannel::Slack {
+            webhook_url: url.to_string(),
+        };
+        assert!(slack("https://hooks.slack.com/services/T/B/X")
+            .validate()
+            .is_ok());
+        assert!(slack("http://localhost:8080/hook").validate().is_ok());
+        assert!(slack("http://hooks.slack.com/services/T/B/X")
+            .validate()
+            .is_err());
+        assert!(slack("not a url").validate().is_err());
+
+        let telegram = NotificationChannel::Telegram {
+            bot_token: String::new(),
+            chat_id: "42".to_string(),
+        };
+        assert!(telegram.validate().is_err());
+    }
+}
@@ -0,0 +1,62 @@
+use super::activity_model::{ContractInteraction, InteractionKind};
+use super::address_model::normalize_address;
+use super::network_model::Network;
+use super::starknet_model::{felt_to_u256, starknet_keccak};
+use alloy::primitives::{keccak256, U256};
+use serde::{Deserialize, Serialize};
+
+/// A contract a user wants to hear about: any call to it and any event it emits.
+#[derive(Serialize, Clone, Deserialize, Debug, PartialEq)]
+pub struct WatchedContract {
+    pub contract_address: String,
+    pub network: Network,
+    /// Limits event notifications to these events. Each filter is a selector (`0x…`), a
+    /// Starknet event name, or an EVM event signature such as
+    /// `Transfer(address,address,uint256)`. Empty means every event.
+    #[serde(default)]
+    pub event_filters: Vec<String>,
+}
+
+impl WatchedContract {
+    pub fn new(
+        contract_address: String,
+        network: String,
+        event_filters: Vec<String>,
+    ) -> Result<Self, String> {
+        let network = Network::from_str(network)?;
+        let contract = WatchedContract {
+            contract_address: normalize_address(&network, &contract_address)?,
+            network,
+            event_filters,
+        };
+        for filter in contract.event_filters.iter() {
+            if contract.filter_selector(filter).is_none() {
+                return Err(format!("Invalid event filter: {}", filter));
+            }
+        }
+        Ok(contract)
+    }
+
+    fn filter_selector(&self, filter: &str) -> Option<U256> {
+        if filter.starts_with("0x") {
+            return felt_to_u256(filter);
+        }
+        match self.network {
+            Network::Starknet => Some(starknet_keccak(filter)),
+            Network::Ethereum | Network::Base | Network::Optimism => {
+                Some(U256::from_be_bytes(keccak256(filter.as_bytes()).0))
+            }
+        }
+    }
+
+    /// Calls always match; events must pass the event filters.
+    pub fn matches(&self, interaction: &ContractInteraction) -> bool {
+        if interaction.kind == InteractionKind::Call || self.event_filters.is_empty() {
+            return true;
+        }
+        let selector = felt_to_u256(&interaction.selector);
+        self.event_filters
+            .iter()
+            .any(|filter| selector.is_some() && self.filter_selector(filter) == selector)
+    }
+}
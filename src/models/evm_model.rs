@@ -0,0 +1,322 @@
+use super::activity_model::{ExecutionDetails, Fee};
+use alloy::primitives::U256;
+use serde::{Deserialize, Serialize};
+
+/// `keccak256("Approval(address,address,uint256)")`, topic 0 of every ERC-20 `Approval` log.
+pub const APPROVAL_TOPIC: &str =
+    "0x8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b925";
+
+/// A block as returned by `eth_getBlockByNumber` with full transactions. The `pending`
+/// block may have no number and has no hash.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EvmBlock {
+    pub number: Option<String>,
+    pub hash: Option<String>,
+    pub parent_hash: String,
+    #[serde(default)]
+    pub timestamp: Option<String>,
+    pub transactions: Vec<EvmTransaction>,
+    /// Filled from `eth_getBlockReceipts` after the block is fetched; empty for the pending block.
+    #[serde(default)]
+    pub receipts: Vec<EvmReceipt>,
+}
+
+impl EvmBlock {
+    /// The block number, 0 for a pending block that doesn't report one.
+    pub fn block_number(&self) -> u128 {
+        self.number
+            .as_deref()
+            .map(parse_hex_quantity)
+            .and_then(Result::ok)
+            .unwrap_or_default()
+    }
+
+    /// Unix time in seconds, 0 when the block doesn't report one.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+            .as_deref()
+            .map(parse_hex_quantity)
+            .and_then(Result::ok)
+            .unwrap_or_default() as u64
+    }
+
+    pub fn receipt(&self, transaction_hash: &str) -> Option<&EvmReceipt> {
+        self.receipts.iter().find(|receipt| {
+            receipt
+                .transaction_hash
+                .eq_ignore_ascii_case(transaction_hash)
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EvmTransaction {
+    pub hash: String,
+    pub from: String,
+    pub to: Option<String>,
+    #[serde(default)]
+    pub value: String,
+    #[serde(default)]
+    pub input: String,
+}
+
+impl EvmTransaction {
+    /// Addresses touched by this transaction, lowercased. Contract creations have no `to`.
+    pub fn participants(&self) -> Vec<String> {
+        let mut participants = vec![self.from.to_lowercase()];
+        if let Some(to) = &self.to {
+            let to = to.to_lowercase();
+            if !participants.contains(&to) {
+                participants.push(to);
+            }
+        }
+        participants
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EvmReceipt {
+    pub transaction_hash: String,
+    /// `0x1` on success, `0x0` when reverted. Missing on pre-Byzantium receipts.
+    pub status: Option<String>,
+    pub gas_used: String,
+    pub effective_gas_price: Option<String>,
+    #[serde(default)]
+    pub logs: Vec<EvmLog>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EvmLog {
+    pub address: String,
+    pub topics: Vec<String>,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvmApproval {
+    pub token_address: String,
+    pub owner: String,
+    pub spender: String,
+    pub amount: U256,
+}
+
+impl EvmLog {
+    /// Decodes the log as an ERC-20 `Approval`. ERC-721 approvals index the token id
+    /// instead of carrying an amount, and are skipped.
+    pub fn as_approval(&self) -> Option<EvmApproval> {
+        if self.topics.len() != 3 || !self.topics[0].eq_ignore_ascii_case(APPROVAL_TOPIC) {
+            return None;
+        }
+        let amount = U256::from_str_radix(self.data.trim_start_matches("0x"), 16).ok()?;
+        Some(EvmApproval {
+            token_address: self.address.to_lowercase(),
+            owner: topic_address(&self.topics[1])?,
+            spender: topic_address(&self.topics[2])?,
+            amount,
+        })
+    }
+}
+
+/// The address in an indexed topic, i.e. the low 20 bytes of the word, lowercased.
+fn topic_address(topic: &str) -> Option<String> {
+    let digits = topic.trim_start_matches("0x");
+    Some(format!(
+        "0x{}",
+        digits.get(digits.len().checked_sub(40)?..)?.to_lowercase()
+    ))
+}
+
+impl EvmReceipt {
+    /// EVM receipts carry no revert reason or finality status.
+    pub fn execution_details(&self) -> ExecutionDetails {
+        let execution_status = match self.status.as_deref() {
+            Some("0x0") => "REVERTED",
+            _ => "SUCCEEDED",
+        };
+        let actual_fee = self.effective_gas_price.as_deref().and_then(|gas_price| {
+            let gas_used = parse_hex_quantity(&self.gas_used).ok()?;
+            let gas_price = parse_hex_quantity(gas_price).ok()?;
+            Some(Fee {
+                amount: gas_used.checked_mul(gas_price)?.to_string(),
+                unit: "WEI".to_string(),
+            })
+        });
+
+        ExecutionDetails {
+            execution_status: execution_status.to_string(),
+            finality_status: None,
+            actual_fee,
+            revert_reason: None,
+        }
+    }
+}
+
+pub fn parse_hex_quantity(quantity: &str) -> Result<u128, String> {
+    let digits = quantity.trim_start_matches("0x");
+    u128::from_str_radix(digits, 16).map_err(|e| format!("Invalid hex quantity {quantity}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const OWNER: &str = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+    const SPENDER: &str = "0xfb6916095ca1df60bb79ce92ce3ea74c37c5d359";
+
+    fn topic(address: &str) -> String {
+        format!("0x{:0>64}", &address[2..])
+    }
+
+    fn approval_log(topics: Vec<String>, data: &str) -> EvmLog {
+        EvmLog {
+            address: "0xA0b86991c6218b36c1d19d4a2e9eb0ce3606eB48".to_string(),
+            topics,
+            data: data.to_string(),
+        }
+    }
+
+    fn receipt(status: Option<&str>, gas_price: Option<&str>) -> EvmReceipt {
+        EvmReceipt {
+            transaction_hash: "0xAB".to_string(),
+            status: status.map(str::to_string),
+            gas_used: "0x5208".to_string(),
+            effective_gas_price: gas_price.map(str::to_string),
+            logs: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn parse_hex_quantity_reads_rpc_quantities() {
+        assert_eq!(parse_hex_quantity("0x0"), Ok(0));
+        assert_eq!(parse_hex_quantity("0x1b4"), Ok(436));
+        assert!(parse_hex_quantity("0x").is_err());
+        assert!(parse_hex_quantity("0xzz").is_err());
+    }
+
+    #[test]
+    fn blocks_parse_from_rpc_json() {
+        let block: EvmBlock = serde_json::from_value(json!({
+            "number": "0x10",
+            "hash": "0x1",
+            "parentHash": "0x0",
+            "timestamp": "0x6553f100",
+            "transactions": [{"hash": "0xab", "from": OWNER, "to": null}],
+        }))
+        .unwrap();
+        assert_eq!(block.block_number(), 16);
+        assert_eq!(block.timestamp(), 1_700_000_000);
+        assert_eq!(block.transactions[0].value, "");
+        assert!(block.receipts.is_empty());
+
+        // The pending block has neither number nor hash.
+        let pending: EvmBlock = serde_json::from_value(json!({
+            "number": null,
+            "hash": null,
+            "parentHash": "0x0",
+            "transactions": [],
+        }))
+        .unwrap();
+        assert_eq!(pending.block_number(), 0);
+        assert_eq!(pending.timestamp(), 0);
+    }
+
+    #[test]
+    fn receipts_are_found_by_hash_in_any_case() {
+        let block = EvmBlock {
+            number: Some("0x1".to_string()),
+            hash: None,
+            parent_hash: "0x0".to_string(),
+            timestamp: None,
+            transactions: Vec::new(),
+            receipts: vec![receipt(Some("0x1"), None)],
+        };
+        assert!(block.receipt("0xab").is_some());
+        assert!(block.receipt("0xcd").is_none());
+    }
+
+    #[test]
+    fn participants_are_lowercased_and_distinct() {
+        let transaction = |to: Option<&str>| EvmTransaction {
+            hash: "0xab".to_string(),
+            from: OWNER.to_uppercase().replacen("0X", "0x", 1),
+            to: to.map(str::to_string),
+            value: "0x0".to_string(),
+            input: "0x".to_string(),
+        };
+        assert_eq!(
+            transaction(Some(SPENDER)).participants(),
+            vec![OWNER.to_string(), SPENDER.to_string()]
+        );
+        assert_eq!(transaction(Some(OWNER)).participants(), vec![OWNER]);
+        assert_eq!(transaction(None).participants(), vec![OWNER]);
+    }
+
+    #[test]
+    fn erc20_approval_logs_are_decoded() {
+        let log = approval_log(
+            vec![APPROVAL_TOPIC.to_string(), topic(OWNER), topic(SPENDER)],
+            "0x00000000000000000000000000000000000000000000000000000000000003e8",
+        );
+        assert_eq!(
+            log.as_approval(),
+            Some(EvmApproval {
+                token_address: "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48".to_string(),
+                owner: OWNER.to_string(),
+                spender: SPENDER.to_string(),
+                amount: U256::from(1000u64),
+            })
+        );
+    }
+
+    #[test]
+    fn other_logs_are_not_approvals() {
+        // ERC-721 approvals index the token id as a fourth topic.
+        let erc721 = approval_log(
+            vec![
+                APPROVAL_TOPIC.to_string(),
+                topic(OWNER),
+                topic(SPENDER),
+                topic("0x01"),
+            ],
+            "0x",
+        );
+        assert_eq!(erc721.as_approval(), None);
+
+        let transfer = approval_log(
+            vec![
+                "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef".to_string(),
+                topic(OWNER),
+                topic(SPENDER),
+            ],
+            "0x01",
+        );
+        assert_eq!(transfer.as_approval(), None);
+    }
+
+    #[test]
+    fn execution_details_come_from_the_receipt() {
+        let succeeded = receipt(Some("0x1"), Some("0x3b9aca00")).execution_details();
+        assert_eq!(succeeded.execution_status, "SUCCEEDED");
+        assert_eq!(
+            succeeded.actual_fee,
+            Some(Fee {
+                amount: "21000000000000".to_string(),
+                unit: "WEI".to_string(),
+            })
+        );
+
+        let reverted = receipt(Some("0x0"), None).execution_details();
+        assert_eq!(reverted.execution_status, "REVERTED");
+        assert_eq!(reverted.actual_fee, None);
+
+        // Pre-Byzantium receipts carry no status.
+        assert_eq!(
+            receipt(None, None).execution_details().execution_status,
+            "SUCCEEDED"
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum MailStatus {
+    /// Waiting for a worker, possibly until `next_attempt_at` after a failed attempt.
+    Queued,
+    /// Claimed by a worker.
+    Sending,
+    Sent,
+    /// Gave up after the maximum number of attempts.
+    Failed,
+}
+
+impl MailStatus {
+    pub fn from_str(status: String) -> Result<Self, String> {
+        let status = match status.to_lowercase().as_str() {
+            "queued" => MailStatus::Queued,
+            "sending" => MailStatus::Sending,
+            "sent" => MailStatus::Sent,
+            "failed" => MailStatus::Failed,
+            _ => {
+                return Err(
+                    "Invalid mail status, expected Queued, Sending, Sent or Failed".to_string(),
+                )
+            }
+        };
+        Ok(status)
+    }
+}
+
+/// An outbound mail in the `mail_queue` collection.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QueuedMail {
+    pub mail_id: String,
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+    /// Sent as the HTML alternative of `body` when set.
+    #[serde(default)]
+    pub html: Option<String>,
+    pub high_priority: bool,
+    pub status: MailStatus,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: DateTime,
+    /// When a worker last claimed the mail.
+    #[serde(default)]
+    pub claimed_at: Option<DateTime>,
+    pub created_at: DateTime,
+    pub sent_at: Option<DateTime>,
+}
+
+impl QueuedMail {
+    pub fn new(
+        to: String,
+        subject: String,
+        body: String,
+        html: Option<String>,
+        high_priority: bool,
+    ) -> Self {
+        let now = DateTime::now();
+        QueuedMail {
+            mail_id: Uuid::new_v4().to_string(),
+            to,
+            subject,
+            body,
+            html,
+            high_priority,
+            status: MailStatus::Queued,
+            attempts: 0,
+            last_error: None,
+            next_attempt_at: now,
+            claimed_at: None,
+            created_at: now,
+            sent_at: None,
+        }
+    }
+}
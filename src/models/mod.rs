@@ -1,3 +1,14 @@
+pub mod activity_model;
+pub mod address_model;
+pub mod balance_model;
+pub mod channel_model;
+pub mod contract_model;
+pub mod evm_model;
+pub mod mail_model;
 pub mod network_model;
+pub mod notification_model;
+pub mod rule_model;
+pub mod starknet_model;
 pub mod user_model;
 pub mod wallet_model;
+pub mod webhook_model;
@@ -1,5 +1,13 @@
 use mongodb::bson::doc;
 use serde::{Deserialize, Serialize};
+use std::env;
+
+// How many scanned block hashes are kept for reorg detection.
+const MAX_RECENT_BLOCKS: usize = 64;
+const MAX_BLOCK_RETRIES: u32 = 10;
+
+/// Starknet finality statuses from least to most final.
+pub const FINALITY_STATUSES: [&str; 3] = ["PENDING", "ACCEPTED_ON_L2", "ACCEPTED_ON_L1"];
 
 #[derive(Serialize, PartialEq, Clone, Deserialize, Debug)]
 pub struct NetworkManager {
@@ -7,9 +15,78 @@ pub struct NetworkManager {
     pub chain_id: String,
     #[serde(with = "serde_with::rust::display_fromstr")]
     pub last_scanned_block: u128,
+    #[serde(default)]
+    pub recent_blocks: Vec<ScannedBlock>,
+    /// Blocks to stay behind the chain tip before scanning.
+    #[serde(default)]
+    pub confirmation_depth: u64,
+    /// Minimum Starknet finality status (e.g. `ACCEPTED_ON_L1`) a block needs before scanning.
+    #[serde(default)]
+    pub required_finality: Option<String>,
+    /// Blocks fetched concurrently while catching up.
+    #[serde(default = "default_backfill_workers")]
+    pub backfill_workers: u32,
+    /// Blocks requested per JSON-RPC batch while catching up.
+    #[serde(default = "default_rpc_batch_size")]
+    pub rpc_batch_size: u32,
+    /// Retries for a block that fails to fetch before the scan stops.
+    #[serde(default = "default_block_retries")]
+    pub block_retries: u32,
+    /// RPC providers in order of preference. When empty the network's RPC env var is used.
+    #[serde(default)]
+    pub rpc_endpoints: Vec<RpcEndpoint>,
+    /// WebSocket endpoint to subscribe to new heads on. Polling is used when unset.
+    #[serde(default)]
+    pub ws_endpoint: Option<String>,
+    /// Alert on transactions in the pending block, before they are confirmed.
+    #[serde(default)]
+    pub pending_alerts: bool,
 }
 
 #[derive(Serialize, PartialEq, Clone, Deserialize, Debug)]
+pub struct RpcEndpoint {
+    pub url: String,
+    /// Maximum requests per second sent to this endpoint, 0 for no limit.
+    #[serde(default)]
+    pub requests_per_second: u32,
+    #[serde(default = "default_rpc_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl RpcEndpoint {
+    pub fn new(url: String) -> Self {
+        RpcEndpoint {
+            url,
+            requests_per_second: 0,
+            timeout_ms: default_rpc_timeout_ms(),
+        }
+    }
+}
+
+fn default_rpc_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_backfill_workers() -> u32 {
+    3
+}
+
+fn default_rpc_batch_size() -> u32 {
+    10
+}
+
+fn default_block_retries() -> u32 {
+    3
+}
+
+#[derive(Serialize, PartialEq, Clone, Deserialize, Debug)]
+pub struct ScannedBlock {
+    #[serde(with = "serde_with::rust::display_fromstr")]
+    pub block_number: u128,
+    pub block_hash: String,
+}
+
+#[derive(Serialize, PartialEq, Eq, Hash, Clone, Deserialize, Debug)]
 pub enum Network {
     Ethereum,
     Starknet,
@@ -37,6 +114,47 @@ impl Network {
         };
         Ok(network)
     }
+
+    pub fn rpc_env_key(&self) -> &'static str {
+        match self {
+            Network::Ethereum => "ETHEREUM_RPC",
+            Network::Starknet => "RPC",
+            Network::Base => "BASE_RPC",
+            Network::Optimism => "OPTIMISM_RPC",
+        }
+    }
+
+    /// The block explorer's name and base url.
+    fn default_explorer(&self) -> (&'static str, &'static str) {
+        match self {
+            Network::Ethereum => ("Etherscan", "https://etherscan.io"),
+            Network::Starknet => ("Starkscan", "https://sepolia.starkscan.co"),
+            Network::Base => ("Basescan", "https://basescan.org"),
+            Network::Optimism => ("Optimistic Etherscan", "https://optimistic.etherscan.io"),
+        }
+    }
+
+    /// Env vars overriding the explorer's name and base url, e.g. to point Starknet at
+    /// mainnet or at another explorer.
+    fn explorer_env_keys(&self) -> (&'static str, &'static str) {
+        match self {
+            Network::Ethereum => ("ETHEREUM_EXPLORER_NAME", "ETHEREUM_EXPLORER_URL"),
+            Network::Starknet => ("STARKNET_EXPLORER_NAME", "STARKNET_EXPLORER_URL"),
+            Network::Base => ("BASE_EXPLORER_NAME", "BASE_EXPLORER_URL"),
+            Network::Optimism => ("OPTIMISM_EXPLORER_NAME", "OPTIMISM_EXPLORER_URL"),
+        }
+    }
+
+    pub fn explorer_name(&self) -> String {
+        env::var(self.explorer_env_keys().0)
+            .unwrap_or_else(|_| self.default_explorer().0.to_string())
+    }
+
+    pub fn tx_explorer_url(&self, transaction_hash: &str) -> String {
+        let base_url = env::var(self.explorer_env_keys().1)
+            .unwrap_or_else(|_| self.default_explorer().1.to_string());
+        format!("{}/tx/{}", base_url.trim_end_matches('/'), transaction_hash)
+    }
 }
 
 impl NetworkManager {
@@ -45,21 +163,137 @@ impl NetworkManager {
             network_type,
             chain_id,
             last_scanned_block: 0,
+            recent_blocks: Vec::new(),
+            confirmation_depth: 0,
+            required_finality: None,
+            backfill_workers: default_backfill_workers(),
+            rpc_batch_size: default_rpc_batch_size(),
+            block_retries: default_block_retries(),
+            rpc_endpoints: Vec::new(),
+            ws_endpoint: None,
+            pending_alerts: false,
+        }
+    }
+
+    pub fn get_block_hash(&self, block_number: u128) -> Option<&str> {
+        self.recent_blocks
+            .iter()
+            .find(|block| block.block_number == block_number)
+            .map(|block| block.block_hash.as_str())
+    }
+
+    /// The newest block deep enough to scan, given the chain head `latest_block`.
+    pub fn confirmed_tip(&self, latest_block: u128) -> u128 {
+        latest_block.saturating_sub(self.confirmation_depth.into())
+    }
+
+    /// Whether `parent_hash` contradicts the hash recorded for the block before `block_number`.
+    pub fn is_reorg(&self, block_number: u128, parent_hash: &str) -> bool {
+        self.get_block_hash(block_number.saturating_sub(1))
+            .is_some_and(|recorded| recorded != parent_hash)
+    }
+
+    pub fn record_scanned_block(&mut self, block_number: u128, block_hash: String) {
+        self.recent_blocks
+            .retain(|block| block.block_number < block_number);
+        self.recent_blocks.push(ScannedBlock {
+            block_number,
+            block_hash,
+        });
+        if self.recent_blocks.len() > MAX_RECENT_BLOCKS {
+            self.recent_blocks
+                .drain(..self.recent_blocks.len() - MAX_RECENT_BLOCKS);
         }
+        self.last_scanned_block = block_number;
+    }
+
+    /// Forgets every block after `block_number` so they get scanned again.
+    pub fn rollback_to(&mut self, block_number: u128) {
+        self.recent_blocks
+            .retain(|block| block.block_number <= block_number);
+        self.last_scanned_block = block_number;
+    }
+
+    pub fn update_confirmation(
+        &mut self,
+        confirmation_depth: u64,
+        required_finality: Option<String>,
+    ) -> Result<String, String> {
+        if let Some(status) = &required_finality {
+            if !FINALITY_STATUSES.contains(&status.as_str()) {
+                return Err(format!("Invalid finality status: {}", status));
+            }
+        }
+        self.confirmation_depth = confirmation_depth;
+        self.required_finality = required_finality;
+        Ok("Confirmation settings updated successfully".to_string())
+    }
+
+    pub fn update_backfill(
+        &mut self,
+        backfill_workers: u32,
+        rpc_batch_size: u32,
+        block_retries: u32,
+    ) -> Result<String, String> {
+        if backfill_workers == 0 || rpc_batch_size == 0 {
+            return Err("backfill_workers and rpc_batch_size must be at least 1".to_string());
+        }
+        if block_retries > MAX_BLOCK_RETRIES {
+            return Err(format!(
+                "block_retries must be at most {}",
+                MAX_BLOCK_RETRIES
+            ));
+        }
+        self.backfill_workers = backfill_workers;
+        self.rpc_batch_size = rpc_batch_size;
+        self.block_retries = block_retries;
+        Ok("Backfill settings updated successfully".to_string())
+    }
+
+    pub fn update_rpc_endpoints(
+        &mut self,
+        rpc_endpoints: Vec<RpcEndpoint>,
+    ) -> Result<String, String> {
+        if rpc_endpoints
+            .iter()
+            .any(|endpoint| endpoint.timeout_ms == 0)
+        {
+            return Err("timeout_ms must be greater than 0".to_string());
+        }
+        self.rpc_endpoints = rpc_endpoints;
+        Ok("RPC endpoints updated successfully".to_string())
+    }
+
+    pub fn update_ws_endpoint(&mut self, ws_endpoint: Option<String>) -> Result<String, String> {
+        if let Some(url) = &ws_endpoint {
+            if !url.starts_with("ws://") && !url.starts_with("wss://") {
+                return Err("ws_endpoint must be a ws:// or wss:// url".to_string());
+            }
+        }
+        self.ws_endpoint = ws_endpoint;
+        Ok("WebSocket endpoint updated successfully".to_string())
+    }
+
+    pub fn update_pending_alerts(&mut self, pending_alerts: bool) {
+        self.pending_alerts = pending_alerts;
     }
 
+    #[allow(dead_code)]
     pub fn update_last_scanned_block(&mut self, block_number: u128) {
         self.last_scanned_block = block_number;
     }
 
+    #[allow(dead_code)]
     pub fn get_network_type(&self) -> Network {
         self.network_type.clone()
     }
 
+    #[allow(dead_code)]
     pub fn get_chain_id(&self) -> String {
         self.chain_id.to_string()
     }
 
+    #[allow(dead_code)]
     pub fn get_last_scanned_block(&self) -> u128 {
         self.last_scanned_block
     }
@@ -68,3 +302,71 @@ impl NetworkManager {
         self.chain_id = chain_id;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager_at(last_block: u128) -> NetworkManager {
+        let mut manager = NetworkManager::new(Network::Ethereum, "1".to_string());
+        for block_number in 1..=last_block {
+            manager.record_scanned_block(block_number, format!("0x{block_number:x}"));
+        }
+        manager
+    }
+
+    #[test]
+    fn only_the_most_recent_hashes_are_kept() {
+        let manager = manager_at(100);
+
+        assert_eq!(manager.recent_blocks.len(), MAX_RECENT_BLOCKS);
+        assert_eq!(manager.recent_blocks[0].block_number, 37);
+        assert_eq!(manager.get_block_hash(36), None);
+        assert_eq!(manager.get_block_hash(100), Some("0x64"));
+        assert_eq!(manager.last_scanned_block, 100);
+    }
+
+    #[test]
+    fn rescanning_a_block_replaces_its_successors() {
+        let mut manager = manager_at(10);
+        manager.record_scanned_block(8, "0xfork".to_string());
+
+        assert_eq!(manager.get_block_hash(8), Some("0xfork"));
+        assert_eq!(manager.get_block_hash(9), None);
+        assert_eq!(manager.last_scanned_block, 8);
+    }
+
+    #[test]
+    fn mismatched_parent_hashes_are_reorgs() {
+        let manager = manager_at(10);
+
+        assert!(!manager.is_reorg(11, "0xa"));
+        assert!(manager.is_reorg(11, "0xfork"));
+        // Nothing is known about blocks past the cursor or before the window.
+        assert!(!manager.is_reorg(13, "0xfork"));
+        assert!(!manager_at(100).is_reorg(30, "0xfork"));
+    }
+
+    #[test]
+    fn rollback_forgets_later_blocks() {
+        let mut manager = manager_at(10);
+        manager.rollback_to(7);
+
+        assert_eq!(manager.last_scanned_block, 7);
+        assert_eq!(manager.get_block_hash(7), Some("0x7"));
+        assert_eq!(manager.get_block_hash(8), None);
+        // The rescanned block is checked against the surviving parent.
+        assert!(manager.is_reorg(8, "0xfork"));
+    }
+
+    #[test]
+    fn the_tip_trails_the_head_by_the_confirmation_depth() {
+        let mut manager = manager_at(0);
+        assert_eq!(manager.confirmed_tip(100), 100);
+
+        manager.update_confirmation(12, None).unwrap();
+        assert_eq!(manager.confirmed_tip(100), 88);
+        assert_eq!(manager.confirmed_tip(12), 0);
+        assert_eq!(manager.confirmed_tip(5), 0);
+    }
+}
@@ -0,0 +1,351 @@
+use super::activity_model::{Activity, ActivityKind};
+use super::network_model::Network;
+use mongodb::bson::{doc, DateTime, Document};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum NotificationStatus {
+    Pending,
+    Sent,
+}
+
+/// How far the transaction behind a notification has got. A `PreConfirmed` alert is later
+/// moved to one of the other stages, and the user gets a follow-up for it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum NotificationStage {
+    PreConfirmed,
+    Confirmed,
+    Reverted,
+    Dropped,
+}
+
+/// Security alerts, such as risky token approvals, are sent as high priority.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum NotificationPriority {
+    #[default]
+    Normal,
+    High,
+}
+
+/// Attempts per channel before a notification stops being retried on it.
+pub const MAX_CHANNEL_ATTEMPTS: u32 = 5;
+const RETRY_BASE_SECS: i64 = 60;
+const RETRY_MAX_SECS: i64 = 3_600;
+
+/// How one of the user's channels fared with a notification.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChannelDelivery {
+    /// `NotificationChannel::id` of the channel.
+    pub channel: String,
+    pub delivered: bool,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+}
+
+fn default_stage() -> NotificationStage {
+    NotificationStage::Confirmed
+}
+
+/// One entry of the notification ledger. `(network, transaction_hash, user_uuid,
+/// wallet_address, kind, event_index)` is unique, so a rescanned block can never notify the
+/// same user twice.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub notification_id: String,
+    pub network: Network,
+    pub transaction_hash: String,
+    pub user_uuid: String,
+    pub event_index: Option<u32>,
+    pub wallet_address: String,
+    #[serde(default)]
+    pub kind: ActivityKind,
+    pub status: NotificationStatus,
+    #[serde(default = "default_stage")]
+    pub stage: NotificationStage,
+    #[serde(default)]
+    pub priority: NotificationPriority,
+    /// Set once a `PreConfirmed` alert has moved on, the pending delivery is then its follow-up.
+    #[serde(default)]
+    pub follow_up: bool,
+    /// The activity that was notified, kept so follow-ups can be written without rescanning.
+    #[serde(default)]
+    pub activity: Option<Activity>,
+    /// Outcome per channel, so a retry only goes to the channels that failed.
+    #[serde(default)]
+    pub deliveries: Vec<ChannelDelivery>,
+    /// When the failed channels are retried; unset once every channel has it or none has
+    /// attempts left.
+    #[serde(default)]
+    pub retry_at: Option<DateTime>,
+    pub created_at: DateTime,
+    pub sent_at: Option<DateTime>,
+}
+
+impl Notification {
+    pub fn new(activity: &Activity, user_uuid: String, stage: NotificationStage) -> Self {
+        Notification {
+            notification_id: Uuid::new_v4().to_string(),
+            network: activity.network.clone(),
+            transaction_hash: activity.transaction_hash.clone(),
+            user_uuid,
+            event_index: activity.event_index,
+            wallet_address: activity.wallet_address.clone(),
+            kind: activity.kind(),
+            status: NotificationStatus::Pending,
+            stage,
+            priority: if activity.approval.is_some() {
+                NotificationPriority::High
+            } else {
+                NotificationPriority::Normal
+            },
+            follow_up: false,
+            activity: Some(activity.clone()),
+            deliveries: Vec::new(),
+            retry_at: None,
+            created_at: DateTime::now(),
+            sent_at: None,
+        }
+    }
+
+    /// Keys of the ledger's unique index, the fields of `ledger_key`.
+    pub fn ledger_index() -> Document {
+        doc! {"network": 1, "transaction_hash": 1, "user_uuid": 1, "wallet_address": 1, "kind": 1, "event_index": 1}
+    }
+
+    /// The ledger's unique key, one entry per user and matched activity.
+    pub fn ledger_key(&self) -> Result<Document, String> {
+        Ok(doc! {
+            "network": self.network.as_str()?,
+            "transaction_hash": &self.transaction_hash,
+            "user_uuid": &self.user_uuid,
+            "wallet_address": &self.wallet_address,
+            "kind": self.kind.as_str(),
+            "event_index": self.event_index,
+        })
+    }
+
+    /// Whether the channel still needs this notification: not delivered yet and with
+    /// attempts left.
+    pub fn is_pending_for(&self, channel_id: &str) -> bool {
+        match self
+            .deliveries
+            .iter()
+            .find(|delivery| delivery.channel == channel_id)
+        {
+            Some(delivery) => !delivery.delivered && delivery.attempts < MAX_CHANNEL_ATTEMPTS,
+            None => true,
+        }
+    }
+
+    pub fn record_delivery(&mut self, channel_id: String, result: Result<(), String>) {
+        let index = match self
+            .deliveries
+            .iter()
+            .position(|delivery| delivery.channel == channel_id)
+        {
+            Some(index) => index,
+            None => {
+                self.deliveries.push(ChannelDelivery {
+                    channel: channel_id,
+                    delivered: false,
+                    attempts: 0,
+                    last_error: None,
+                });
+                self.deliveries.len() - 1
+            }
+        };
+        let delivery = &mut self.deliveries[index];
+        delivery.attempts += 1;
+        match result {
+            Ok(()) => {
+                delivery.delivered = true;
+                delivery.last_error = None;
+            }
+            Err(err) => delivery.last_error = Some(err),
+        }
+    }
+
+    /// Every channel it was sent to has it.
+    pub fn is_delivered(&self) -> bool {
+        !self.deliveries.is_empty() && self.deliveries.iter().all(|delivery| delivery.delivered)
+    }
+
+    /// When to retry the failed channels, backing off with their attempts, or `None` when
+    /// none is left to retry.
+    pub fn next_retry(&self) -> Option<DateTime> {
+        let attempts = self
+            .deliveries
+            .iter()
+            .filter(|delivery| !delivery.delivered && delivery.attempts < MAX_CHANNEL_ATTEMPTS)
+            .map(|delivery| delivery.attempts)
+            .max()?;
+        let delay = (RETRY_BASE_SECS << attempts.saturating_sub(1)).min(RETRY_MAX_SECS);
+        Some(DateTime::from_millis(
+            DateTime::now().timestamp_millis() + delay * 1000,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::activity_model::{
+        AllowanceRisk, Approval, ContractInteraction, Direction, InteractionKind, TokenTransfer,
+    };
+
+    const WALLET: &str = "0x07b649b20453b7efd8168056287540fbae24da819348689a7592e2ea55d0680d";
+    const TOKEN: &str = "0x053c91253bc9682c04929ca02ed00b3e423f6710d2ee7e0d5ebb06f3ecf368a8";
+    const OTHER: &str = "0x0213c67ed78bc280887234fe5ed5e77272465317978ae86c25a71531d9332a2d";
+
+    fn sent(wallet: &str) -> Activity {
+        Activity::new(
+            Network::Starknet,
+            7,
+            "0xabc".to_string(),
+            wallet.to_string(),
+            Direction::Outgoing,
+        )
+    }
+
+    fn transfer(event_index: u32) -> Activity {
+        sent(WALLET).with_transfer(
+            event_index,
+            TokenTransfer {
+                token_address: TOKEN.to_string(),
+                amount: "1".to_string(),
+                counterparty: OTHER.to_string(),
+            },
+        )
+    }
+
+    fn approval(event_index: u32) -> Activity {
+        sent(WALLET).with_approval_event(
+            event_index,
+            Approval {
+                token_address: TOKEN.to_string(),
+                spender: OTHER.to_string(),
+                amount: "1".to_string(),
+                risk: AllowanceRisk::Unlimited,
+            },
+        )
+    }
+
+    fn key(activity: &Activity, user: &str) -> Document {
+        Notification::new(activity, user.to_string(), NotificationStage::Confirmed)
+            .ledger_key()
+            .unwrap()
+    }
+
+    #[test]
+    fn the_ledger_index_covers_the_ledger_key() {
+        let key = key(&transfer(0), "user");
+        let index = Notification::ledger_index();
+        assert!(key.keys().eq(index.keys()));
+    }
+
+    #[test]
+    fn a_rescanned_activity_has_the_same_key() {
+        let first = Notification::new(
+            &transfer(3),
+            "user".to_string(),
+            NotificationStage::PreConfirmed,
+        );
+        let rescanned = Notification::new(
+            &transfer(3),
+            "user".to_string(),
+            NotificationStage::Confirmed,
+        );
+        assert_ne!(first.notification_id, rescanned.notification_id);
+        assert_eq!(first.ledger_key(), rescanned.ledger_key());
+    }
+
+    #[test]
+    fn each_user_and_wallet_has_its_own_entry() {
+        assert_ne!(key(&transfer(0), "user"), key(&transfer(0), "other user"));
+        assert_ne!(key(&sent(WALLET), "user"), key(&sent(OTHER), "user"));
+    }
+
+    #[test]
+    fn activities_of_one_transaction_are_kept_apart() {
+        // The same event index can't tell a transfer from an approval of one transaction.
+        assert_ne!(key(&transfer(0), "user"), key(&approval(0), "user"));
+        assert_ne!(key(&transfer(0), "user"), key(&transfer(1), "user"));
+        assert_ne!(key(&transfer(0), "user"), key(&sent(WALLET), "user"));
+
+        let interaction = sent(WALLET).with_interaction(
+            None,
+            ContractInteraction {
+                kind: InteractionKind::Call,
+                selector: "0x1".to_string(),
+                caller: None,
+            },
+        );
+        assert_ne!(key(&interaction, "user"), key(&sent(WALLET), "user"));
+    }
+
+    #[test]
+    fn approvals_are_high_priority() {
+        let notify = |activity: &Activity| {
+            Notification::new(activity, "user".to_string(), NotificationStage::Confirmed).priority
+        };
+        assert_eq!(notify(&approval(0)), NotificationPriority::High);
+        assert_eq!(notify(&transfer(0)), NotificationPriority::Normal);
+    }
+
+    #[test]
+    fn delivered_channels_are_not_sent_again() {
+        let mut notification = Notification::new(
+            &transfer(0),
+            "user".to_string(),
+            NotificationStage::Confirmed,
+        );
+        assert!(notification.is_pending_for("Email"));
+        assert!(!notification.is_delivered());
+
+        notification.record_delivery("Email".to_string(), Ok(()));
+        notification.record_delivery("Slack:1".to_string(), Err("timed out".to_string()));
+        assert!(!notification.is_pending_for("Email"));
+        assert!(notification.is_pending_for("Slack:1"));
+        assert!(!notification.is_delivered());
+        assert_eq!(
+            notification.deliveries[1].last_error.as_deref(),
+            Some("timed out")
+        );
+
+        notification.record_delivery("Slack:1".to_string(), Ok(()));
+        assert_eq!(notification.deliveries.len(), 2);
+        assert_eq!(notification.deliveries[1].attempts, 2);
+        assert_eq!(notification.deliveries[1].last_error, None);
+        assert!(notification.is_delivered());
+        assert_eq!(notification.next_retry(), None);
+    }
+
+    #[test]
+    fn failed_channels_are_retried_with_backoff_until_out_of_attempts() {
+        let mut notification = Notification::new(
+            &transfer(0),
+            "user".to_string(),
+            NotificationStage::Confirmed,
+        );
+        let delay = |notification: &Notification| {
+            notification
+                .next_retry()
+                .map(|at| at.timestamp_millis() - DateTime::now().timestamp_millis())
+        };
+
+        notification.record_delivery("Slack:1".to_string(), Err("down".to_string()));
+        let first = delay(&notification).unwrap();
+        notification.record_delivery("Slack:1".to_string(), Err("down".to_string()));
+        let second = delay(&notification).unwrap();
+        assert!(first <= RETRY_BASE_SECS * 1000 && first > 0);
+        assert!(second > first);
+
+        for _ in 2..MAX_CHANNEL_ATTEMPTS {
+            notification.record_delivery("Slack:1".to_string(), Err("down".to_string()));
+        }
+        assert!(!notification.is_pending_for("Slack:1"));
+        assert_eq!(delay(&notification), None);
+        assert!(!notification.is_delivered());
+    }
+}
@@ -0,0 +1,370 @@
+use super::activity_model::{Activity, Direction};
+use super::address_model::{normalize_any_address, same_address};
+use alloy::primitives::U256;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum OutcomeFilter {
+    Succeeded,
+    Failed,
+}
+
+/// One test a transaction must pass for a rule to match. Amounts and fees are raw units
+/// (wei, fri, or the token's smallest unit) as decimal strings.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum RuleCondition {
+    Direction {
+        direction: Direction,
+    },
+    /// A token transfer of at least `min_amount`, optionally of a single token.
+    TransferAbove {
+        token_address: Option<String>,
+        min_amount: String,
+    },
+    /// The transaction calls, transfers with, or is emitted by `contract_address`.
+    Contract {
+        contract_address: String,
+    },
+    FeeAbove {
+        min_fee: String,
+    },
+    Outcome {
+        outcome: OutcomeFilter,
+    },
+}
+
+impl RuleCondition {
+    /// Validates the condition and normalizes its addresses.
+    fn normalized(self) -> Result<Self, String> {
+        match self {
+            RuleCondition::TransferAbove {
+                token_address,
+                min_amount,
+            } => {
+                validate_amount(&min_amount)?;
+                Ok(RuleCondition::TransferAbove {
+                    token_address: token_address
+                        .as_deref()
+                        .map(normalize_any_address)
+                        .transpose()?,
+                    min_amount,
+                })
+            }
+            RuleCondition::Contract { contract_address } => Ok(RuleCondition::Contract {
+                contract_address: normalize_any_address(&contract_address)?,
+            }),
+            RuleCondition::FeeAbove { min_fee } => {
+                validate_amount(&min_fee)?;
+                Ok(RuleCondition::FeeAbove { min_fee })
+            }
+            condition => Ok(condition),
+        }
+    }
+
+    pub fn matches(&self, activity: &Activity) -> bool {
+        match self {
+            RuleCondition::Direction { direction } => &activity.direction == direction,
+            RuleCondition::TransferAbove {
+                token_address,
+                min_amount,
+            } => activity.transfer.as_ref().is_some_and(|transfer| {
+                let same_token = token_address
+                    .as_ref()
+                    .is_none_or(|token| same_address(token, &transfer.token_address));
+                same_token && parse_amount(&transfer.amount) >= parse_amount(min_amount)
+            }),
+            RuleCondition::Contract { contract_address } => {
+                let is_contract = |address: &str| same_address(address, contract_address);
+                let called = activity
+                    .calls
+                    .iter()
+                    .any(|call| is_contract(&call.contract_address));
+                let transferred = activity.transfer.as_ref().is_some_and(|transfer| {
+                    is_contract(&transfer.token_address) || is_contract(&transfer.counterparty)
+                });
+                let watched =
+                    activity.interaction.is_some() && is_contract(&activity.wallet_address);
+                called || transferred || watched
+            }
+            RuleCondition::FeeAbove { min_fee } => activity
+                .execution
+                .as_ref()
+                .and_then(|execution| execution.actual_fee.as_ref())
+                .is_some_and(|fee| parse_amount(&fee.amount) >= parse_amount(min_fee)),
+            RuleCondition::Outcome { outcome } => match outcome {
+                OutcomeFilter::Failed => activity.is_reverted(),
+                OutcomeFilter::Succeeded => activity.execution.is_some() && !activity.is_reverted(),
+            },
+        }
+    }
+}
+
+pub fn parse_amount(amount: &str) -> Option<U256> {
+    U256::from_str_radix(amount, 10).ok()
+}
+
+fn validate_amount(amount: &str) -> Result<(), String> {
+    parse_amount(amount)
+        .map(|_| ())
+        .ok_or(format!("Invalid amount: {}", amount))
+}
+
+/// A user defined filter on notifications. A rule matches when all its conditions do.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AlertRule {
+    pub rule_id: String,
+    pub name: String,
+    pub conditions: Vec<RuleCondition>,
+    pub enabled: bool,
+}
+
+impl AlertRule {
+    pub fn new(name: String, conditions: Vec<RuleCondition>) -> Result<Self, String> {
+        let mut rule = AlertRule {
+            rule_id: Uuid::new_v4().to_string(),
+            name: String::new(),
+            conditions: Vec::new(),
+            enabled: true,
+        };
+        rule.update(Some(name), Some(conditions), None)?;
+        Ok(rule)
+    }
+
+    pub fn update(
+        &mut self,
+        name: Option<String>,
+        conditions: Option<Vec<RuleCondition>>,
+        enabled: Option<bool>,
+    ) -> Result<String, String> {
+        let conditions = match conditions {
+            Some(conditions) if conditions.is_empty() => {
+                return Err("A rule needs at least one condition".to_string());
+            }
+            Some(conditions) => Some(
+                conditions
+                    .into_iter()
+                    .map(RuleCondition::normalized)
+                    .collect::<Result<Vec<RuleCondition>, String>>()?,
+            ),
+            None => None,
+        };
+
+        if let Some(name) = name {
+            self.name = name;
+        }
+        if let Some(conditions) = conditions {
+            self.conditions = conditions;
+        }
+        if let Some(enabled) = enabled {
+            self.enabled = enabled;
+        }
+        Ok("Rule updated successfully".to_string())
+    }
+
+    pub fn matches(&self, activity: &Activity) -> bool {
+        self.enabled
+            && self
+                .conditions
+                .iter()
+                .all(|condition| condition.matches(activity))
+    }
+}
+
+/// Users without enabled rules are notified of everything; otherwise at least one rule
+/// must match.
+pub fn rules_allow(rules: &[AlertRule], activity: &Activity) -> bool {
+    let mut enabled = rules.iter().filter(|rule| rule.enabled).peekable();
+    enabled.peek().is_none() || enabled.any(|rule| rule.matches(activity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::activity_model::{
+        ContractInteraction, DecodedCall, ExecutionDetails, Fee, InteractionKind, TokenTransfer,
+    };
+    use crate::models::network_model::Network;
+
+    const WALLET: &str = "0x07b649b20453b7efd8168056287540fbae24da819348689a7592e2ea55d0680d";
+    const TOKEN: &str = "0x04718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d";
+    const OTHER: &str = "0x0213c67ed78bc280887234fe5ed5e77272465317978ae86c25a71531d9332a2d";
+
+    fn transfer(amount: &str) -> Activity {
+        Activity::new(
+            Network::Starknet,
+            1,
+            "0x1".to_string(),
+            WALLET.to_string(),
+            Direction::Outgoing,
+        )
+        .with_transfer(
+            0,
+            TokenTransfer {
+                // Addresses read from the chain may lose their leading zeros.
+                token_address: TOKEN.replacen("0x0", "0x", 1),
+                amount: amount.to_string(),
+                counterparty: OTHER.to_string(),
+            },
+        )
+    }
+
+    fn executed(status: &str, fee: &str) -> Activity {
+        transfer("1").with_execution(ExecutionDetails {
+            execution_status: status.to_string(),
+            finality_status: None,
+            actual_fee: Some(Fee {
+                amount: fee.to_string(),
+                unit: "FRI".to_string(),
+            }),
+            revert_reason: None,
+        })
+    }
+
+    fn transfer_above(token_address: Option<&str>, min_amount: &str) -> RuleCondition {
+        RuleCondition::TransferAbove {
+            token_address: token_address.map(str::to_string),
+            min_amount: min_amount.to_string(),
+        }
+    }
+
+    fn rule(conditions: Vec<RuleCondition>) -> AlertRule {
+        AlertRule::new("rule".to_string(), conditions).unwrap()
+    }
+
+    #[test]
+    fn transfer_above_compares_amounts_inclusively() {
+        let rule = rule(vec![transfer_above(None, "100")]);
+        assert!(rule.matches(&transfer("100")));
+        assert!(rule.matches(&transfer("101")));
+        assert!(!rule.matches(&transfer("99")));
+    }
+
+    #[test]
+    fn transfer_above_matches_the_token_in_any_form() {
+        let uppercase = TOKEN.to_uppercase().replacen("0X", "0x", 1);
+        assert!(rule(vec![transfer_above(Some(&uppercase), "1")]).matches(&transfer("5")));
+        assert!(!rule(vec![transfer_above(Some(OTHER), "1")]).matches(&transfer("5")));
+    }
+
+    #[test]
+    fn transfer_above_needs_a_transfer() {
+        let activity = Activity::new(
+            Network::Starknet,
+            1,
+            "0x1".to_string(),
+            WALLET.to_string(),
+            Direction::Outgoing,
+        );
+        assert!(!rule(vec![transfer_above(None, "0")]).matches(&activity));
+    }
+
+    #[test]
+    fn contract_matches_calls_transfers_and_watched_contracts() {
+        let contract = |address: &str| {
+            rule(vec![RuleCondition::Contract {
+                contract_address: address.to_string(),
+            }])
+        };
+        assert!(contract(TOKEN).matches(&transfer("1")));
+        assert!(contract(OTHER).matches(&transfer("1")));
+
+        let mut called = Activity::new(
+            Network::Starknet,
+            1,
+            "0x1".to_string(),
+            WALLET.to_string(),
+            Direction::Outgoing,
+        );
+        assert!(!contract(OTHER).matches(&called));
+        called.calls.push(DecodedCall {
+            contract_address: OTHER.to_string(),
+            selector: "0x2".to_string(),
+            function_name: None,
+            calldata: Vec::new(),
+            summary: String::new(),
+        });
+        assert!(contract(OTHER).matches(&called));
+
+        let watched = Activity::new(
+            Network::Starknet,
+            1,
+            "0x1".to_string(),
+            OTHER.to_string(),
+            Direction::Incoming,
+        )
+        .with_interaction(
+            None,
+            ContractInteraction {
+                kind: InteractionKind::Call,
+                selector: "0x2".to_string(),
+                caller: None,
+            },
+        );
+        assert!(contract(OTHER).matches(&watched));
+    }
+
+    #[test]
+    fn fee_and_outcome_need_the_receipt() {
+        let fee_above = rule(vec![RuleCondition::FeeAbove {
+            min_fee: "1000".to_string(),
+        }]);
+        assert!(fee_above.matches(&executed("SUCCEEDED", "1000")));
+        assert!(!fee_above.matches(&executed("SUCCEEDED", "999")));
+        assert!(!fee_above.matches(&transfer("1")));
+
+        let outcome = |outcome: OutcomeFilter| rule(vec![RuleCondition::Outcome { outcome }]);
+        assert!(outcome(OutcomeFilter::Failed).matches(&executed("REVERTED", "1")));
+        assert!(!outcome(OutcomeFilter::Failed).matches(&executed("SUCCEEDED", "1")));
+        assert!(outcome(OutcomeFilter::Succeeded).matches(&executed("SUCCEEDED", "1")));
+        assert!(!outcome(OutcomeFilter::Succeeded).matches(&transfer("1")));
+    }
+
+    #[test]
+    fn a_rule_matches_only_when_all_its_conditions_do() {
+        let rule = rule(vec![
+            RuleCondition::Direction {
+                direction: Direction::Outgoing,
+            },
+            transfer_above(None, "100"),
+        ]);
+        assert!(rule.matches(&transfer("150")));
+        assert!(!rule.matches(&transfer("50")));
+    }
+
+    #[test]
+    fn new_rules_are_validated_and_normalized() {
+        assert!(AlertRule::new("empty".to_string(), Vec::new()).is_err());
+        assert!(AlertRule::new("amount".to_string(), vec![transfer_above(None, "1.5")]).is_err());
+        assert!(
+            AlertRule::new("token".to_string(), vec![transfer_above(Some("0xzz"), "1")]).is_err()
+        );
+
+        let rule = rule(vec![RuleCondition::Contract {
+            contract_address: "0x213C67ED78BC280887234FE5ED5E77272465317978AE86C25A71531D9332A2D"
+                .to_string(),
+        }]);
+        assert_eq!(
+            rule.conditions,
+            vec![RuleCondition::Contract {
+                contract_address: OTHER.to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn rules_allow_everything_until_one_is_enabled() {
+        let activity = transfer("50");
+        assert!(rules_allow(&[], &activity));
+
+        let mut large = rule(vec![transfer_above(None, "100")]);
+        assert!(!rules_allow(&[large.clone()], &activity));
+
+        large.update(None, None, Some(false)).unwrap();
+        assert!(rules_allow(&[large.clone()], &activity));
+
+        let small = rule(vec![transfer_above(None, "10")]);
+        assert!(rules_allow(&[large, small], &activity));
+    }
+}
@@ -0,0 +1,692 @@
+use super::activity_model::{ExecutionDetails, Fee};
+use alloy::primitives::{keccak256, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// `starknet_keccak("Transfer")`, the first key of every ERC-20 `Transfer` event.
+pub const TRANSFER_EVENT_SELECTOR: &str =
+    "0x99cd8bde557814842a3121e8ddfd433a539b8c9f14bf31ebf108d12e6196e9";
+
+/// `starknet_keccak("Approval")`, the first key of every ERC-20 `Approval` event.
+pub const APPROVAL_EVENT_SELECTOR: &str =
+    "0x134692b230b9e1ffa39098904722134159652b09c5bc41d88d6698779d228ff";
+
+/// A block as returned by `starknet_getBlockWithReceipts`. The `pending` block has no hash,
+/// number or status yet, so those default to empty, 0 and `PENDING`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StarknetBlock {
+    #[serde(default)]
+    pub block_hash: String,
+    #[serde(default)]
+    pub block_number: u64,
+    pub parent_hash: String,
+    #[serde(default = "pending_status")]
+    pub status: String,
+    pub timestamp: u64,
+    pub sequencer_address: String,
+    #[serde(default)]
+    pub starknet_version: String,
+    pub transactions: Vec<TransactionWithReceipt>,
+}
+
+fn pending_status() -> String {
+    "PENDING".to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransactionWithReceipt {
+    pub transaction: StarknetTransaction,
+    pub receipt: TransactionReceipt,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransactionReceipt {
+    pub transaction_hash: String,
+    pub actual_fee: FeePayment,
+    pub execution_status: String,
+    pub finality_status: String,
+    pub revert_reason: Option<String>,
+    #[serde(default)]
+    pub events: Vec<StarknetEvent>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FeePayment {
+    pub amount: String,
+    pub unit: String,
+}
+
+impl TransactionReceipt {
+    pub fn execution_details(&self) -> ExecutionDetails {
+        ExecutionDetails {
+            execution_status: self.execution_status.clone(),
+            finality_status: Some(self.finality_status.clone()),
+            actual_fee: felt_to_u256(&self.actual_fee.amount).map(|amount| Fee {
+                amount: amount.to_string(),
+                unit: self.actual_fee.unit.clone(),
+            }),
+            revert_reason: self.revert_reason.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StarknetEvent {
+    pub from_address: String,
+    pub keys: Vec<String>,
+    pub data: Vec<String>,
+}
+
+/// A decoded ERC-20 `Transfer` event. Addresses are kept as emitted (unpadded felts).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferEvent {
+    pub token_address: String,
+    pub from: String,
+    pub to: String,
+    pub amount: U256,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApprovalEvent {
+    pub token_address: String,
+    pub owner: String,
+    pub spender: String,
+    pub amount: U256,
+}
+
+/// Transactions as returned by the Starknet block endpoints, tagged by their `type` field.
+/// Types this crawler doesn't know about yet deserialize to `Unknown` instead of failing
+/// the whole block. Blocks with receipts carry the hash on the receipt, so
+/// `transaction_hash` may be empty here.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum StarknetTransaction {
+    #[serde(rename = "INVOKE")]
+    Invoke(InvokeTransaction),
+    #[serde(rename = "DECLARE")]
+    Declare(DeclareTransaction),
+    #[serde(rename = "DEPLOY_ACCOUNT")]
+    DeployAccount(DeployAccountTransaction),
+    #[serde(rename = "DEPLOY")]
+    Deploy(DeployTransaction),
+    #[serde(rename = "L1_HANDLER")]
+    L1Handler(L1HandlerTransaction),
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum InvokeTransaction {
+    V0(InvokeTransactionV0),
+    V1(InvokeTransactionV1),
+    V3(InvokeTransactionV3),
+    /// A shape none of the above match, such as a future version. Kept so the rest of the
+    /// block still parses; it has no decoded calls or sender.
+    Unknown(serde_json::Value),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InvokeTransactionV0 {
+    #[serde(default)]
+    pub transaction_hash: String,
+    pub version: String,
+    pub contract_address: String,
+    pub entry_point_selector: String,
+    pub calldata: Vec<String>,
+    pub max_fee: String,
+    pub signature: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InvokeTransactionV1 {
+    #[serde(default)]
+    pub transaction_hash: String,
+    pub version: String,
+    pub sender_address: String,
+    pub calldata: Vec<String>,
+    pub max_fee: String,
+    pub nonce: String,
+    pub signature: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InvokeTransactionV3 {
+    #[serde(default)]
+    pub transaction_hash: String,
+    pub version: String,
+    pub sender_address: String,
+    pub calldata: Vec<String>,
+    pub nonce: String,
+    pub signature: Vec<String>,
+    pub resource_bounds: serde_json::Value,
+    #[serde(default)]
+    pub tip: String,
+    #[serde(default)]
+    pub paymaster_data: Vec<String>,
+    #[serde(default)]
+    pub account_deployment_data: Vec<String>,
+    #[serde(default)]
+    pub nonce_data_availability_mode: String,
+    #[serde(default)]
+    pub fee_data_availability_mode: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeclareTransaction {
+    #[serde(default)]
+    pub transaction_hash: String,
+    pub version: String,
+    pub sender_address: String,
+    pub class_hash: String,
+    pub compiled_class_hash: Option<String>,
+    pub max_fee: Option<String>,
+    pub nonce: Option<String>,
+    #[serde(default)]
+    pub signature: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeployAccountTransaction {
+    #[serde(default)]
+    pub transaction_hash: String,
+    pub version: String,
+    pub class_hash: String,
+    pub contract_address_salt: String,
+    pub constructor_calldata: Vec<String>,
+    pub max_fee: Option<String>,
+    pub nonce: String,
+    #[serde(default)]
+    pub signature: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeployTransaction {
+    #[serde(default)]
+    pub transaction_hash: String,
+    pub version: String,
+    pub class_hash: String,
+    pub contract_address_salt: String,
+    pub constructor_calldata: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct L1HandlerTransaction {
+    #[serde(default)]
+    pub transaction_hash: String,
+    pub version: String,
+    pub nonce: String,
+    pub contract_address: String,
+    pub entry_point_selector: String,
+    pub calldata: Vec<String>,
+}
+
+/// A single contract call made by a transaction.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ContractCall {
+    pub to: String,
+    pub selector: String,
+    pub calldata: Vec<String>,
+}
+
+impl StarknetTransaction {
+    /// The contract calls the transaction makes. Account transactions are decoded from their
+    /// `__execute__` multicall; an INVOKE v0 is a single direct call.
+    pub fn calls(&self) -> Vec<ContractCall> {
+        match self {
+            StarknetTransaction::Invoke(InvokeTransaction::V0(tx)) => vec![ContractCall {
+                to: tx.contract_address.clone(),
+                selector: tx.entry_point_selector.clone(),
+                calldata: tx.calldata.clone(),
+            }],
+            StarknetTransaction::Invoke(InvokeTransaction::V1(tx)) => {
+                decode_multicall(&tx.calldata).unwrap_or_default()
+            }
+            StarknetTransaction::Invoke(InvokeTransaction::V3(tx)) => {
+                decode_multicall(&tx.calldata).unwrap_or_default()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// The account that initiated the transaction, when the transaction carries one.
+    /// INVOKE v0 predates account abstraction, so its target contract acts as the sender.
+    pub fn sender_address(&self) -> Option<&str> {
+        match self {
+            StarknetTransaction::Invoke(InvokeTransaction::V0(tx)) => Some(&tx.contract_address),
+            StarknetTransaction::Invoke(InvokeTransaction::V1(tx)) => Some(&tx.sender_address),
+            StarknetTransaction::Invoke(InvokeTransaction::V3(tx)) => Some(&tx.sender_address),
+            StarknetTransaction::Declare(tx) => Some(&tx.sender_address),
+            StarknetTransaction::Invoke(InvokeTransaction::Unknown(_))
+            | StarknetTransaction::DeployAccount(_)
+            | StarknetTransaction::Deploy(_)
+            | StarknetTransaction::L1Handler(_)
+            | StarknetTransaction::Unknown => None,
+        }
+    }
+}
+
+impl StarknetEvent {
+    /// Decodes the event as an ERC-20 `Transfer`.
+    pub fn as_transfer(&self) -> Option<TransferEvent> {
+        let (from, to, amount) = self.token_event(TRANSFER_EVENT_SELECTOR)?;
+        Some(TransferEvent {
+            token_address: self.from_address.clone(),
+            from,
+            to,
+            amount,
+        })
+    }
+
+    /// Decodes the event as an ERC-20 `Approval`.
+    pub fn as_approval(&self) -> Option<ApprovalEvent> {
+        let (owner, spender, amount) = self.token_event(APPROVAL_EVENT_SELECTOR)?;
+        Some(ApprovalEvent {
+            token_address: self.from_address.clone(),
+            owner,
+            spender,
+            amount,
+        })
+    }
+
+    /// Decodes an ERC-20 event with two addresses and a u256 amount, such as `Transfer`
+    /// and `Approval`. Cairo 0 tokens (ETH, STRK) put the addresses in `data`, Cairo 1
+    /// OpenZeppelin tokens mark them as keys.
+    fn token_event(&self, selector: &str) -> Option<(String, String, U256)> {
+        if felt_to_u256(self.keys.first()?)? != felt_to_u256(selector)? {
+            return None;
+        }
+
+        let (first, second, low, high) = match (self.keys.len(), self.data.len()) {
+            (1, 4) => (&self.data[0], &self.data[1], &self.data[2], &self.data[3]),
+            (3, 2) => (&self.keys[1], &self.keys[2], &self.data[0], &self.data[1]),
+            _ => return None,
+        };
+
+        Some((
+            first.clone(),
+            second.clone(),
+            felt_to_u256(low)? + (felt_to_u256(high)? << 128),
+        ))
+    }
+}
+
+pub fn felt_to_u256(felt: &str) -> Option<U256> {
+    U256::from_str_radix(felt.trim_start_matches("0x"), 16).ok()
+}
+
+fn felt_to_usize(felt: &str) -> Option<usize> {
+    felt_to_u256(felt)?.try_into().ok()
+}
+
+/// `starknet_keccak`: the low 250 bits of keccak256, used for selectors and event keys.
+pub fn starknet_keccak(name: &str) -> U256 {
+    let mask = (U256::from(1) << 250) - U256::from(1);
+    U256::from_be_bytes(keccak256(name.as_bytes()).0) & mask
+}
+
+/// Splits account `__execute__` calldata into its calls. Cairo 1 accounts inline each call's
+/// arguments, legacy Cairo 0 accounts give each call an (offset, length) into one argument
+/// array at the end. Returns `None` when the calldata fits neither layout.
+pub fn decode_multicall(calldata: &[String]) -> Option<Vec<ContractCall>> {
+    decode_inline_multicall(calldata).or_else(|| decode_legacy_multicall(calldata))
+}
+
+fn decode_inline_multicall(calldata: &[String]) -> Option<Vec<ContractCall>> {
+    let call_count = felt_to_usize(calldata.first()?)?;
+    let mut position = 1;
+    let mut calls = Vec::new();
+
+    for _ in 0..call_count {
+        let args_len = felt_to_usize(calldata.get(position + 2)?)?;
+        let args_end = (position + 3).checked_add(args_len)?;
+        calls.push(ContractCall {
+            to: calldata.get(position)?.clone(),
+            selector: calldata.get(position + 1)?.clone(),
+            calldata: calldata.get(position + 3..args_end)?.to_vec(),
+        });
+        position = args_end;
+    }
+
+    (position == calldata.len()).then_some(calls)
+}
+
+fn decode_legacy_multicall(calldata: &[String]) -> Option<Vec<ContractCall>> {
+    let call_count = felt_to_usize(calldata.first()?)?;
+    let header_end = call_count.checked_mul(4)?.checked_add(1)?;
+    let data_len = felt_to_usize(calldata.get(header_end)?)?;
+    let data = calldata.get(header_end + 1..)?;
+    if data.len() != data_len {
+        return None;
+    }
+
+    calldata[1..header_end]
+        .chunks(4)
+        .map(|call| {
+            let offset = felt_to_usize(&call[2])?;
+            let len = felt_to_usize(&call[3])?;
+            Some(ContractCall {
+                to: call[0].clone(),
+                selector: call[1].clone(),
+                calldata: data.get(offset..offset.checked_add(len)?)?.to_vec(),
+            })
+        })
+        .collect()
+}
+
+/// Maps the selector of every function in a class ABI to its name. Sierra classes return
+/// the ABI as a JSON string and nest functions in interfaces; legacy classes return a list.
+pub fn abi_function_names(abi: &serde_json::Value) -> HashMap<U256, String> {
+    let entries = match abi {
+        serde_json::Value::String(abi) => serde_json::from_str(abi).unwrap_or_default(),
+        abi => abi.clone(),
+    };
+
+    let mut functions = HashMap::new();
+    for entry in entries.as_array().into_iter().flatten() {
+        match entry["type"].as_str() {
+            Some("function") => {
+                if let Some(name) = entry["name"].as_str() {
+                    functions.insert(starknet_keccak(name), name.to_string());
+                }
+            }
+            Some("interface") => functions.extend(abi_function_names(&entry["items"])),
+            _ => {}
+        }
+    }
+    functions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    /// Block 265733 from `responce.json`, trimmed to one of its transactions and extended
+    /// with an INVOKE v3, L1_HANDLER, DEPLOY_ACCOUNT and DECLARE, with receipts, as
+    /// `starknet_getBlockWithReceipts` returns them.
+    const BLOCK_WITH_RECEIPTS: &str = include_str!("fixtures/starknet_block_with_receipts.json");
+    /// A `starknet_getBlockWithTxs` response for block 265733.
+    const BLOCK_WITH_TXS: &str = include_str!("../../responce.json");
+
+    const ETH: &str = "0x49d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7";
+    const TRANSFER: &str = "0x83afd3f4caedc6eebf44246fe54e38c95e3179a5ec9ea81740eca5b482d12e";
+
+    fn transaction(value: Value) -> StarknetTransaction {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn felts(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn blocks_with_receipts_parse() {
+        let block: StarknetBlock = serde_json::from_str(BLOCK_WITH_RECEIPTS).unwrap();
+        assert_eq!(block.block_number, 265733);
+        assert_eq!(block.status, "ACCEPTED_ON_L2");
+        assert_eq!(block.transactions.len(), 5);
+
+        let kinds: Vec<&str> = block
+            .transactions
+            .iter()
+            .map(|tx| match &tx.transaction {
+                StarknetTransaction::Invoke(InvokeTransaction::V1(_)) => "INVOKE v1",
+                StarknetTransaction::Invoke(InvokeTransaction::V3(_)) => "INVOKE v3",
+                StarknetTransaction::L1Handler(_) => "L1_HANDLER",
+                StarknetTransaction::DeployAccount(_) => "DEPLOY_ACCOUNT",
+                StarknetTransaction::Declare(_) => "DECLARE",
+                other => panic!("unexpected {other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "INVOKE v1",
+                "INVOKE v3",
+                "L1_HANDLER",
+                "DEPLOY_ACCOUNT",
+                "DECLARE"
+            ]
+        );
+
+        // The ETH transfer: a Cairo 0 event with both addresses in its data.
+        let sent = &block.transactions[0];
+        let transfer = sent.receipt.events[0].as_transfer().unwrap();
+        assert_eq!(transfer.token_address, ETH);
+        assert_eq!(
+            Some(transfer.from.as_str()),
+            sent.transaction.sender_address()
+        );
+        assert_eq!(transfer.amount, U256::from(0x2386ccc25e41e0u64));
+        assert_eq!(
+            sent.receipt.execution_details().actual_fee,
+            Some(Fee {
+                amount: 0x2c1e4b0fd6e0u64.to_string(),
+                unit: "WEI".to_string(),
+            })
+        );
+
+        // The reverted approve: only its STRK fee, a Cairo 1 event with keyed addresses.
+        let reverted = &block.transactions[1];
+        let calls = reverted.transaction.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(
+            felt_to_u256(&calls[0].selector),
+            Some(starknet_keccak("approve"))
+        );
+        let details = reverted.receipt.execution_details();
+        assert_eq!(details.execution_status, "REVERTED");
+        assert!(details.revert_reason.unwrap().contains("u256_sub Overflow"));
+        let fee = reverted.receipt.events[0].as_transfer().unwrap();
+        assert_eq!(fee.to, block.sequencer_address);
+        assert_eq!(fee.amount, U256::from(0x4d8a1f02c1b4u64));
+
+        let declare = &block.transactions[4].transaction;
+        assert_eq!(
+            declare.sender_address(),
+            reverted.transaction.sender_address()
+        );
+        assert_eq!(block.transactions[2].transaction.sender_address(), None);
+        assert_eq!(block.transactions[3].transaction.sender_address(), None);
+    }
+
+    #[test]
+    fn recorded_invoke_transactions_decode_their_multicalls() {
+        let block: Value = serde_json::from_str(BLOCK_WITH_TXS).unwrap();
+        let transactions: Vec<StarknetTransaction> =
+            serde_json::from_value(block["transactions"].clone()).unwrap();
+        assert_eq!(transactions.len(), 84);
+        for tx in transactions.iter() {
+            assert!(matches!(
+                tx,
+                StarknetTransaction::Invoke(InvokeTransaction::V1(_))
+            ));
+            assert!(tx.sender_address().is_some());
+            assert!(!tx.calls().is_empty(), "{tx:?}");
+        }
+
+        // The plain ETH transfer in the block.
+        let transfer = transactions
+            .iter()
+            .flat_map(|tx| tx.calls())
+            .find(|call| call.to == ETH && call.selector == TRANSFER)
+            .unwrap();
+        assert_eq!(transfer.calldata.len(), 3);
+    }
+
+    #[test]
+    fn invoke_versions_are_told_apart() {
+        let v0 = transaction(json!({
+            "type": "INVOKE",
+            "version": "0x0",
+            "transaction_hash": "0x1",
+            "contract_address": "0xabc",
+            "entry_point_selector": TRANSFER,
+            "calldata": ["0x2", "0x3"],
+            "max_fee": "0x10",
+            "signature": [],
+        }));
+        assert_eq!(v0.sender_address(), Some("0xabc"));
+        assert_eq!(
+            v0.calls(),
+            vec![ContractCall {
+                to: "0xabc".to_string(),
+                selector: TRANSFER.to_string(),
+                calldata: felts(&["0x2", "0x3"]),
+            }]
+        );
+
+        let v1 = transaction(json!({
+            "type": "INVOKE",
+            "version": "0x1",
+            "sender_address": "0xa11ce",
+            "calldata": ["0x1", ETH, TRANSFER, "0x3", "0xb0b", "0x5", "0x0"],
+            "max_fee": "0x10",
+            "nonce": "0x1",
+            "signature": [],
+        }));
+        assert!(matches!(
+            v1,
+            StarknetTransaction::Invoke(InvokeTransaction::V1(_))
+        ));
+
+        let v3 = transaction(json!({
+            "type": "INVOKE",
+            "version": "0x3",
+            "sender_address": "0xa11ce",
+            "calldata": ["0x0"],
+            "nonce": "0x1",
+            "signature": [],
+            "resource_bounds": {},
+        }));
+        assert!(matches!(
+            v3,
+            StarknetTransaction::Invoke(InvokeTransaction::V3(_))
+        ));
+        assert_eq!(v3.sender_address(), Some("0xa11ce"));
+        assert_eq!(v3.calls(), Vec::new());
+    }
+
+    #[test]
+    fn other_transaction_types_parse() {
+        let deploy = transaction(json!({
+            "type": "DEPLOY",
+            "version": "0x0",
+            "class_hash": "0x1",
+            "contract_address_salt": "0x2",
+            "constructor_calldata": [],
+        }));
+        assert!(matches!(deploy, StarknetTransaction::Deploy(_)));
+
+        let l1_handler = transaction(json!({
+            "type": "L1_HANDLER",
+            "version": "0x0",
+            "nonce": "0x1",
+            "contract_address": "0xabc",
+            "entry_point_selector": "0x2",
+            "calldata": ["0x3"],
+        }));
+        assert!(matches!(l1_handler, StarknetTransaction::L1Handler(_)));
+        // Messages from L1 have no account behind them and aren't decoded as calls.
+        assert_eq!(l1_handler.sender_address(), None);
+        assert_eq!(l1_handler.calls(), Vec::new());
+
+        let declare_v1 = transaction(json!({
+            "type": "DECLARE",
+            "version": "0x1",
+            "sender_address": "0xa11ce",
+            "class_hash": "0x1",
+            "max_fee": "0x10",
+            "nonce": "0x1",
+        }));
+        match declare_v1 {
+            StarknetTransaction::Declare(declare) => assert_eq!(declare.compiled_class_hash, None),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn unrecognised_transactions_fall_back_instead_of_failing_the_block() {
+        let unknown_type = transaction(json!({"type": "DEPLOY_V2", "version": "0x0"}));
+        assert!(matches!(unknown_type, StarknetTransaction::Unknown));
+        assert_eq!(unknown_type.sender_address(), None);
+
+        let future_invoke = json!({
+            "type": "INVOKE",
+            "version": "0x4",
+            "sender_address": "0xa11ce",
+            "proof": ["0x1"],
+        });
+        match transaction(future_invoke.clone()) {
+            StarknetTransaction::Invoke(InvokeTransaction::Unknown(value)) => {
+                assert_eq!(value["version"], future_invoke["version"]);
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        let future_invoke = transaction(future_invoke);
+        assert_eq!(future_invoke.sender_address(), None);
+        assert_eq!(future_invoke.calls(), Vec::new());
+    }
+
+    #[test]
+    fn inline_multicalls_carry_each_calls_arguments() {
+        let calldata = felts(&[
+            "0x2", ETH, TRANSFER, "0x3", "0xb0b", "0x5", "0x0", "0xc0de", "0x1", "0x0",
+        ]);
+        assert_eq!(
+            decode_multicall(&calldata),
+            Some(vec![
+                ContractCall {
+                    to: ETH.to_string(),
+                    selector: TRANSFER.to_string(),
+                    calldata: felts(&["0xb0b", "0x5", "0x0"]),
+                },
+                ContractCall {
+                    to: "0xc0de".to_string(),
+                    selector: "0x1".to_string(),
+                    calldata: Vec::new(),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn legacy_multicalls_index_one_argument_array() {
+        // Two calls as (to, selector, offset, length), then the shared arguments.
+        let calldata = felts(&[
+            "0x2", ETH, TRANSFER, "0x0", "0x3", "0xc0de", "0x1", "0x3", "0x1", "0x4", "0xb0b",
+            "0x5", "0x0", "0x7",
+        ]);
+        assert_eq!(
+            decode_multicall(&calldata),
+            Some(vec![
+                ContractCall {
+                    to: ETH.to_string(),
+                    selector: TRANSFER.to_string(),
+                    calldata: felts(&["0xb0b", "0x5", "0x0"]),
+                },
+                ContractCall {
+                    to: "0xc0de".to_string(),
+                    selector: "0x1".to_string(),
+                    calldata: felts(&["0x7"]),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn calldata_in_neither_layout_is_rejected() {
+        assert_eq!(decode_multicall(&[]), None);
+        // Claims a call whose arguments run past the end.
+        assert_eq!(
+            decode_multicall(&felts(&["0x1", ETH, TRANSFER, "0x5", "0x1"])),
+            None
+        );
+        // Legacy layout with an argument length that doesn't match.
+        assert_eq!(
+            decode_multicall(&felts(&["0x1", ETH, TRANSFER, "0x0", "0x1", "0x2", "0x7"])),
+            None
+        );
+    }
+}
@@ -1,15 +1,95 @@
+use super::activity_model::Activity;
+use super::address_model::{normalize_address, same_address};
+use super::balance_model::BalanceWatch;
+use super::channel_model::NotificationChannel;
+use super::contract_model::WatchedContract;
 use super::network_model::Network;
+use super::rule_model::{rules_allow, AlertRule, RuleCondition};
 use super::wallet_model::Wallet;
 use serde::{Deserialize, Serialize};
-use std::error::Error;
 use uuid::Uuid;
 
+/// Which transaction outcomes a user wants to be notified about.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum NotifyOn {
+    #[default]
+    All,
+    SuccessOnly,
+    FailureOnly,
+}
+
+impl NotifyOn {
+    pub fn from_str(notify_on: String) -> Result<Self, String> {
+        let notify_on = match notify_on.to_lowercase().as_str() {
+            "all" => NotifyOn::All,
+            "successonly" | "success_only" => NotifyOn::SuccessOnly,
+            "failureonly" | "failure_only" => NotifyOn::FailureOnly,
+            _ => {
+                return Err(
+                    "Invalid notify_on, expected All, SuccessOnly or FailureOnly".to_string(),
+                )
+            }
+        };
+        Ok(notify_on)
+    }
+
+    /// Activity whose outcome isn't known yet counts as a success.
+    pub fn allows(&self, activity: &Activity) -> bool {
+        match self {
+            NotifyOn::All => true,
+            NotifyOn::SuccessOnly => !activity.is_reverted(),
+            NotifyOn::FailureOnly => activity.is_reverted(),
+        }
+    }
+}
+
+/// Language notifications are written in. Templates fall back to English when a
+/// translation is missing.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum Language {
+    #[default]
+    English,
+    French,
+}
+
+impl Language {
+    pub fn from_str(language: String) -> Result<Self, String> {
+        let language = match language.to_lowercase().as_str() {
+            "english" | "en" => Language::English,
+            "french" | "fr" => Language::French,
+            _ => return Err("Invalid language, expected English or French".to_string()),
+        };
+        Ok(language)
+    }
+
+    /// Folder of the language's templates.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::French => "fr",
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct User {
     pub user_uuid: String,
     pub name: String,
     pub email: String,
     pub wallets: Vec<Wallet>,
+    #[serde(default)]
+    pub notify_on: NotifyOn,
+    #[serde(default)]
+    pub watched_contracts: Vec<WatchedContract>,
+    #[serde(default)]
+    pub rules: Vec<AlertRule>,
+    #[serde(default)]
+    pub balance_watches: Vec<BalanceWatch>,
+    /// Where notifications are delivered; email when empty.
+    #[serde(default)]
+    pub channels: Vec<NotificationChannel>,
+    #[serde(default)]
+    pub language: Language,
 }
 
 impl User {
@@ -19,30 +99,44 @@ impl User {
             name,
             email: email.to_lowercase(),
             wallets,
+            notify_on: NotifyOn::All,
+            watched_contracts: Vec::new(),
+            rules: Vec::new(),
+            balance_watches: Vec::new(),
+            channels: Vec::new(),
+            language: Language::English,
         })
     }
 
     pub fn add_wallet(&mut self, user_wallet: Wallet) -> Result<User, String> {
-        if self.wallets.contains(&user_wallet) {
+        if self.wallets.iter().any(|wallet| {
+            wallet.network == user_wallet.network
+                && same_address(&wallet.wallet_address, &user_wallet.wallet_address)
+        }) {
             return Err("Wallet already exists in the user's wallets".to_string());
         }
         self.wallets.push(user_wallet);
         Ok(self.clone())
     }
 
+    #[allow(dead_code)]
     pub fn get_wallet_by_address(&self, address: &str) -> Result<&Wallet, String> {
-        let wallet = self.wallets.iter().find(|w| w.wallet_address == address);
+        let wallet = self
+            .wallets
+            .iter()
+            .find(|w| same_address(&w.wallet_address, address));
         match wallet {
             Some(w) => Ok(w),
             None => Err("Could not find wallet with given address".to_string()),
         }
     }
 
-    pub fn remove_wallet(&mut self, address: &str) -> Result<String, String> {
+    /// Removes the wallet `address` on `network`; the same address on other networks is kept.
+    pub fn remove_wallet(&mut self, address: &str, network: &Network) -> Result<String, String> {
         let index = self
             .wallets
             .iter()
-            .position(|w| w.wallet_address == address);
+            .position(|w| w.network == *network && same_address(&w.wallet_address, address));
         match index {
             Some(i) => {
                 self.wallets.remove(i);
@@ -55,15 +149,24 @@ impl User {
     pub fn update_wallet_network(
         &mut self,
         address: &str,
+        network: &Network,
         new_network: Network,
     ) -> Result<String, String> {
+        if self.wallets.iter().any(|w| {
+            w.network == new_network
+                && w.network != *network
+                && same_address(&w.wallet_address, address)
+        }) {
+            return Err("Wallet already exists in the user's wallets".to_string());
+        }
         let user_wallet = self
             .wallets
             .iter_mut()
-            .find(|w| w.wallet_address == address);
+            .find(|w| w.network == *network && same_address(&w.wallet_address, address));
 
         match user_wallet {
             Some(wallet) => {
+                wallet.wallet_address = normalize_address(&new_network, &wallet.wallet_address)?;
                 wallet.network = new_network;
                 return Ok("Wallet network updated successfully".to_string());
             }
@@ -79,4 +182,233 @@ impl User {
         self.email = new_email;
         return Ok("Email updated successfully".to_string());
     }
+
+    pub fn update_notify_on(&mut self, notify_on: NotifyOn) -> Result<String, String> {
+        self.notify_on = notify_on;
+        Ok("Notification preference updated successfully".to_string())
+    }
+
+    pub fn update_language(&mut self, language: Language) -> Result<String, String> {
+        self.language = language;
+        Ok("Language updated successfully".to_string())
+    }
+
+    pub fn update_channels(
+        &mut self,
+        channels: Vec<NotificationChannel>,
+    ) -> Result<String, String> {
+        // Channels read back from `get_channels` have their secrets masked, keep the stored ones.
+        let channels: Vec<NotificationChannel> = channels
+            .into_iter()
+            .map(|channel| {
+                self.channels
+                    .iter()
+                    .find(|existing| existing.redacted() == channel)
+                    .cloned()
+                    .unwrap_or(channel)
+            })
+            .collect();
+        for channel in channels.iter() {
+            channel.validate()?;
+        }
+        self.channels = channels
+            .into_iter()
+            .map(NotificationChannel::with_secret)
+            .collect();
+        Ok("Notification channels updated successfully".to_string())
+    }
+
+    /// The secret of the user's webhook at `url`, if it is still registered.
+    pub fn webhook_secret(&self, url: &str) -> Option<Option<String>> {
+        self.channels.iter().find_map(|channel| match channel {
+            NotificationChannel::Webhook {
+                url: webhook_url,
+                secret,
+            } if webhook_url == url => Some(secret.clone()),
+            _ => None,
+        })
+    }
+
+    pub fn notification_channels(&self) -> Vec<NotificationChannel> {
+        if self.channels.is_empty() {
+            return vec![NotificationChannel::Email];
+        }
+        self.channels.clone()
+    }
+
+    pub fn add_watched_contract(&mut self, contract: WatchedContract) -> Result<User, String> {
+        if self.watched_contracts.iter().any(|watched| {
+            same_address(&watched.contract_address, &contract.contract_address)
+                && watched.network == contract.network
+        }) {
+            return Err("Contract is already watched by the user".to_string());
+        }
+        self.watched_contracts.push(contract);
+        Ok(self.clone())
+    }
+
+    pub fn remove_watched_contract(&mut self, address: &str) -> Result<String, String> {
+        let index = self
+            .watched_contracts
+            .iter()
+            .position(|watched| same_address(&watched.contract_address, address));
+        match index {
+            Some(i) => {
+                self.watched_contracts.remove(i);
+                Ok("Contract removed successfully".to_string())
+            }
+            None => Err("Could not find watched contract with given address".to_string()),
+        }
+    }
+
+    /// The user's watch on `address`, ignoring case and leading zeros.
+    pub fn watched_contract(&self, network: &Network, address: &str) -> Option<&WatchedContract> {
+        self.watched_contracts.iter().find(|watched| {
+            &watched.network == network && same_address(&watched.contract_address, address)
+        })
+    }
+
+    /// Security alerts always go out; anything else must pass the user's outcome
+    /// preference and alert rules.
+    pub fn wants_notification(&self, activity: &Activity) -> bool {
+        activity.approval.is_some()
+            || (self.notify_on.allows(activity) && rules_allow(&self.rules, activity))
+    }
+
+    pub fn add_rule(&mut self, rule: AlertRule) -> Result<User, String> {
+        self.rules.push(rule);
+        Ok(self.clone())
+    }
+
+    pub fn update_rule(
+        &mut self,
+        rule_id: &str,
+        name: Option<String>,
+        conditions: Option<Vec<RuleCondition>>,
+        enabled: Option<bool>,
+    ) -> Result<String, String> {
+        match self.rules.iter_mut().find(|rule| rule.rule_id == rule_id) {
+            Some(rule) => rule.update(name, conditions, enabled),
+            None => Err("Could not find rule with given id".to_string()),
+        }
+    }
+
+    pub fn remove_rule(&mut self, rule_id: &str) -> Result<String, String> {
+        let index = self.rules.iter().position(|rule| rule.rule_id == rule_id);
+        match index {
+            Some(i) => {
+                self.rules.remove(i);
+                Ok("Rule removed successfully".to_string())
+            }
+            None => Err("Could not find rule with given id".to_string()),
+        }
+    }
+
+    pub fn add_balance_watch(&mut self, watch: BalanceWatch) -> Result<User, String> {
+        if self.balance_watches.iter().any(|existing| {
+            existing.network == watch.network
+                && same_address(&existing.wallet_address, &watch.wallet_address)
+                && same_address(&existing.token_address, &watch.token_address)
+        }) {
+            return Err("This wallet's balance of the token is already watched".to_string());
+        }
+        self.balance_watches.push(watch);
+        Ok(self.clone())
+    }
+
+    pub fn remove_balance_watch(&mut self, watch_id: &str) -> Result<String, String> {
+        let index = self
+            .balance_watches
+            .iter()
+            .position(|watch| watch.watch_id == watch_id);
+        match index {
+            Some(i) => {
+                self.balance_watches.remove(i);
+                Ok("Balance watch removed successfully".to_string())
+            }
+            None => Err("Could not find balance watch with given id".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EVM_WALLET: &str = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+
+    fn user_on(networks: &[&str]) -> User {
+        let mut user =
+            User::new("Ada".to_string(), "ada@example.com".to_string(), Vec::new()).unwrap();
+        for network in networks {
+            let wallet = Wallet::new(EVM_WALLET.to_string(), network.to_string()).unwrap();
+            user.add_wallet(wallet).unwrap();
+        }
+        user
+    }
+
+    fn networks(user: &User) -> Vec<Network> {
+        user.wallets
+            .iter()
+            .map(|wallet| wallet.network.clone())
+            .collect()
+    }
+
+    #[test]
+    fn removing_a_wallet_keeps_the_address_on_other_networks() {
+        let mut user = user_on(&["Ethereum", "Base"]);
+        user.remove_wallet(EVM_WALLET, &Network::Base).unwrap();
+        assert_eq!(networks(&user), [Network::Ethereum]);
+
+        let err = user.remove_wallet(EVM_WALLET, &Network::Base).unwrap_err();
+        assert_eq!(err, "Could not find wallet with given address");
+        assert_eq!(networks(&user), [Network::Ethereum]);
+    }
+
+    #[test]
+    fn moving_a_wallet_only_changes_the_one_on_its_network() {
+        let mut user = user_on(&["Ethereum", "Base"]);
+        user.update_wallet_network(EVM_WALLET, &Network::Base, Network::Optimism)
+            .unwrap();
+        assert_eq!(networks(&user), [Network::Ethereum, Network::Optimism]);
+
+        // Moving onto a network that already has the address would duplicate it.
+        let err = user
+            .update_wallet_network(EVM_WALLET, &Network::Optimism, Network::Ethereum)
+            .unwrap_err();
+        assert_eq!(err, "Wallet already exists in the user's wallets");
+        assert_eq!(networks(&user), [Network::Ethereum, Network::Optimism]);
+    }
+
+    #[test]
+    fn resubmitted_redacted_channels_keep_their_secrets() {
+        let mut user =
+            User::new("Ada".to_string(), "ada@example.com".to_string(), Vec::new()).unwrap();
+        let telegram = NotificationChannel::Telegram {
+            bot_token: "123456:ABC-DEF1234ghIkl-zyx57W2v1u123ew11".to_string(),
+            chat_id: "42".to_string(),
+        };
+        user.update_channels(vec![
+            NotificationChannel::Webhook {
+                url: "https://example.com/hook".to_string(),
+                secret: None,
+            },
+            telegram.clone(),
+        ])
+        .unwrap();
+        let stored = user.channels.clone();
+
+        let redacted = stored.iter().map(NotificationChannel::redacted).collect();
+        user.update_channels(redacted).unwrap();
+        assert_eq!(user.channels, stored);
+
+        // A new token replaces the stored one.
+        let rotated = NotificationChannel::Telegram {
+            bot_token: "654321:new-token".to_string(),
+            chat_id: "42".to_string(),
+        };
+        user.update_channels(vec![rotated.clone()]).unwrap();
+        assert_eq!(user.channels, vec![rotated]);
+        assert_ne!(user.channels[0], telegram);
+    }
 }
@@ -1,3 +1,4 @@
+use super::address_model::normalize_address;
 use super::network_model::Network;
 use serde::{Deserialize, Serialize};
 
@@ -8,11 +9,12 @@ pub struct Wallet {
 }
 
 impl Wallet {
+    /// Validates `wallet_address` for the network and stores it normalized.
     pub fn new(wallet_address: String, network: String) -> Result<Self, String> {
         let network = Network::from_str(network);
         if let Ok(user_network) = network {
             Ok(Wallet {
-                wallet_address,
+                wallet_address: normalize_address(&user_network, &wallet_address)?,
                 network: user_network,
             })
         } else {
@@ -20,6 +22,7 @@ impl Wallet {
         }
     }
 
+    #[allow(dead_code)]
     pub fn change_network(&mut self, network: String) -> Result<String, String> {
         let network = Network::from_str(network);
         if let Ok(new_network) = network {
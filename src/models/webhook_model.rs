@@ -0,0 +1,48 @@
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// One payload posted to a user's webhook. Pending deliveries wait in the webhook queue
+/// until `retry_at`; once every attempt has failed they move to the dead-letter collection
+/// until an admin replays them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WebhookDelivery {
+    pub delivery_id: String,
+    pub user_uuid: String,
+    pub url: String,
+    /// The JSON body exactly as signed and sent.
+    pub payload: String,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime,
+    pub failed_at: Option<DateTime>,
+    /// When a worker may next attempt the delivery.
+    #[serde(default = "DateTime::now")]
+    pub retry_at: DateTime,
+    /// When a worker last claimed the delivery.
+    #[serde(default)]
+    pub claimed_at: Option<DateTime>,
+}
+
+impl WebhookDelivery {
+    pub fn new(user_uuid: String, url: String, payload: String) -> Self {
+        WebhookDelivery {
+            delivery_id: Uuid::new_v4().to_string(),
+            user_uuid,
+            url,
+            payload,
+            attempts: 0,
+            last_error: None,
+            created_at: DateTime::now(),
+            failed_at: None,
+            retry_at: DateTime::now(),
+            claimed_at: None,
+        }
+    }
+
+    pub fn record_failure(&mut self, error: String) {
+        self.attempts += 1;
+        self.last_error = Some(error);
+        self.failed_at = Some(DateTime::now());
+    }
+}
@@ -1,20 +1,15 @@
-use crate::models::network_model::{Network, NetworkManager};
-use crate::models::user_model::User;
-use crate::models::wallet_model::Wallet;
+use crate::crawlers::supervisor::CrawlerSupervisor;
+use crate::models::activity_model::ActivityKind;
+use crate::models::mail_model::MailStatus;
+use crate::models::network_model::{Network, NetworkManager, RpcEndpoint};
+use crate::models::notification_model::NotificationStage;
+use crate::models::user_model::Language;
 use crate::services::db::Database;
+use crate::services::webhook::attempt_delivery;
 use crate::utils::api_response::ApiResponse;
+use crate::utils::mail_structure::preview_mail;
 
-use actix_web::{
-    delete,
-    error::ResponseError,
-    get,
-    http::{header::ContentType, StatusCode},
-    patch, post, put,
-    web::Data,
-    web::Json,
-    web::Path,
-    HttpResponse,
-};
+use actix_web::{delete, get, patch, post, web::Data, web::Json, web::Path};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -34,6 +29,63 @@ pub struct SubmitSetLastBlock {
     pub last_scanned_block: u128,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubmitSetConfirmation {
+    pub network_type: String,
+    pub confirmation_depth: u64,
+    pub required_finality: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubmitSetBackfill {
+    pub network_type: String,
+    pub backfill_workers: u32,
+    pub rpc_batch_size: u32,
+    pub block_retries: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubmitSetRpcEndpoints {
+    pub network_type: String,
+    pub rpc_endpoints: Vec<RpcEndpoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubmitSetWsEndpoint {
+    pub network_type: String,
+    pub ws_endpoint: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubmitSetPendingAlerts {
+    pub network_type: String,
+    pub pending_alerts: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubmitReplayWebhook {
+    pub delivery_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubmitPreviewMail {
+    /// `activity` or `balance`.
+    pub template: String,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub network: Option<String>,
+    /// Stage of the sample activity, `Confirmed` when left out.
+    #[serde(default)]
+    pub stage: Option<NotificationStage>,
+    /// Kind of the sample activity, a `Transfer` when left out.
+    #[serde(default)]
+    pub kind: Option<ActivityKind>,
+    /// Render the sample as a follow-up to a pending alert.
+    #[serde(default)]
+    pub follow_up: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SubmitUpdateNetwork {
     pub old_chain_id: String,
@@ -59,12 +111,17 @@ macro_rules! try_or_return {
 }
 
 #[post("admin/network")]
-pub async fn create_network(db: Data<Database>, request: Json<SubmitCreateNetwork>) -> ApiResponse {
+pub async fn create_network(
+    db: Data<Database>,
+    supervisor: Data<CrawlerSupervisor>,
+    request: Json<SubmitCreateNetwork>,
+) -> ApiResponse {
     let network_type = try_or_return_string!(Network::from_str(request.network_type.clone()));
     let chain_id = request.chain_id.clone();
 
-    let new_network = NetworkManager::new(network_type, chain_id);
+    let new_network = NetworkManager::new(network_type.clone(), chain_id);
     let response_network = try_or_return!(db.create_network(new_network).await);
+    supervisor.start(network_type);
 
     ApiResponse::new(201, format!("{:?}", response_network))
 }
@@ -92,12 +149,17 @@ pub async fn get_all_network(db: Data<Database>) -> ApiResponse {
 }
 
 #[delete("admin/network")]
-pub async fn delete_network(db: Data<Database>, request: Json<SubmitDeleteNetwork>) -> ApiResponse {
+pub async fn delete_network(
+    db: Data<Database>,
+    supervisor: Data<CrawlerSupervisor>,
+    request: Json<SubmitDeleteNetwork>,
+) -> ApiResponse {
     let network_type = request.network_type.clone();
 
     let network = try_or_return_string!(Network::from_str(network_type));
 
-    let response_network = try_or_return!(db.delete_network(network).await);
+    let response_network = try_or_return!(db.delete_network(network.clone()).await);
+    supervisor.stop(&network);
 
     ApiResponse::new(200, format!("{:?}", response_network))
 }
@@ -128,3 +190,154 @@ pub async fn set_last_scanned_block(
 
     ApiResponse::new(200, format!("{:?}", response_network))
 }
+
+#[patch("admin/network/confirmation")]
+pub async fn set_confirmation(
+    db: Data<Database>,
+    request: Json<SubmitSetConfirmation>,
+) -> ApiResponse {
+    let network_type = request.network_type.clone();
+    let network = try_or_return_string!(Network::from_str(network_type));
+
+    let mut network_manager = try_or_return!(db.get_network_via_name(network).await);
+    try_or_return_string!(network_manager.update_confirmation(
+        request.confirmation_depth,
+        request.required_finality.clone()
+    ));
+    let response_network = try_or_return!(db.update_network(network_manager).await);
+
+    ApiResponse::new(200, format!("{:?}", response_network))
+}
+
+#[patch("admin/network/backfill")]
+pub async fn set_backfill(db: Data<Database>, request: Json<SubmitSetBackfill>) -> ApiResponse {
+    let network_type = request.network_type.clone();
+    let network = try_or_return_string!(Network::from_str(network_type));
+
+    let mut network_manager = try_or_return!(db.get_network_via_name(network).await);
+    try_or_return_string!(network_manager.update_backfill(
+        request.backfill_workers,
+        request.rpc_batch_size,
+        request.block_retries
+    ));
+    let response_network = try_or_return!(db.update_network(network_manager).await);
+
+    ApiResponse::new(200, format!("{:?}", response_network))
+}
+
+#[patch("admin/network/rpc_endpoints")]
+pub async fn set_rpc_endpoints(
+    db: Data<Database>,
+    supervisor: Data<CrawlerSupervisor>,
+    request: Json<SubmitSetRpcEndpoints>,
+) -> ApiResponse {
+    let network_type = request.network_type.clone();
+    let network = try_or_return_string!(Network::from_str(network_type));
+
+    let mut network_manager = try_or_return!(db.get_network_via_name(network.clone()).await);
+    try_or_return_string!(network_manager.update_rpc_endpoints(request.rpc_endpoints.clone()));
+    let response_network = try_or_return!(db.update_network(network_manager).await);
+    supervisor.restart(network);
+
+    ApiResponse::new(200, format!("{:?}", response_network))
+}
+
+#[patch("admin/network/ws_endpoint")]
+pub async fn set_ws_endpoint(
+    db: Data<Database>,
+    supervisor: Data<CrawlerSupervisor>,
+    request: Json<SubmitSetWsEndpoint>,
+) -> ApiResponse {
+    let network_type = request.network_type.clone();
+    let network = try_or_return_string!(Network::from_str(network_type));
+
+    let mut network_manager = try_or_return!(db.get_network_via_name(network.clone()).await);
+    try_or_return_string!(network_manager.update_ws_endpoint(request.ws_endpoint.clone()));
+    let response_network = try_or_return!(db.update_network(network_manager).await);
+    supervisor.restart(network);
+
+    ApiResponse::new(200, format!("{:?}", response_network))
+}
+
+#[patch("admin/network/pending_alerts")]
+pub async fn set_pending_alerts(
+    db: Data<Database>,
+    request: Json<SubmitSetPendingAlerts>,
+) -> ApiResponse {
+    let network_type = request.network_type.clone();
+    let network = try_or_return_string!(Network::from_str(network_type));
+
+    let mut network_manager = try_or_return!(db.get_network_via_name(network).await);
+    network_manager.update_pending_alerts(request.pending_alerts);
+    let response_network = try_or_return!(db.update_network(network_manager).await);
+
+    ApiResponse::new(200, format!("{:?}", response_network))
+}
+
+#[get("admin/webhooks/dead_letters")]
+pub async fn get_dead_letters(db: Data<Database>) -> ApiResponse {
+    let deliveries = try_or_return!(db.get_dead_letters().await);
+    ApiResponse::new(200, format!("{:?}", deliveries))
+}
+
+/// Sends a dead-lettered delivery once more, signed with the webhook's current secret. It
+/// leaves the queue when it goes through.
+#[post("admin/webhooks/dead_letters/replay")]
+pub async fn replay_dead_letter(
+    db: Data<Database>,
+    request: Json<SubmitReplayWebhook>,
+) -> ApiResponse {
+    let mut delivery = try_or_return!(db.get_dead_letter(request.delivery_id.clone()).await);
+    let user = try_or_return!(db.get_user_via_id(delivery.user_uuid.clone()).await);
+    let secret = match user.webhook_secret(&delivery.url) {
+        Some(secret) => secret,
+        None => return ApiResponse::new(404, "Webhook is no longer registered".to_string()),
+    };
+
+    match attempt_delivery(&delivery, secret.as_deref()).await {
+        Ok(()) => {
+            try_or_return!(db.delete_dead_letter(delivery.delivery_id).await);
+            ApiResponse::new(200, "Webhook delivery replayed successfully".to_string())
+        }
+        Err(err) => {
+            delivery.record_failure(err.clone());
+            try_or_return!(db.update_dead_letter(delivery).await);
+            ApiResponse::new(502, format!("Replay failed: {err}"))
+        }
+    }
+}
+
+/// Outbound mail in the queue with the given status: Queued, Sending, Sent or Failed.
+#[get("admin/mail_queue/{status}")]
+pub async fn get_mail_queue(db: Data<Database>, path: Path<String>) -> ApiResponse {
+    let status = try_or_return_string!(MailStatus::from_str(path.into_inner()));
+    let mails = try_or_return!(db.get_mail_by_status(status).await);
+    ApiResponse::new(200, format!("{:?}", mails))
+}
+
+/// Renders a mail template with sample data.
+#[post("admin/mail_templates/preview")]
+pub async fn preview_mail_template(request: Json<SubmitPreviewMail>) -> ApiResponse {
+    let request = request.into_inner();
+    let language = match request.language {
+        Some(language) => try_or_return_string!(Language::from_str(language)),
+        None => Language::English,
+    };
+    let network = match request.network {
+        Some(network) => try_or_return_string!(Network::from_str(network)),
+        None => Network::Starknet,
+    };
+    let stage = request.stage.unwrap_or(NotificationStage::Confirmed);
+    let kind = request.kind.unwrap_or(ActivityKind::Transfer);
+
+    let mail = try_or_return_string!(preview_mail(
+        &request.template,
+        &language,
+        &network,
+        stage,
+        kind,
+        request.follow_up
+    ));
+    let body = try_or_return_string!(serde_json::to_string(&mail).map_err(|e| e.to_string()));
+    ApiResponse::new(200, body)
+}
@@ -1,21 +1,24 @@
+use crate::crawlers::supervisor::CrawlerSupervisor;
+use crate::models::activity_model::ActivityRecord;
+use crate::models::address_model::{normalize_any_address, same_address};
+use crate::models::balance_model::BalanceWatch;
+use crate::models::channel_model::NotificationChannel;
+use crate::models::contract_model::WatchedContract;
 use crate::models::network_model::Network;
-use crate::models::user_model::User;
+use crate::models::rule_model::{AlertRule, RuleCondition};
+use crate::models::user_model::{Language, NotifyOn, User};
 use crate::models::wallet_model::Wallet;
 use crate::services::db::Database;
 use crate::utils::api_response::ApiResponse;
-use actix_web::{
-    delete,
-    error::ResponseError,
-    get,
-    http::{header::ContentType, StatusCode},
-    patch, post, put,
-    web::Data,
-    web::Json,
-    web::Path,
-    HttpResponse,
-};
+use actix_web::{delete, get, patch, post, web::Data, web::Json, web::Path, web::Query};
 use serde::{Deserialize, Serialize};
 
+// Upper bound on the history scanned for a newly added wallet.
+const MAX_BACKFILL_BLOCKS: u64 = 10_000;
+const DEFAULT_ACTIVITY_PAGE_SIZE: u64 = 20;
+const MAX_ACTIVITY_PAGE_SIZE: u64 = 100;
+const MAX_ACTIVITY_PAGE: u64 = 10_000;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SubmitCreateUser {
     pub name: String,
@@ -27,12 +30,16 @@ pub struct SubmitAddWallet {
     pub user_id: String,
     pub wallet_address: String,
     pub network: String,
+    /// Past blocks to scan for the wallet's history, none when unset.
+    #[serde(default)]
+    pub backfill_blocks: Option<u64>,
 }
 
 #[derive(Serialize, Clone, Deserialize)]
 pub struct SubmitUpdateWallet {
     pub user_id: String,
     pub wallet_address: String,
+    pub network: String,
     pub new_network: String,
 }
 
@@ -40,6 +47,7 @@ pub struct SubmitUpdateWallet {
 pub struct SubmitDeleteWallet {
     pub user_id: String,
     pub wallet_address: String,
+    pub network: String,
 }
 
 #[derive(Serialize, Clone, Deserialize)]
@@ -68,6 +76,95 @@ pub struct SubmitUpdateUserEmail {
     new_email: String,
 }
 
+#[derive(Serialize, Clone, Deserialize)]
+pub struct SubmitUpdateNotifyOn {
+    pub user_id: String,
+    pub notify_on: String,
+}
+
+#[derive(Serialize, Clone, Deserialize)]
+pub struct SubmitUpdateLanguage {
+    pub user_id: String,
+    pub language: String,
+}
+
+#[derive(Serialize, Clone, Deserialize)]
+pub struct SubmitUpdateChannels {
+    pub user_id: String,
+    pub channels: Vec<NotificationChannel>,
+}
+
+#[derive(Serialize, Clone, Deserialize)]
+pub struct SubmitAddContract {
+    pub user_id: String,
+    pub contract_address: String,
+    pub network: String,
+    #[serde(default)]
+    pub event_filters: Vec<String>,
+}
+
+#[derive(Serialize, Clone, Deserialize)]
+pub struct SubmitDeleteContract {
+    pub user_id: String,
+    pub contract_address: String,
+}
+
+#[derive(Serialize, Clone, Deserialize)]
+pub struct SubmitAddRule {
+    pub user_id: String,
+    pub name: String,
+    pub conditions: Vec<RuleCondition>,
+}
+
+#[derive(Serialize, Clone, Deserialize)]
+pub struct SubmitUpdateRule {
+    pub user_id: String,
+    pub rule_id: String,
+    pub name: Option<String>,
+    pub conditions: Option<Vec<RuleCondition>>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Serialize, Clone, Deserialize)]
+pub struct SubmitDeleteRule {
+    pub user_id: String,
+    pub rule_id: String,
+}
+
+#[derive(Serialize, Clone, Deserialize)]
+pub struct SubmitAddBalanceWatch {
+    pub user_id: String,
+    pub wallet_address: String,
+    pub network: String,
+    pub token_address: String,
+    pub below: Option<String>,
+    pub above: Option<String>,
+}
+
+#[derive(Serialize, Clone, Deserialize)]
+pub struct SubmitDeleteBalanceWatch {
+    pub user_id: String,
+    pub watch_id: String,
+}
+
+/// Query string of the activity feed. `from` and `to` are unix seconds; pages start at 1.
+#[derive(Serialize, Clone, Deserialize)]
+pub struct SubmitGetActivity {
+    pub wallet: Option<String>,
+    pub network: Option<String>,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub page: Option<u64>,
+    pub limit: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct ActivityPage {
+    page: u64,
+    limit: u64,
+    activity: Vec<ActivityRecord>,
+}
+
 macro_rules! try_or_return_string {
     ($result:expr) => {
         match $result {
@@ -156,16 +253,56 @@ pub async fn update_user_email(
     return ApiResponse::new(200, format!("{:?}", response_user));
 }
 
+#[patch("/user/notify_on")]
+pub async fn update_notify_on(
+    db: Data<Database>,
+    request: Json<SubmitUpdateNotifyOn>,
+) -> ApiResponse {
+    let notify_on = try_or_return_string!(NotifyOn::from_str(request.notify_on.clone()));
+
+    let mut user: User = try_or_return!(db.get_user_via_id(request.user_id.clone()).await);
+    try_or_return_string!(user.update_notify_on(notify_on));
+    let response_user = try_or_return!(db.update_user(user).await);
+    ApiResponse::new(200, format!("{:?}", response_user))
+}
+
+#[patch("/user/language")]
+pub async fn update_language(
+    db: Data<Database>,
+    request: Json<SubmitUpdateLanguage>,
+) -> ApiResponse {
+    let language = try_or_return_string!(Language::from_str(request.language.clone()));
+
+    let mut user: User = try_or_return!(db.get_user_via_id(request.user_id.clone()).await);
+    try_or_return_string!(user.update_language(language));
+    let response_user = try_or_return!(db.update_user(user).await);
+    ApiResponse::new(200, format!("{:?}", response_user))
+}
+
 #[post("/user/wallets")]
-pub async fn add_wallet(db: Data<Database>, request: Json<SubmitAddWallet>) -> ApiResponse {
+pub async fn add_wallet(
+    db: Data<Database>,
+    supervisor: Data<CrawlerSupervisor>,
+    request: Json<SubmitAddWallet>,
+) -> ApiResponse {
     let user_id = request.user_id.clone();
     let user_wallet = try_or_return_string!(Wallet::new(
         request.wallet_address.clone(),
         request.network.clone()
     ));
+    let backfill_blocks = request.backfill_blocks.unwrap_or(0);
+    if backfill_blocks > MAX_BACKFILL_BLOCKS {
+        return ApiResponse::new(
+            400,
+            format!("backfill_blocks can't be more than {}", MAX_BACKFILL_BLOCKS),
+        );
+    }
     let mut user: User = try_or_return!(db.get_user_via_id(user_id.clone()).await);
-    try_or_return_string!(user.add_wallet(user_wallet));
+    try_or_return_string!(user.add_wallet(user_wallet.clone()));
     let response_user = try_or_return!(db.update_user(user).await);
+    if backfill_blocks > 0 {
+        supervisor.backfill(user_wallet, backfill_blocks);
+    }
     return ApiResponse::new(200, format!("{:?}", response_user));
 }
 
@@ -173,11 +310,13 @@ pub async fn add_wallet(db: Data<Database>, request: Json<SubmitAddWallet>) -> A
 pub async fn update_wallets(db: Data<Database>, request: Json<SubmitUpdateWallet>) -> ApiResponse {
     let user_id = request.user_id.clone();
     let wallets_address = request.wallet_address.clone();
+    let network = try_or_return_string!(Network::from_str(request.network.clone()));
     let new_network = request.new_network.clone();
 
     let mut user: User = try_or_return!(db.get_user_via_id(user_id.clone()).await);
     try_or_return_string!(user.update_wallet_network(
         &wallets_address,
+        &network,
         try_or_return_string!(Network::from_str(new_network)),
     ));
     let response_user = try_or_return!(db.update_user(user).await);
@@ -188,9 +327,10 @@ pub async fn update_wallets(db: Data<Database>, request: Json<SubmitUpdateWallet
 pub async fn delete_wallet(db: Data<Database>, request: Json<SubmitDeleteWallet>) -> ApiResponse {
     let user_id = request.user_id.clone();
     let wallets_address = request.wallet_address.clone();
+    let network = try_or_return_string!(Network::from_str(request.network.clone()));
 
     let mut user: User = try_or_return!(db.get_user_via_id(user_id.clone()).await);
-    try_or_return_string!(user.remove_wallet(&wallets_address));
+    try_or_return_string!(user.remove_wallet(&wallets_address, &network));
     let response_user = try_or_return!(db.update_user(user).await);
     return ApiResponse::new(
         200,
@@ -217,8 +357,215 @@ pub async fn get_users_via_wallet(
     db: Data<Database>,
     request: Path<SubmitGetUserViaWallet>,
 ) -> ApiResponse {
-    let wallets_address = request.into_inner().wallet_address;
+    let wallets_address =
+        try_or_return_string!(normalize_any_address(&request.into_inner().wallet_address));
     println!("finding wallets users...");
-    let users: Vec<User> = try_or_return!(db.find_users_with_wallet_address(wallets_address).await);
+    let users: Vec<User> = try_or_return!(
+        db.find_users_with_wallet_address(None, wallets_address)
+            .await
+    );
     return ApiResponse::new(200, format!("{:?}", users));
 }
+
+#[patch("/user/channels")]
+pub async fn update_channels(
+    db: Data<Database>,
+    request: Json<SubmitUpdateChannels>,
+) -> ApiResponse {
+    let request = request.into_inner();
+    let mut user: User = try_or_return!(db.get_user_via_id(request.user_id).await);
+    try_or_return_string!(user.update_channels(request.channels));
+    let response_user = try_or_return!(db.update_user(user).await);
+    ApiResponse::new(200, format!("{:?}", response_user.notification_channels()))
+}
+
+#[get("/user/channels/by-id/{user_id}")]
+pub async fn get_channels(db: Data<Database>, request: Path<SubmitGetProfile>) -> ApiResponse {
+    let user_id = request.into_inner().user_id;
+
+    let user: User = try_or_return!(db.get_user_via_id(user_id).await);
+    let channels: Vec<NotificationChannel> = user
+        .notification_channels()
+        .iter()
+        .map(NotificationChannel::redacted)
+        .collect();
+    ApiResponse::new(200, format!("{:?}", channels))
+}
+
+#[post("/user/contracts")]
+pub async fn add_contract(db: Data<Database>, request: Json<SubmitAddContract>) -> ApiResponse {
+    let contract = try_or_return_string!(WatchedContract::new(
+        request.contract_address.clone(),
+        request.network.clone(),
+        request.event_filters.clone()
+    ));
+    let mut user: User = try_or_return!(db.get_user_via_id(request.user_id.clone()).await);
+    try_or_return_string!(user.add_watched_contract(contract));
+    let response_user = try_or_return!(db.update_user(user).await);
+    ApiResponse::new(200, format!("{:?}", response_user.watched_contracts))
+}
+
+#[delete("/user/contracts")]
+pub async fn delete_contract(
+    db: Data<Database>,
+    request: Json<SubmitDeleteContract>,
+) -> ApiResponse {
+    let mut user: User = try_or_return!(db.get_user_via_id(request.user_id.clone()).await);
+    try_or_return_string!(user.remove_watched_contract(&request.contract_address));
+    let response_user = try_or_return!(db.update_user(user).await);
+    ApiResponse::new(200, format!("{:?}", response_user.watched_contracts))
+}
+
+#[get("/user/contracts/by-id/{user_id}")]
+pub async fn get_contracts(db: Data<Database>, request: Path<SubmitGetProfile>) -> ApiResponse {
+    let user_id = request.into_inner().user_id;
+
+    let user: User = try_or_return!(db.get_user_via_id(user_id).await);
+    ApiResponse::new(200, format!("{:?}", user.watched_contracts))
+}
+
+#[post("/user/rules")]
+pub async fn add_rule(db: Data<Database>, request: Json<SubmitAddRule>) -> ApiResponse {
+    let rule = try_or_return_string!(AlertRule::new(
+        request.name.clone(),
+        request.conditions.clone()
+    ));
+    let mut user: User = try_or_return!(db.get_user_via_id(request.user_id.clone()).await);
+    try_or_return_string!(user.add_rule(rule));
+    let response_user = try_or_return!(db.update_user(user).await);
+    ApiResponse::new(200, format!("{:?}", response_user.rules))
+}
+
+#[patch("/user/rules")]
+pub async fn update_rule(db: Data<Database>, request: Json<SubmitUpdateRule>) -> ApiResponse {
+    let request = request.into_inner();
+    let mut user: User = try_or_return!(db.get_user_via_id(request.user_id).await);
+    try_or_return_string!(user.update_rule(
+        &request.rule_id,
+        request.name,
+        request.conditions,
+        request.enabled
+    ));
+    let response_user = try_or_return!(db.update_user(user).await);
+    ApiResponse::new(200, format!("{:?}", response_user.rules))
+}
+
+#[delete("/user/rules")]
+pub async fn delete_rule(db: Data<Database>, request: Json<SubmitDeleteRule>) -> ApiResponse {
+    let mut user: User = try_or_return!(db.get_user_via_id(request.user_id.clone()).await);
+    try_or_return_string!(user.remove_rule(&request.rule_id));
+    let response_user = try_or_return!(db.update_user(user).await);
+    ApiResponse::new(200, format!("{:?}", response_user.rules))
+}
+
+#[get("/user/rules/by-id/{user_id}")]
+pub async fn get_rules(db: Data<Database>, request: Path<SubmitGetProfile>) -> ApiResponse {
+    let user_id = request.into_inner().user_id;
+
+    let user: User = try_or_return!(db.get_user_via_id(user_id).await);
+    ApiResponse::new(200, format!("{:?}", user.rules))
+}
+
+#[post("/user/balance_watches")]
+pub async fn add_balance_watch(
+    db: Data<Database>,
+    request: Json<SubmitAddBalanceWatch>,
+) -> ApiResponse {
+    let request = request.into_inner();
+    let watch = try_or_return_string!(BalanceWatch::new(
+        request.wallet_address,
+        request.network,
+        request.token_address,
+        request.below,
+        request.above
+    ));
+    let mut user: User = try_or_return!(db.get_user_via_id(request.user_id).await);
+    try_or_return_string!(user.add_balance_watch(watch));
+    let response_user = try_or_return!(db.update_user(user).await);
+    ApiResponse::new(200, format!("{:?}", response_user.balance_watches))
+}
+
+#[delete("/user/balance_watches")]
+pub async fn delete_balance_watch(
+    db: Data<Database>,
+    request: Json<SubmitDeleteBalanceWatch>,
+) -> ApiResponse {
+    let mut user: User = try_or_return!(db.get_user_via_id(request.user_id.clone()).await);
+    try_or_return_string!(user.remove_balance_watch(&request.watch_id));
+    let response_user = try_or_return!(db.update_user(user).await);
+    ApiResponse::new(200, format!("{:?}", response_user.balance_watches))
+}
+
+#[get("/user/balance_watches/by-id/{user_id}")]
+pub async fn get_balance_watches(
+    db: Data<Database>,
+    request: Path<SubmitGetProfile>,
+) -> ApiResponse {
+    let user_id = request.into_inner().user_id;
+
+    let user: User = try_or_return!(db.get_user_via_id(user_id).await);
+    ApiResponse::new(200, format!("{:?}", user.balance_watches))
+}
+
+/// The user's activity feed: stored activity of their wallets and watched contracts,
+/// newest first, as JSON.
+#[get("/user/{user_id}/activity")]
+pub async fn get_activity(
+    db: Data<Database>,
+    request: Path<SubmitGetProfile>,
+    query: Query<SubmitGetActivity>,
+) -> ApiResponse {
+    let user_id = request.into_inner().user_id;
+    let query = query.into_inner();
+    let network = match query.network {
+        Some(network) => Some(try_or_return_string!(Network::from_str(network))),
+        None => None,
+    };
+
+    let user: User = try_or_return!(db.get_user_via_id(user_id).await);
+    let addresses: Vec<(Network, String)> = user
+        .wallets
+        .iter()
+        .map(|wallet| (wallet.network.clone(), wallet.wallet_address.clone()))
+        .chain(
+            user.watched_contracts
+                .iter()
+                .map(|contract| (contract.network.clone(), contract.contract_address.clone())),
+        )
+        .filter(|(address_network, address)| {
+            network
+                .as_ref()
+                .is_none_or(|network| network == address_network)
+                && query
+                    .wallet
+                    .as_ref()
+                    .is_none_or(|wallet| same_address(wallet, address))
+        })
+        .collect();
+    if query.wallet.is_some() && addresses.is_empty() {
+        return ApiResponse::new(404, "Wallet not found for user".to_string());
+    }
+
+    let page = query.page.unwrap_or(1).clamp(1, MAX_ACTIVITY_PAGE);
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_ACTIVITY_PAGE_SIZE)
+        .clamp(1, MAX_ACTIVITY_PAGE_SIZE);
+    let activity = try_or_return!(
+        db.get_activity(
+            &addresses,
+            query.from,
+            query.to,
+            (page - 1).saturating_mul(limit),
+            limit as i64
+        )
+        .await
+    );
+    let body = try_or_return_string!(serde_json::to_string(&ActivityPage {
+        page,
+        limit,
+        activity
+    })
+    .map_err(|e| e.to_string()));
+    ApiResponse::new(200, body)
+}
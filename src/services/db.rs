@@ -1,23 +1,33 @@
-use crate::models::network_model::{Network, NetworkManager};
+use crate::models::activity_model::{Activity, ActivityRecord};
+use crate::models::address_model::{normalize_address, normalize_any_address};
+use crate::models::contract_model::WatchedContract;
+use crate::models::mail_model::{MailStatus, QueuedMail};
+use crate::models::network_model::{Network, NetworkManager, ScannedBlock};
+use crate::models::notification_model::{Notification, NotificationStage, NotificationStatus};
 use crate::models::user_model::User;
 use crate::models::wallet_model::Wallet;
+use crate::models::webhook_model::WebhookDelivery;
 use dotenv::dotenv;
 use futures_util::stream::StreamExt;
-use mongodb::bson::from_document;
-use mongodb::error::Error;
+use mongodb::bson::to_bson;
+use mongodb::error::{Error, ErrorKind, WriteFailure};
 use mongodb::{
-    bson::doc,
-    options::IndexOptions,
+    bson::{doc, DateTime, Document},
+    options::{FindOptions, IndexOptions, ReturnDocument},
     results::{DeleteResult, InsertOneResult, UpdateResult},
     Client, Collection, IndexModel,
 };
 use std::env;
-use std::result;
 
 #[derive(Debug, Clone)]
 pub struct Database {
     users: Collection<User>,
     networks: Collection<NetworkManager>,
+    notifications: Collection<Notification>,
+    activity: Collection<ActivityRecord>,
+    dead_letters: Collection<WebhookDelivery>,
+    webhook_queue: Collection<WebhookDelivery>,
+    mail_queue: Collection<QueuedMail>,
 }
 
 #[derive(Debug)]
@@ -35,6 +45,45 @@ impl DatabaseResponse {
     }
 }
 
+/// Outcome of claiming a notification in the ledger.
+#[derive(Debug)]
+pub enum NotificationClaim {
+    /// Not delivered yet, deliver it now.
+    Deliver(Notification),
+    /// Already delivered; the existing ledger entry.
+    Delivered(Notification),
+}
+
+/// Matches users holding `wallet_address`. EVM networks share one address space, so the
+/// network has to match on the same wallet entry.
+fn wallet_filter(network: Option<&Network>, wallet_address: String) -> Result<Document, String> {
+    match network {
+        Some(network) => {
+            let wallet_address =
+                normalize_address(network, &wallet_address).unwrap_or(wallet_address);
+            Ok(doc! {
+                "wallets": {
+                    "$elemMatch": {
+                        "wallet_address": wallet_address,
+                        "network": network.as_str()?,
+                    }
+                }
+            })
+        }
+        None => {
+            let wallet_address = normalize_any_address(&wallet_address).unwrap_or(wallet_address);
+            Ok(doc! {"wallets.wallet_address": wallet_address})
+        }
+    }
+}
+
+fn is_duplicate_key(err: &Error) -> bool {
+    matches!(
+        err.kind.as_ref(),
+        ErrorKind::Write(WriteFailure::WriteError(write_error)) if write_error.code == 11000
+    )
+}
+
 macro_rules! try_or_return_string {
     ($result:expr) => {
         match $result {
@@ -56,8 +105,60 @@ impl Database {
 
         let users = db.collection("users");
         let networks = db.collection("networks");
+        let notifications: Collection<Notification> = db.collection("notifications");
+
+        // Replaced by the keys below, which also tell apart activities of one transaction.
+        let _ = notifications
+            .drop_index("network_1_transaction_hash_1_user_uuid_1_event_index_1")
+            .await;
+        let ledger_index = IndexModel::builder()
+            .keys(Notification::ledger_index())
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        notifications
+            .create_index(ledger_index)
+            .await
+            .expect("failed to create notifications index");
+
+        let activity: Collection<ActivityRecord> = db.collection("activity");
+        let _ = activity
+            .drop_index("network_1_transaction_hash_1_wallet_address_1_event_index_1")
+            .await;
+        let activity_index = IndexModel::builder()
+            .keys(doc! {"network": 1, "transaction_hash": 1, "wallet_address": 1, "kind": 1, "event_index": 1})
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        activity
+            .create_index(activity_index)
+            .await
+            .expect("failed to create activity index");
+
+        let mail_queue: Collection<QueuedMail> = db.collection("mail_queue");
+        let mail_queue_index = IndexModel::builder()
+            .keys(doc! {"status": 1, "next_attempt_at": 1})
+            .build();
+        mail_queue
+            .create_index(mail_queue_index)
+            .await
+            .expect("failed to create mail queue index");
+
+        let webhook_queue: Collection<WebhookDelivery> = db.collection("webhook_deliveries");
+        let webhook_queue_index = IndexModel::builder().keys(doc! {"retry_at": 1}).build();
+        webhook_queue
+            .create_index(webhook_queue_index)
+            .await
+            .expect("failed to create webhook queue index");
+
         println!("DATABASE CONNECTION SUCCESSFUL!!!!");
-        return Database { users, networks };
+        return Database {
+            users,
+            networks,
+            notifications,
+            activity,
+            dead_letters: db.collection("webhook_dead_letters"),
+            webhook_queue,
+            mail_queue,
+        };
     }
 
     pub async fn create_user(&self, user: User) -> Result<InsertOneResult, DatabaseResponse> {
@@ -78,6 +179,7 @@ impl Database {
         }
     }
 
+    #[allow(dead_code)]
     pub async fn change_email(
         &self,
         email: String,
@@ -95,7 +197,7 @@ impl Database {
     }
 
     pub async fn get_all_users(&self) -> Result<Vec<User>, DatabaseResponse> {
-        let mut result = self.users.find(doc! {}).await;
+        let result = self.users.find(doc! {}).await;
         match result {
             Ok(mut cursor) => {
                 let mut users: Vec<User> = Vec::new();
@@ -180,23 +282,96 @@ impl Database {
         }
     }
 
+    /// Users with the wallet `wallet_address` on `network`, or on any network when `None`.
+    /// The address is normalized first, so lowercase EVM addresses find their checksummed form.
     pub async fn find_users_with_wallet_address(
         &self,
+        network: Option<Network>,
         wallet_address: String,
     ) -> Result<Vec<User>, DatabaseResponse> {
+        let filter = try_or_return_string!(wallet_filter(network.as_ref(), wallet_address));
+        let result = self.users.find(filter).await;
+
+        match result {
+            Ok(mut cursor) => {
+                let mut users: Vec<User> = Vec::new();
+                while let Some(doc) = cursor.next().await {
+                    match doc {
+                        Ok(user) => users.push(user),
+                        Err(e) => return Err(DatabaseResponse::new(500, format!("{}", e))),
+                    }
+                }
+                Ok(users)
+            }
+            Err(e) => return Err(DatabaseResponse::new(500, format!("{}", e))),
+        }
+    }
+
+    pub async fn get_all_watched_contracts_via_network(
+        &self,
+        network: Network,
+    ) -> Result<Vec<WatchedContract>, DatabaseResponse> {
+        let result = self.users.find(doc! {}).await;
+        match result {
+            Ok(mut cursor) => {
+                let mut contracts: Vec<WatchedContract> = Vec::new();
+                while let Some(result) = cursor.next().await {
+                    match result {
+                        Ok(user) => {
+                            for contract in user.watched_contracts.iter() {
+                                if contract.network == network {
+                                    contracts.push(contract.clone());
+                                }
+                            }
+                        }
+                        Err(e) => return Err(DatabaseResponse::new(500, format!("{}", e))),
+                    }
+                }
+                Ok(contracts)
+            }
+            Err(err) => Err(DatabaseResponse::new(500, format!("{}", err))),
+        }
+    }
+
+    pub async fn find_users_watching_contract(
+        &self,
+        network: Network,
+        contract_address: String,
+    ) -> Result<Vec<User>, DatabaseResponse> {
+        let contract_address =
+            normalize_address(&network, &contract_address).unwrap_or(contract_address);
         let filter = doc! {
-            "wallets": {
+            "watched_contracts": {
                 "$elemMatch": {
-                    "wallet_address": {
-                        "$regex": wallet_address,
-                        "$options": "i"
-                    }
+                    "contract_address": contract_address,
+                    "network": try_or_return_string!(network.as_str()),
                 }
             }
         };
 
         let result = self.users.find(filter).await;
+        match result {
+            Ok(mut cursor) => {
+                let mut users: Vec<User> = Vec::new();
+                while let Some(doc) = cursor.next().await {
+                    match doc {
+                        Ok(user) => users.push(user),
+                        Err(e) => return Err(DatabaseResponse::new(500, format!("{}", e))),
+                    }
+                }
+                Ok(users)
+            }
+            Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
+        }
+    }
 
+    pub async fn find_users_with_balance_watches(
+        &self,
+        network: Network,
+    ) -> Result<Vec<User>, DatabaseResponse> {
+        let filter = doc! {"balance_watches.network": try_or_return_string!(network.as_str())};
+
+        let result = self.users.find(filter).await;
         match result {
             Ok(mut cursor) => {
                 let mut users: Vec<User> = Vec::new();
@@ -208,7 +383,28 @@ impl Database {
                 }
                 Ok(users)
             }
-            Err(e) => return Err(DatabaseResponse::new(500, format!("{}", e))),
+            Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
+        }
+    }
+
+    /// Stores the balance seen by the latest check of one of the user's balance watches,
+    /// leaving the rest of the user untouched.
+    pub async fn update_last_balance(
+        &self,
+        user_uuid: String,
+        watch_id: String,
+        balance: String,
+    ) -> Result<UpdateResult, DatabaseResponse> {
+        let result = self
+            .users
+            .update_one(
+                doc! {"user_uuid": user_uuid, "balance_watches.watch_id": watch_id},
+                doc! {"$set": {"balance_watches.$.last_balance": balance}},
+            )
+            .await;
+        match result {
+            Ok(update_result) => Ok(update_result),
+            Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
         }
     }
 
@@ -370,8 +566,8 @@ impl Database {
             .await;
         match result {
             Ok(update_result) => {
-                if update_result.modified_count == 0 {
-                    Err(DatabaseResponse::new(404, "User not found".to_string()))
+                if update_result.matched_count == 0 {
+                    Err(DatabaseResponse::new(404, "Network not found".to_string()))
                 } else {
                     Ok(network)
                 }
@@ -380,6 +576,34 @@ impl Database {
         }
     }
 
+    /// Moves the scan cursor and the recent block hashes only, so settings changed while a
+    /// block was scanned are kept. Nothing is written, and `false` returned, when the cursor
+    /// is no longer at `expected_last_block`, e.g. after an admin rewind.
+    pub async fn update_scan_cursor(
+        &self,
+        network: Network,
+        expected_last_block: u128,
+        last_scanned_block: u128,
+        recent_blocks: &[ScannedBlock],
+    ) -> Result<bool, DatabaseResponse> {
+        let recent_blocks = try_or_return_string!(to_bson(recent_blocks));
+        let result = try_or_return_string!(
+            self.networks
+                .update_one(
+                    doc! {
+                        "network_type": try_or_return_string!(network.as_str()),
+                        "last_scanned_block": expected_last_block.to_string(),
+                    },
+                    doc! {"$set": {
+                        "last_scanned_block": last_scanned_block.to_string(),
+                        "recent_blocks": recent_blocks,
+                    }},
+                )
+                .await
+        );
+        Ok(result.matched_count > 0)
+    }
+
     pub async fn get_last_scanned_block(&self, network: Network) -> Result<u128, DatabaseResponse> {
         let network_name = try_or_return_string!(network.as_str());
         let result = self
@@ -423,4 +647,527 @@ impl Database {
             Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
         }
     }
+
+    /// Stores `activities`, found in a block from `block_timestamp`, in the history.
+    /// Activity that is already stored is left as is.
+    pub async fn record_activities(
+        &self,
+        activities: &[Activity],
+        block_timestamp: u64,
+        backfilled: bool,
+    ) -> Result<(), DatabaseResponse> {
+        for activity in activities {
+            let record = try_or_return_string!(to_bson(&ActivityRecord::new(
+                activity.clone(),
+                block_timestamp,
+                backfilled
+            )));
+            let filter = doc! {
+                "network": try_or_return_string!(activity.network.as_str()),
+                "transaction_hash": activity.transaction_hash.clone(),
+                "wallet_address": activity.wallet_address.clone(),
+                "kind": activity.kind().as_str(),
+                "event_index": activity.event_index,
+            };
+            let result = self
+                .activity
+                .update_one(filter, doc! {"$setOnInsert": record})
+                .upsert(true)
+                .await;
+            if let Err(e) = result {
+                return Err(DatabaseResponse::new(500, format!("{}", e)));
+            }
+        }
+        Ok(())
+    }
+
+    /// A page of the stored activity of `addresses`, newest first. `from` and `to` bound
+    /// the block time in unix seconds, both inclusive.
+    pub async fn get_activity(
+        &self,
+        addresses: &[(Network, String)],
+        from: Option<u64>,
+        to: Option<u64>,
+        skip: u64,
+        limit: i64,
+    ) -> Result<Vec<ActivityRecord>, DatabaseResponse> {
+        if addresses.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut any_address = Vec::new();
+        for (network, address) in addresses {
+            any_address.push(doc! {
+                "network": try_or_return_string!(network.as_str()),
+                // Activity is recorded under the lowercased address.
+                "wallet_address": address.to_lowercase(),
+            });
+        }
+        let mut filter = doc! {"$or": any_address};
+        let mut block_time = doc! {};
+        if let Some(from) = from {
+            block_time.insert("$gte", from as i64);
+        }
+        if let Some(to) = to {
+            block_time.insert("$lte", to as i64);
+        }
+        if !block_time.is_empty() {
+            filter.insert("block_timestamp", block_time);
+        }
+
+        let options = FindOptions::builder()
+            .sort(doc! {"block_timestamp": -1, "recorded_at": -1})
+            .skip(skip)
+            .limit(limit)
+            .build();
+        let result = self.activity.find(filter).with_options(options).await;
+        match result {
+            Ok(mut cursor) => {
+                let mut records: Vec<ActivityRecord> = Vec::new();
+                while let Some(doc) = cursor.next().await {
+                    match doc {
+                        Ok(record) => records.push(record),
+                        Err(e) => return Err(DatabaseResponse::new(500, format!("{}", e))),
+                    }
+                }
+                Ok(records)
+            }
+            Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
+        }
+    }
+
+    /// Records that `notification` is about to be delivered. An existing entry is handed
+    /// back instead: to be delivered again if a crash left it `Pending`, or as `Delivered`
+    /// so the caller can decide whether it needs a follow-up.
+    pub async fn claim_notification(
+        &self,
+        notification: Notification,
+    ) -> Result<NotificationClaim, DatabaseResponse> {
+        match self.notifications.insert_one(notification.clone()).await {
+            Ok(_) => Ok(NotificationClaim::Deliver(notification)),
+            Err(err) if is_duplicate_key(&err) => {
+                let filter = try_or_return_string!(notification.ledger_key());
+                match self.notifications.find_one(filter).await {
+                    Ok(Some(existing)) if existing.status == NotificationStatus::Sent => {
+                        Ok(NotificationClaim::Delivered(existing))
+                    }
+                    Ok(Some(existing)) => Ok(NotificationClaim::Deliver(existing)),
+                    Ok(None) => Err(DatabaseResponse::new(
+                        500,
+                        "Notification ledger entry disappeared".to_string(),
+                    )),
+                    Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
+                }
+            }
+            Err(e) => Err(DatabaseResponse::new(
+                500,
+                format!("Error recording notification: {}", e),
+            )),
+        }
+    }
+
+    /// Moves a `PreConfirmed` entry to `stage` with the `activity` its follow-up reports.
+    /// The follow-up is left pending delivery, or recorded as sent when `deliver` is false
+    /// because the user doesn't want it. Returns `None` if the entry already moved on, so
+    /// only one caller sends the follow-up.
+    pub async fn advance_notification(
+        &self,
+        notification_id: String,
+        stage: NotificationStage,
+        activity: &Activity,
+        deliver: bool,
+    ) -> Result<Option<Notification>, DatabaseResponse> {
+        let stage = try_or_return_string!(to_bson(&stage));
+        let mut update = doc! {
+            "stage": stage,
+            "status": "Pending",
+            "follow_up": true,
+            "activity": try_or_return_string!(to_bson(activity)),
+            "deliveries": [],
+            "retry_at": null,
+        };
+        if !deliver {
+            update.insert("status", "Sent");
+            update.insert("sent_at", DateTime::now());
+        }
+        let result = self
+            .notifications
+            .find_one_and_update(
+                doc! {"notification_id": notification_id, "stage": "PreConfirmed"},
+                doc! {"$set": update},
+            )
+            .return_document(ReturnDocument::After)
+            .await;
+        match result {
+            Ok(notification) => Ok(notification),
+            Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
+        }
+    }
+
+    /// Pre-confirmed alerts on `network` that never got a follow-up, only those older than
+    /// `before` when set.
+    pub async fn get_pre_confirmed(
+        &self,
+        network: Network,
+        before: Option<DateTime>,
+    ) -> Result<Vec<Notification>, DatabaseResponse> {
+        let mut filter = doc! {
+            "network": try_or_return_string!(network.as_str()),
+            "stage": "PreConfirmed",
+        };
+        if let Some(before) = before {
+            filter.insert("created_at", doc! {"$lt": before});
+        }
+        let result = self.notifications.find(filter).await;
+        match result {
+            Ok(mut cursor) => {
+                let mut notifications: Vec<Notification> = Vec::new();
+                while let Some(doc) = cursor.next().await {
+                    match doc {
+                        Ok(notification) => notifications.push(notification),
+                        Err(e) => return Err(DatabaseResponse::new(500, format!("{}", e))),
+                    }
+                }
+                Ok(notifications)
+            }
+            Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
+        }
+    }
+
+    /// Saves the per-channel outcomes of a delivery. The entry is `Sent` once every channel
+    /// has it, otherwise it stays `Pending` until its `retry_at`.
+    pub async fn record_notification_deliveries(
+        &self,
+        notification: &Notification,
+    ) -> Result<UpdateResult, DatabaseResponse> {
+        let mut update = doc! {
+            "deliveries": try_or_return_string!(to_bson(&notification.deliveries)),
+            "retry_at": notification.next_retry(),
+        };
+        if notification.is_delivered() {
+            update.insert("status", "Sent");
+            update.insert("sent_at", DateTime::now());
+        }
+        let result = try_or_return_string!(
+            self.notifications
+                .update_one(
+                    doc! {"notification_id": notification.notification_id.clone()},
+                    doc! {"$set": update},
+                )
+                .await
+        );
+        Ok(result)
+    }
+
+    /// Claims the pending notification whose failed channels are due the earliest, moving
+    /// its `retry_at` `lease_secs` ahead so only one caller retries it.
+    pub async fn claim_notification_retry(
+        &self,
+        lease_secs: i64,
+    ) -> Result<Option<Notification>, DatabaseResponse> {
+        let now = DateTime::now();
+        let lease_end = DateTime::from_millis(now.timestamp_millis() + lease_secs * 1000);
+        let result = self
+            .notifications
+            .find_one_and_update(
+                doc! {"status": "Pending", "retry_at": {"$lte": now}},
+                doc! {"$set": {"retry_at": lease_end}},
+            )
+            .sort(doc! {"retry_at": 1})
+            .return_document(ReturnDocument::After)
+            .await;
+        match result {
+            Ok(notification) => Ok(notification),
+            Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
+        }
+    }
+
+    pub async fn insert_dead_letter(
+        &self,
+        delivery: WebhookDelivery,
+    ) -> Result<InsertOneResult, DatabaseResponse> {
+        match self.dead_letters.insert_one(delivery).await {
+            Ok(result) => Ok(result),
+            Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
+        }
+    }
+
+    pub async fn enqueue_webhook_delivery(
+        &self,
+        delivery: WebhookDelivery,
+    ) -> Result<InsertOneResult, DatabaseResponse> {
+        match self.webhook_queue.insert_one(delivery).await {
+            Ok(result) => Ok(result),
+            Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
+        }
+    }
+
+    /// Claims the delivery due the earliest. Its `retry_at` moves `lease_secs` ahead, so a
+    /// delivery whose worker died is picked up again once the lease runs out.
+    pub async fn claim_next_webhook_delivery(
+        &self,
+        lease_secs: i64,
+    ) -> Result<Option<WebhookDelivery>, DatabaseResponse> {
+        let now = DateTime::now();
+        let lease_end = DateTime::from_millis(now.timestamp_millis() + lease_secs * 1000);
+        let result = self
+            .webhook_queue
+            .find_one_and_update(
+                doc! {"retry_at": {"$lte": now}},
+                doc! {"$set": {"retry_at": lease_end, "claimed_at": now}},
+            )
+            .sort(doc! {"retry_at": 1})
+            .return_document(ReturnDocument::After)
+            .await;
+        match result {
+            Ok(delivery) => Ok(delivery),
+            Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
+        }
+    }
+
+    /// Saves a failed attempt of a pending delivery, to be retried at its `retry_at`.
+    pub async fn reschedule_webhook_delivery(
+        &self,
+        delivery: WebhookDelivery,
+    ) -> Result<UpdateResult, DatabaseResponse> {
+        let result = try_or_return_string!(
+            self.webhook_queue
+                .replace_one(doc! {"delivery_id": delivery.delivery_id.clone()}, delivery)
+                .await
+        );
+        Ok(result)
+    }
+
+    pub async fn delete_webhook_delivery(
+        &self,
+        delivery_id: String,
+    ) -> Result<DeleteResult, DatabaseResponse> {
+        let result = try_or_return_string!(
+            self.webhook_queue
+                .delete_one(doc! {"delivery_id": delivery_id})
+                .await
+        );
+        Ok(result)
+    }
+
+    pub async fn get_dead_letters(&self) -> Result<Vec<WebhookDelivery>, DatabaseResponse> {
+        let result = self.dead_letters.find(doc! {}).await;
+        match result {
+            Ok(mut cursor) => {
+                let mut deliveries: Vec<WebhookDelivery> = Vec::new();
+                while let Some(doc) = cursor.next().await {
+                    match doc {
+                        Ok(delivery) => deliveries.push(delivery),
+                        Err(e) => return Err(DatabaseResponse::new(500, format!("{}", e))),
+                    }
+                }
+                Ok(deliveries)
+            }
+            Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
+        }
+    }
+
+    pub async fn get_dead_letter(
+        &self,
+        delivery_id: String,
+    ) -> Result<WebhookDelivery, DatabaseResponse> {
+        let result = self
+            .dead_letters
+            .find_one(doc! {"delivery_id": delivery_id})
+            .await;
+        match result {
+            Ok(Some(delivery)) => Ok(delivery),
+            Ok(None) => Err(DatabaseResponse::new(
+                404,
+                "Webhook delivery not found".to_string(),
+            )),
+            Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
+        }
+    }
+
+    pub async fn update_dead_letter(
+        &self,
+        delivery: WebhookDelivery,
+    ) -> Result<WebhookDelivery, DatabaseResponse> {
+        let result = self
+            .dead_letters
+            .replace_one(
+                doc! {"delivery_id": delivery.delivery_id.clone()},
+                delivery.clone(),
+            )
+            .await;
+        match result {
+            Ok(_) => Ok(delivery),
+            Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
+        }
+    }
+
+    pub async fn delete_dead_letter(
+        &self,
+        delivery_id: String,
+    ) -> Result<DeleteResult, DatabaseResponse> {
+        match self
+            .dead_letters
+            .delete_one(doc! {"delivery_id": delivery_id})
+            .await
+        {
+            Ok(result) => Ok(result),
+            Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
+        }
+    }
+
+    pub async fn enqueue_mail(
+        &self,
+        mail: QueuedMail,
+    ) -> Result<InsertOneResult, DatabaseResponse> {
+        match self.mail_queue.insert_one(mail).await {
+            Ok(result) => Ok(result),
+            Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
+        }
+    }
+
+    /// Atomically moves the oldest due mail to `Sending` so only one worker picks it up.
+    /// Mail claimed more than `lease_secs` ago and still `Sending` is claimed again, as its
+    /// worker is assumed to have died or hung mid-send.
+    pub async fn claim_next_mail(
+        &self,
+        lease_secs: i64,
+    ) -> Result<Option<QueuedMail>, DatabaseResponse> {
+        let now = DateTime::now();
+        let claimed_before = DateTime::from_millis(now.timestamp_millis() - lease_secs * 1000);
+        let result = self
+            .mail_queue
+            .find_one_and_update(
+                doc! {"$or": [
+                    {"status": "Queued", "next_attempt_at": {"$lte": now}},
+                    {"status": "Sending", "claimed_at": {"$lt": claimed_before}},
+                    {"status": "Sending", "claimed_at": null},
+                ]},
+                doc! {"$set": {"status": "Sending", "claimed_at": now}, "$inc": {"attempts": 1}},
+            )
+            .sort(doc! {"next_attempt_at": 1})
+            .return_document(ReturnDocument::After)
+            .await;
+        match result {
+            Ok(mail) => Ok(mail),
+            Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
+        }
+    }
+
+    pub async fn mark_mail_sent(&self, mail_id: String) -> Result<UpdateResult, DatabaseResponse> {
+        let result = try_or_return_string!(
+            self.mail_queue
+                .update_one(
+                    doc! {"mail_id": mail_id},
+                    doc! {"$set": {"status": "Sent", "sent_at": DateTime::now(), "last_error": null}},
+                )
+                .await
+        );
+        Ok(result)
+    }
+
+    /// Records a failed attempt; the mail is retried at `retry_at`, or marked failed when `None`.
+    pub async fn mark_mail_failed(
+        &self,
+        mail_id: String,
+        error: String,
+        retry_at: Option<DateTime>,
+    ) -> Result<UpdateResult, DatabaseResponse> {
+        let update = match retry_at {
+            Some(retry_at) => {
+                doc! {"$set": {"status": "Queued", "last_error": error, "next_attempt_at": retry_at}}
+            }
+            None => doc! {"$set": {"status": "Failed", "last_error": error}},
+        };
+        let result = try_or_return_string!(
+            self.mail_queue
+                .update_one(doc! {"mail_id": mail_id}, update)
+                .await
+        );
+        Ok(result)
+    }
+
+    pub async fn get_mail_by_status(
+        &self,
+        status: MailStatus,
+    ) -> Result<Vec<QueuedMail>, DatabaseResponse> {
+        let status = try_or_return_string!(to_bson(&status));
+        let result = self
+            .mail_queue
+            .find(doc! {"status": status})
+            .sort(doc! {"created_at": -1})
+            .await;
+        match result {
+            Ok(mut cursor) => {
+                let mut mails: Vec<QueuedMail> = Vec::new();
+                while let Some(doc) = cursor.next().await {
+                    match doc {
+                        Ok(mail) => mails.push(mail),
+                        Err(e) => return Err(DatabaseResponse::new(500, format!("{}", e))),
+                    }
+                }
+                Ok(mails)
+            }
+            Err(e) => Err(DatabaseResponse::new(500, format!("{}", e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::{to_document, Bson};
+
+    const ADDRESS: &str = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+
+    fn user(network: &str) -> User {
+        let wallet = Wallet::new(ADDRESS.to_string(), network.to_string()).unwrap();
+        User::new(
+            "Ada".to_string(),
+            format!("{network}@example.com"),
+            vec![wallet],
+        )
+        .unwrap()
+    }
+
+    /// Evaluates a `wallets` `$elemMatch` filter the way MongoDB would.
+    fn matches(filter: &Document, user: &User) -> bool {
+        let conditions = filter
+            .get_document("wallets")
+            .unwrap()
+            .get_document("$elemMatch")
+            .unwrap();
+        let user = to_document(user).unwrap();
+        user.get_array("wallets")
+            .unwrap()
+            .iter()
+            .any(|wallet| match wallet {
+                Bson::Document(wallet) => conditions
+                    .iter()
+                    .all(|(key, value)| wallet.get(key) == Some(value)),
+                _ => false,
+            })
+    }
+
+    #[test]
+    fn wallet_lookups_are_scoped_to_the_network() {
+        let on_ethereum = user("Ethereum");
+        let on_base = user("Base");
+
+        let filter = wallet_filter(Some(&Network::Base), ADDRESS.to_string()).unwrap();
+        assert!(matches(&filter, &on_base));
+        assert!(!matches(&filter, &on_ethereum));
+
+        let filter = wallet_filter(Some(&Network::Ethereum), ADDRESS.to_uppercase()).unwrap();
+        assert!(!matches(&filter, &on_base));
+        assert!(matches(&filter, &on_ethereum));
+    }
+
+    #[test]
+    fn wallet_lookups_without_a_network_match_any() {
+        let filter = wallet_filter(None, ADDRESS.to_string()).unwrap();
+        assert_eq!(
+            filter,
+            doc! {"wallets.wallet_address": "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"}
+        );
+    }
 }
@@ -0,0 +1,105 @@
+use crate::models::mail_model::QueuedMail;
+use crate::services::db::Database;
+use crate::services::mail_transport::MailTransport;
+use crate::services::mailer::send_queued_mail;
+use dotenv::dotenv;
+use mongodb::bson::DateTime;
+use std::env;
+use std::sync::OnceLock;
+use tokio::sync::Notify;
+use tokio::time::{timeout, Duration};
+
+const DEFAULT_MAIL_WORKERS: usize = 2;
+const MAX_ATTEMPTS: u32 = 5;
+const RETRY_BASE_SECS: i64 = 30;
+const RETRY_MAX_SECS: i64 = 3_600;
+// Mail claimed longer ago than this is assumed abandoned by its worker and claimed again.
+const CLAIM_LEASE_SECS: i64 = 300;
+// Fallback poll for retries coming due and mail queued by another instance.
+const POLL_INTERVAL_SECS: u64 = 2;
+
+static MAIL_QUEUED: OnceLock<Notify> = OnceLock::new();
+
+fn mail_queued() -> &'static Notify {
+    MAIL_QUEUED.get_or_init(Notify::new)
+}
+
+/// Wakes an idle worker after a mail is queued.
+pub fn wake_mail_workers() {
+    mail_queued().notify_one();
+}
+
+/// Starts `MAIL_WORKERS` workers (default 2).
+pub async fn start_mail_workers(db: Database) {
+    dotenv().ok();
+    let workers = env::var("MAIL_WORKERS")
+        .ok()
+        .and_then(|workers| workers.parse::<usize>().ok())
+        .filter(|workers| *workers > 0)
+        .unwrap_or(DEFAULT_MAIL_WORKERS);
+
+    // Surface a bad transport config at startup rather than on the first mail.
+    if let Err(e) = MailTransport::from_env() {
+        println!("Mail transport is misconfigured, sends fail until it is fixed: {e}");
+    }
+
+    for worker in 0..workers {
+        tokio::spawn(run_mail_worker(worker, db.clone()));
+    }
+}
+
+async fn run_mail_worker(worker: usize, db: Database) {
+    loop {
+        match db.claim_next_mail(CLAIM_LEASE_SECS).await {
+            Ok(Some(mail)) => deliver(worker, mail, &db).await,
+            Ok(None) => {
+                let _ = timeout(
+                    Duration::from_secs(POLL_INTERVAL_SECS),
+                    mail_queued().notified(),
+                )
+                .await;
+            }
+            Err(e) => {
+                println!("Mail worker {worker}: error claiming mail: {:?}", e);
+                tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+            }
+        }
+    }
+}
+
+async fn deliver(worker: usize, mail: QueuedMail, db: &Database) {
+    let result = match send_queued_mail(&mail).await {
+        Ok(()) => {
+            println!("Mail worker {worker}: sent {} to {}", mail.mail_id, mail.to);
+            db.mark_mail_sent(mail.mail_id).await
+        }
+        Err(err) => {
+            let retry_at = retry_at(mail.attempts);
+            match retry_at {
+                Some(_) => println!(
+                    "Mail worker {worker}: {} failed (attempt {}/{MAX_ATTEMPTS}), retrying: {err}",
+                    mail.mail_id, mail.attempts
+                ),
+                None => println!(
+                    "Mail worker {worker}: {} failed {} times, giving up: {err}",
+                    mail.mail_id, mail.attempts
+                ),
+            }
+            db.mark_mail_failed(mail.mail_id, err, retry_at).await
+        }
+    };
+    if let Err(e) = result {
+        println!("Mail worker {worker}: error updating mail status: {:?}", e);
+    }
+}
+
+/// When to retry after `attempts` failed attempts, or `None` once they are used up.
+fn retry_at(attempts: u32) -> Option<DateTime> {
+    if attempts >= MAX_ATTEMPTS {
+        return None;
+    }
+    let delay = (RETRY_BASE_SECS << attempts.saturating_sub(1)).min(RETRY_MAX_SECS);
+    Some(DateTime::from_millis(
+        DateTime::now().timestamp_millis() + delay * 1000,
+    ))
+}
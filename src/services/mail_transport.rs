@@ -0,0 +1,167 @@
+use dotenv::dotenv;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::{Credentials, Mechanism};
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::{AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::env;
+
+const DEFAULT_SMTP_HOST: &str = "smtp.gmail.com";
+const DEFAULT_MAIL_DIR: &str = "mail";
+
+/// How the SMTP connection is secured, from `SMTP_TLS`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SmtpTls {
+    /// TLS from the first byte, usually port 465.
+    Implicit,
+    /// Plain connection upgraded with STARTTLS, usually port 587.
+    StartTls,
+    /// No encryption, for local relays and stub servers.
+    None,
+}
+
+impl SmtpTls {
+    pub fn from_str(tls: String) -> Result<Self, String> {
+        let tls = match tls.to_lowercase().as_str() {
+            "implicit" | "tls" | "ssl" => SmtpTls::Implicit,
+            "starttls" => SmtpTls::StartTls,
+            "none" => SmtpTls::None,
+            _ => {
+                return Err(format!(
+                    "Invalid SMTP_TLS {tls}, expected implicit, starttls or none"
+                ))
+            }
+        };
+        Ok(tls)
+    }
+
+    fn default_port(&self) -> u16 {
+        match self {
+            SmtpTls::Implicit => 465,
+            SmtpTls::StartTls => 587,
+            SmtpTls::None => 25,
+        }
+    }
+}
+
+fn parse_mechanism(mechanism: &str) -> Result<Mechanism, String> {
+    match mechanism.trim().to_lowercase().as_str() {
+        "plain" => Ok(Mechanism::Plain),
+        "login" => Ok(Mechanism::Login),
+        "xoauth2" => Ok(Mechanism::Xoauth2),
+        _ => Err(format!(
+            "Invalid SMTP_AUTH mechanism {mechanism}, expected plain, login or xoauth2"
+        )),
+    }
+}
+
+fn optional_env(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|value| !value.trim().is_empty())
+}
+
+/// Where outbound mail goes, picked by `MAIL_TRANSPORT`: `smtp` (default), `file` or `stdout`.
+pub enum MailTransport {
+    Smtp(AsyncSmtpTransport<Tokio1Executor>),
+    /// Writes each mail as an `.eml` file under `MAIL_DIR`, for development.
+    File(AsyncFileTransport<Tokio1Executor>),
+    /// Prints each mail, for development.
+    Stdout,
+}
+
+impl MailTransport {
+    pub fn from_env() -> Result<Self, String> {
+        dotenv().ok();
+        let transport = optional_env("MAIL_TRANSPORT").unwrap_or_else(|| "smtp".to_string());
+        match transport.to_lowercase().as_str() {
+            "smtp" => Ok(MailTransport::Smtp(smtp_from_env()?)),
+            "file" => {
+                let dir = optional_env("MAIL_DIR").unwrap_or_else(|| DEFAULT_MAIL_DIR.to_string());
+                std::fs::create_dir_all(&dir)
+                    .map_err(|e| format!("Could not create mail directory {dir}: {e}"))?;
+                Ok(MailTransport::File(AsyncFileTransport::new(dir)))
+            }
+            "stdout" => Ok(MailTransport::Stdout),
+            _ => Err(format!(
+                "Invalid MAIL_TRANSPORT {transport}, expected smtp, file or stdout"
+            )),
+        }
+    }
+
+    pub async fn send(&self, email: Message) -> Result<(), String> {
+        match self {
+            MailTransport::Smtp(transport) => match transport.send(email).await {
+                Ok(_) => Ok(()),
+                Err(e) => Err(format!("Could not send email: {e}")),
+            },
+            MailTransport::File(transport) => match transport.send(email).await {
+                Ok(_) => Ok(()),
+                Err(e) => Err(format!("Could not write email: {e}")),
+            },
+            MailTransport::Stdout => {
+                println!("{}", String::from_utf8_lossy(&email.formatted()));
+                Ok(())
+            }
+        }
+    }
+}
+
+/// SMTP relay from `SMTP_HOST`, `SMTP_PORT`, `SMTP_TLS` and `SMTP_AUTH`. Gmail over
+/// implicit TLS when unset; authenticates only when `SMTP_USERNAME` is set.
+fn smtp_from_env() -> Result<AsyncSmtpTransport<Tokio1Executor>, String> {
+    let host = optional_env("SMTP_HOST").unwrap_or_else(|| DEFAULT_SMTP_HOST.to_string());
+    let tls = match optional_env("SMTP_TLS") {
+        Some(tls) => SmtpTls::from_str(tls)?,
+        None => SmtpTls::Implicit,
+    };
+    let port = match optional_env("SMTP_PORT") {
+        Some(port) => port
+            .parse::<u16>()
+            .map_err(|_| format!("Invalid SMTP_PORT {port}"))?,
+        None => tls.default_port(),
+    };
+
+    let tls = match tls {
+        SmtpTls::None => Tls::None,
+        secured => {
+            let parameters = TlsParameters::new(host.clone())
+                .map_err(|e| format!("Could not set up TLS for {host}: {e}"))?;
+            if secured == SmtpTls::Implicit {
+                Tls::Wrapper(parameters)
+            } else {
+                Tls::Required(parameters)
+            }
+        }
+    };
+
+    let mut builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+        .port(port)
+        .tls(tls);
+    if let Some(username) = optional_env("SMTP_USERNAME") {
+        let password = env::var("SMTP_PASSWORD")
+            .map_err(|_| "SMTP_PASSWORD must be set with SMTP_USERNAME".to_string())?;
+        builder = builder.credentials(Credentials::new(username, password));
+    }
+    if let Some(mechanisms) = optional_env("SMTP_AUTH") {
+        let mechanisms = mechanisms
+            .split(',')
+            .map(parse_mechanism)
+            .collect::<Result<Vec<Mechanism>, String>>()?;
+        builder = builder.authentication(mechanisms);
+    }
+    Ok(builder.build())
+}
+
+/// Sender mailbox from `MAIL_FROM` and an optional `MAIL_FROM_NAME`, falling back to
+/// `SMTP_USERNAME`.
+pub fn sender_from_env() -> Result<Mailbox, String> {
+    dotenv().ok();
+    let address = optional_env("MAIL_FROM")
+        .or_else(|| optional_env("SMTP_USERNAME"))
+        .ok_or("MAIL_FROM or SMTP_USERNAME must be set")?;
+    let mut sender: Mailbox = address
+        .parse()
+        .map_err(|e| format!("Invalid sender address {address}: {e}"))?;
+    if let Some(name) = optional_env("MAIL_FROM_NAME") {
+        sender.name = Some(name);
+    }
+    Ok(sender)
+}
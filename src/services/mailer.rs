@@ -1,83 +1,514 @@
-use crate::crawlers::starknet_crawler::build_address;
+use crate::models::activity_model::{Activity, ExecutionDetails};
+use crate::models::balance_model::{BalanceCrossing, BalanceWatch};
+use crate::models::mail_model::QueuedMail;
 use crate::models::network_model::Network;
-use crate::utils::mail_structure::write_mail;
-
-use super::db::Database;
-use dotenv::dotenv;
-use lettre::message::header::ContentType;
-use lettre::transport::smtp::authentication::Credentials;
-use lettre::{Message, SmtpTransport, Transport};
-use std::env;
-
-pub fn send_mail(receivers_email: String, message_body: String) {
-    dotenv().ok();
-    let smtp_username = env::var("SMTP_USERNAME").expect("SMTP_USERNAME not found");
-    let smtp_password = env::var("SMTP_PASSWORD").expect("SMTP_PASSWORD not found");
-
-    let email = Message::builder()
-        .from(smtp_username.parse().unwrap())
-        .reply_to(smtp_username.parse().unwrap())
-        .to(receivers_email.parse().unwrap())
-        .subject("Transaction Notification")
-        .header(ContentType::TEXT_PLAIN)
-        .body(message_body)
-        .unwrap();
-
-    let creds = Credentials::new(smtp_username.to_owned(), smtp_password.to_owned());
-
-    // Open a remote connection to gmail
-    let mailer = SmtpTransport::relay("smtp.gmail.com")
-        .unwrap()
-        .credentials(creds)
-        .build();
-
-    // Send the email
-    match mailer.send(&email) {
-        Ok(_) => println!("Email sent successfully!"),
-        Err(e) => panic!("Could not send email: {e:?}"),
+use crate::models::notification_model::{Notification, NotificationPriority, NotificationStage};
+use crate::models::user_model::User;
+use crate::utils::mail_structure::{render_mail, ActivityMail, BalanceMail};
+
+use super::db::{Database, DatabaseResponse, NotificationClaim};
+use super::mail_queue::wake_mail_workers;
+use super::mail_transport::{sender_from_env, MailTransport};
+use super::notifier::{notify_user, OutgoingNotification};
+use alloy::primitives::U256;
+use lettre::message::header::{ContentType, HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::Message;
+use mongodb::bson::DateTime;
+use serde_json::json;
+use std::sync::OnceLock;
+use tokio::time::{sleep, Duration};
+
+const RETRY_INTERVAL_SECS: u64 = 30;
+// Long enough for one delivery to every channel before another caller may retry it.
+const RETRY_LEASE_SECS: i64 = 120;
+
+static MAIL_TRANSPORT: OnceLock<MailTransport> = OnceLock::new();
+
+/// Persists the mail in the outbound queue; the mail workers send it.
+pub async fn queue_mail(
+    db: &Database,
+    receivers_email: String,
+    subject: &str,
+    message_body: String,
+    html_body: Option<String>,
+    high_priority: bool,
+) -> Result<(), String> {
+    let mail = QueuedMail::new(
+        receivers_email,
+        subject.to_string(),
+        message_body,
+        html_body,
+        high_priority,
+    );
+    db.enqueue_mail(mail).await.map_err(|e| e.message)?;
+    wake_mail_workers();
+    Ok(())
+}
+
+/// The configured transport, built on first use so a misconfiguration is retried after
+/// the environment is fixed.
+fn mail_transport() -> Result<&'static MailTransport, String> {
+    if let Some(transport) = MAIL_TRANSPORT.get() {
+        return Ok(transport);
+    }
+    let transport = MailTransport::from_env()?;
+    Ok(MAIL_TRANSPORT.get_or_init(|| transport))
+}
+
+fn build_message(mail: &QueuedMail) -> Result<Message, String> {
+    let sender = sender_from_env()?;
+    let receiver: Mailbox = mail
+        .to
+        .parse()
+        .map_err(|e| format!("Invalid receiver address {}: {e}", mail.to))?;
+
+    let builder = Message::builder()
+        .from(sender.clone())
+        .reply_to(sender)
+        .to(receiver)
+        .subject(mail.subject.clone());
+    let mut email = match &mail.html {
+        Some(html) => builder.multipart(MultiPart::alternative_plain_html(
+            mail.body.clone(),
+            html.clone(),
+        )),
+        None => builder
+            .header(ContentType::TEXT_PLAIN)
+            .body(mail.body.clone()),
+    }
+    .map_err(|e| format!("Could not build email: {e}"))?;
+    if mail.high_priority {
+        let headers = email.headers_mut();
+        headers.insert_raw(HeaderValue::new(
+            HeaderName::new_from_ascii_str("X-Priority"),
+            "1".to_string(),
+        ));
+        headers.insert_raw(HeaderValue::new(
+            HeaderName::new_from_ascii_str("Importance"),
+            "high".to_string(),
+        ));
     }
+    Ok(email)
 }
 
-pub async fn process_transactions_to_mail(
-    transactions: Vec<&serde_json::Value>,
-    blockchain: Network,
-    db: Database,
+/// Sends a queued mail once through the configured transport.
+pub async fn send_queued_mail(mail: &QueuedMail) -> Result<(), String> {
+    let email = build_message(mail)?;
+    mail_transport()?.send(email).await
+}
+
+pub async fn process_transactions_to_mail(activities: Vec<Activity>, db: Database) {
+    for activity in activities {
+        let stage = if activity.is_reverted() {
+            NotificationStage::Reverted
+        } else {
+            NotificationStage::Confirmed
+        };
+        notify_wallet_users(&activity, stage, &db).await;
+    }
+}
+
+/// Early alerts for activity in the pending block. The confirmed block later sends the
+/// follow-up.
+pub async fn process_pending_transactions_to_mail(activities: Vec<Activity>, db: Database) {
+    for activity in activities {
+        notify_wallet_users(&activity, NotificationStage::PreConfirmed, &db).await;
+    }
+}
+
+pub async fn notify_wallet_users(activity: &Activity, stage: NotificationStage, db: &Database) {
+    match find_recipients(activity, db).await {
+        Ok(users) => {
+            for user in users {
+                if !user.wants_notification(activity) {
+                    continue;
+                }
+                let claim = db
+                    .claim_notification(Notification::new(
+                        activity,
+                        user.user_uuid.clone(),
+                        stage.clone(),
+                    ))
+                    .await;
+                let notification = match claim {
+                    // The user already had an early alert for this transaction, follow it up.
+                    Ok(NotificationClaim::Deliver(existing))
+                    | Ok(NotificationClaim::Delivered(existing))
+                        if existing.stage == NotificationStage::PreConfirmed
+                            && stage != NotificationStage::PreConfirmed =>
+                    {
+                        match db
+                            .advance_notification(
+                                existing.notification_id,
+                                stage.clone(),
+                                activity,
+                                true,
+                            )
+                            .await
+                        {
+                            Ok(Some(notification)) => notification,
+                            Ok(None) => continue,
+                            Err(e) => {
+                                println!("Error recording notification: {:?}", e);
+                                continue;
+                            }
+                        }
+                    }
+                    Ok(NotificationClaim::Deliver(notification)) => notification,
+                    Ok(NotificationClaim::Delivered(_)) => {
+                        println!("Notification already sent, skipping");
+                        continue;
+                    }
+                    Err(e) => {
+                        println!("Error recording notification: {:?}", e);
+                        continue;
+                    }
+                };
+
+                deliver_notification(&user, activity, &notification, db).await;
+            }
+        }
+        Err(e) => {
+            println!("Error finding users with wallet address: {:?}", e);
+        }
+    }
+}
+
+/// Users watching the wallet, or the contract, the activity is about. Contract watchers
+/// are only included when the interaction passes their event filters.
+async fn find_recipients(
+    activity: &Activity,
+    db: &Database,
+) -> Result<Vec<User>, DatabaseResponse> {
+    let interaction = match &activity.interaction {
+        Some(interaction) => interaction,
+        None => {
+            return db
+                .find_users_with_wallet_address(
+                    Some(activity.network.clone()),
+                    activity.wallet_address.clone(),
+                )
+                .await
+        }
+    };
+
+    let users = db
+        .find_users_watching_contract(activity.network.clone(), activity.wallet_address.clone())
+        .await?;
+    Ok(users
+        .into_iter()
+        .filter(|user| {
+            user.watched_contract(&activity.network, &activity.wallet_address)
+                .is_some_and(|watched| watched.matches(interaction))
+        })
+        .collect())
+}
+
+/// Follows up pre-confirmed alerts on `network` whose transaction landed in the block
+/// `block_number` but that no confirmed activity advanced: the user doesn't want the
+/// outcome, or it changed what was matched, like a reverted transfer that emitted no
+/// `Transfer` event. `execution` gives the outcome of a transaction in the block. The
+/// entries are resolved either way, and only delivered when the user wants the outcome.
+pub async fn resolve_pre_confirmed(
+    network: Network,
+    block_number: u128,
+    execution: impl Fn(&str) -> Option<ExecutionDetails>,
+    db: &Database,
 ) {
-    for transaction in transactions {
-        let transaction_hash = transaction["transaction_hash"].as_str().unwrap();
-        let wallet_address = build_address(
-            transaction["sender_address"]
-                .as_str()
-                .unwrap_or("0x0000000000000000000000")
-                .to_string()
-                .to_lowercase(),
-        );
-        let tx_url = format!("https://sepolia.starkscan.co/tx/{}", transaction_hash);
-        let network = blockchain.as_str().unwrap_or("Starknet".to_string());
+    let pending = match db.get_pre_confirmed(network, None).await {
+        Ok(pending) => pending,
+        Err(e) => {
+            println!("Error finding pending notifications: {:?}", e);
+            return;
+        }
+    };
 
-        match db
-            .find_users_with_wallet_address(wallet_address.clone())
+    for entry in pending {
+        let (execution, activity) = match (execution(&entry.transaction_hash), &entry.activity) {
+            (Some(execution), Some(activity)) => (execution, activity),
+            _ => continue,
+        };
+        let user = match db.get_user_via_id(entry.user_uuid.clone()).await {
+            Ok(user) => user,
+            Err(e) => {
+                println!("Error finding user for notification: {:?}", e);
+                continue;
+            }
+        };
+        let (stage, activity, deliver) = follow_up(&user, activity, block_number, execution);
+        let notification = match db
+            .advance_notification(entry.notification_id, stage, &activity, deliver)
             .await
         {
-            Ok(users) => {
-                for user in users {
-                    let name = user.name;
-                    let email_address = user.email;
-                    let email_body = write_mail(
-                        name.clone(),
-                        email_address.clone(),
-                        network.clone(),
-                        tx_url.clone(),
-                        wallet_address.clone(),
-                    );
-                    println!("SENDING EMAIL TO: {}", name.clone());
-                    send_mail(email_address.clone(), email_body);
-                }
+            Ok(Some(notification)) => notification,
+            Ok(None) => continue,
+            Err(e) => {
+                println!("Error recording notification: {:?}", e);
+                continue;
             }
+        };
+        if deliver {
+            deliver_notification(&user, &activity, &notification, db).await;
+        }
+    }
+}
+
+/// The follow-up of an alert on `pending` activity whose transaction was included in
+/// `block_number` with `execution`: its stage, the activity it reports and whether the
+/// user wants it.
+fn follow_up(
+    user: &User,
+    pending: &Activity,
+    block_number: u128,
+    execution: ExecutionDetails,
+) -> (NotificationStage, Activity, bool) {
+    let mut activity = pending.clone().with_execution(execution);
+    activity.block_number = block_number;
+    let stage = if activity.is_reverted() {
+        NotificationStage::Reverted
+    } else {
+        NotificationStage::Confirmed
+    };
+    let deliver = user.wants_notification(&activity);
+    (stage, activity, deliver)
+}
+
+/// Sends a `Dropped` follow-up for pre-confirmed alerts on `network` raised before `before`
+/// whose transaction never reached a scanned block.
+pub async fn notify_dropped_transactions(network: Network, before: DateTime, db: &Database) {
+    let stale = match db.get_pre_confirmed(network, Some(before)).await {
+        Ok(stale) => stale,
+        Err(e) => {
+            println!("Error finding pending notifications: {:?}", e);
+            return;
+        }
+    };
+
+    for entry in stale {
+        let activity = match &entry.activity {
+            Some(activity) => activity.clone(),
+            None => continue,
+        };
+        let notification = match db
+            .advance_notification(
+                entry.notification_id,
+                NotificationStage::Dropped,
+                &activity,
+                true,
+            )
+            .await
+        {
+            Ok(Some(notification)) => notification,
+            Ok(None) => continue,
             Err(e) => {
-                println!("Error finding users with wallet address: {:?}", e);
+                println!("Error recording notification: {:?}", e);
                 continue;
             }
+        };
+        match db.get_user_via_id(notification.user_uuid.clone()).await {
+            Ok(user) => deliver_notification(&user, &activity, &notification, db).await,
+            Err(e) => println!("Error finding user for notification: {:?}", e),
+        }
+    }
+}
+
+/// Sends the notification to the user's channels that do not have it yet and records how
+/// each one fared; failed channels are retried by `run_notification_retries`.
+async fn deliver_notification(
+    user: &User,
+    activity: &Activity,
+    notification: &Notification,
+    db: &Database,
+) {
+    let mail = match render_mail(
+        "activity",
+        &user.language,
+        &ActivityMail::new(user, activity, notification),
+    ) {
+        Ok(mail) => mail,
+        Err(e) => {
+            println!("Error rendering notification: {e}");
+            return;
+        }
+    };
+    let message = OutgoingNotification {
+        subject: mail.subject,
+        body: mail.text,
+        html: Some(mail.html),
+        high_priority: notification.priority == NotificationPriority::High,
+        data: json!({
+            "notification": notification,
+            "activity": activity,
+        }),
+    };
+
+    let mut notification = notification.clone();
+    let channels = user.notification_channels();
+    // Forget channels the user has removed since the last attempt.
+    notification.deliveries.retain(|delivery| {
+        channels
+            .iter()
+            .any(|channel| channel.id() == delivery.channel)
+    });
+    let pending = channels
+        .into_iter()
+        .filter(|channel| notification.is_pending_for(&channel.id()))
+        .collect();
+    for (channel, result) in notify_user(user, pending, &message, db).await {
+        notification.record_delivery(channel.id(), result);
+    }
+    if let Err(e) = db.record_notification_deliveries(&notification).await {
+        println!("Error recording notification deliveries: {:?}", e);
+    }
+}
+
+/// Retries notifications on the channels that failed them, each `RETRY_INTERVAL_SECS`.
+pub async fn run_notification_retries(db: Database) {
+    loop {
+        loop {
+            let notification = match db.claim_notification_retry(RETRY_LEASE_SECS).await {
+                Ok(Some(notification)) => notification,
+                Ok(None) => break,
+                Err(e) => {
+                    println!("Error finding notifications to retry: {:?}", e);
+                    break;
+                }
+            };
+            let activity = match &notification.activity {
+                Some(activity) => activity.clone(),
+                None => continue,
+            };
+            match db.get_user_via_id(notification.user_uuid.clone()).await {
+                Ok(user) => deliver_notification(&user, &activity, &notification, &db).await,
+                Err(e) => println!("Error finding user for notification: {:?}", e),
+            }
         }
+        sleep(Duration::from_secs(RETRY_INTERVAL_SECS)).await;
+    }
+}
+
+pub async fn notify_balance_crossing(
+    db: &Database,
+    user: &User,
+    watch: &BalanceWatch,
+    balance: U256,
+    crossing: BalanceCrossing,
+) {
+    let mail = match render_mail(
+        "balance",
+        &user.language,
+        &BalanceMail::new(user, watch, balance, &crossing),
+    ) {
+        Ok(mail) => mail,
+        Err(e) => {
+            println!("Error rendering balance alert: {e}");
+            return;
+        }
+    };
+    let message = OutgoingNotification {
+        subject: mail.subject,
+        body: mail.text,
+        html: Some(mail.html),
+        high_priority: false,
+        data: json!({
+            "balance_watch": watch,
+            "balance": balance.to_string(),
+        }),
+    };
+    // Balance alerts are not kept in the ledger, so failed channels are only logged.
+    notify_user(user, user.notification_channels(), &message, db).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::activity_model::{Direction, TokenTransfer};
+    use crate::models::user_model::NotifyOn;
+
+    fn user(notify_on: NotifyOn) -> User {
+        let mut user =
+            User::new("Ada".to_string(), "ada@example.com".to_string(), Vec::new()).unwrap();
+        user.notify_on = notify_on;
+        user
+    }
+
+    /// An incoming transfer seen in the pending block, before its outcome is known.
+    fn pending_transfer() -> Activity {
+        Activity::new(
+            Network::Starknet,
+            0,
+            "0x1234".to_string(),
+            "0x0abc".to_string(),
+            Direction::Incoming,
+        )
+        .with_transfer(
+            0,
+            TokenTransfer {
+                token_address: "0x049d".to_string(),
+                amount: "1000".to_string(),
+                counterparty: "0x0def".to_string(),
+            },
+        )
+    }
+
+    fn execution(status: &str) -> ExecutionDetails {
+        ExecutionDetails {
+            execution_status: status.to_string(),
+            finality_status: Some("ACCEPTED_ON_L2".to_string()),
+            actual_fee: None,
+            revert_reason: None,
+        }
+    }
+
+    #[test]
+    fn reverted_transactions_resolve_as_reverted() {
+        // The revert undid the transfer, so the block has no Transfer event to match the
+        // alert with; its outcome comes from the transaction itself.
+        let (stage, activity, deliver) = follow_up(
+            &user(NotifyOn::All),
+            &pending_transfer(),
+            42,
+            execution("REVERTED"),
+        );
+        assert_eq!(stage, NotificationStage::Reverted);
+        assert!(activity.is_reverted());
+        assert_eq!(activity.block_number, 42);
+        assert_eq!(activity.transfer, pending_transfer().transfer);
+        assert!(deliver);
+    }
+
+    #[test]
+    fn unwanted_outcomes_are_resolved_without_delivery() {
+        let (stage, _, deliver) = follow_up(
+            &user(NotifyOn::SuccessOnly),
+            &pending_transfer(),
+            42,
+            execution("REVERTED"),
+        );
+        assert_eq!(stage, NotificationStage::Reverted);
+        assert!(!deliver);
+
+        let (stage, _, deliver) = follow_up(
+            &user(NotifyOn::FailureOnly),
+            &pending_transfer(),
+            42,
+            execution("SUCCEEDED"),
+        );
+        assert_eq!(stage, NotificationStage::Confirmed);
+        assert!(!deliver);
+    }
+
+    #[test]
+    fn wanted_outcomes_are_delivered() {
+        let (stage, activity, deliver) = follow_up(
+            &user(NotifyOn::SuccessOnly),
+            &pending_transfer(),
+            42,
+            execution("SUCCEEDED"),
+        );
+        assert_eq!(stage, NotificationStage::Confirmed);
+        assert!(!activity.is_reverted());
+        assert!(deliver);
     }
 }
@@ -1,2 +1,8 @@
 pub mod db;
+pub mod mail_queue;
+pub mod mail_transport;
 pub mod mailer;
+pub mod notifier;
+pub mod rpc;
+pub mod subscription;
+pub mod webhook;
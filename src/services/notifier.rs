@@ -0,0 +1,332 @@
+use crate::models::channel_model::NotificationChannel;
+use crate::models::user_model::User;
+use crate::models::webhook_model::WebhookDelivery;
+use crate::services::db::Database;
+use crate::services::mailer::queue_mail;
+use crate::services::webhook::queue_delivery;
+use async_trait::async_trait;
+use dotenv::dotenv;
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::json;
+use std::env;
+use std::sync::OnceLock;
+use tokio::time::Duration;
+
+static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
+
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+const TELEGRAM_API_URL: &str = "https://api.telegram.org";
+// Discord rejects messages longer than this.
+const DISCORD_MAX_CONTENT: usize = 2000;
+
+pub fn http_client() -> Client {
+    HTTP_CLIENT.get_or_init(Client::new).clone()
+}
+
+/// A notification rendered once and delivered through any channel.
+#[derive(Serialize, Debug, Clone)]
+pub struct OutgoingNotification {
+    pub subject: String,
+    pub body: String,
+    /// HTML alternative of `body`, only sent by email.
+    #[serde(skip)]
+    pub html: Option<String>,
+    pub high_priority: bool,
+    /// Structured details for channels that take JSON, such as the notification and its
+    /// activity.
+    pub data: serde_json::Value,
+}
+
+impl OutgoingNotification {
+    fn text(&self) -> String {
+        format!("{}\n{}", self.subject, self.body.trim())
+    }
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send(&self, user: &User, notification: &OutgoingNotification) -> Result<(), String>;
+}
+
+/// Queues the mail; the mail workers send it and retry failures.
+pub struct EmailNotifier {
+    pub db: Database,
+}
+
+#[async_trait]
+impl Notifier for EmailNotifier {
+    async fn send(&self, user: &User, notification: &OutgoingNotification) -> Result<(), String> {
+        queue_mail(
+            &self.db,
+            user.email.clone(),
+            &notification.subject,
+            notification.body.clone(),
+            notification.html.clone(),
+            notification.high_priority,
+        )
+        .await
+    }
+}
+
+/// Queues the delivery; the webhook workers sign and post it, retry failures and
+/// dead-letter it once the attempts are used up.
+pub struct WebhookNotifier {
+    pub url: String,
+    pub db: Database,
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn send(&self, user: &User, notification: &OutgoingNotification) -> Result<(), String> {
+        let payload = serde_json::to_string(notification).map_err(|e| e.to_string())?;
+        let delivery = WebhookDelivery::new(user.user_uuid.clone(), self.url.clone(), payload);
+        queue_delivery(&self.db, delivery).await
+    }
+}
+
+pub struct TelegramNotifier {
+    /// Bot API base url, `TELEGRAM_API_URL` or the public Telegram API.
+    pub api_url: String,
+    pub bot_token: String,
+    pub chat_id: String,
+}
+
+#[async_trait]
+impl Notifier for TelegramNotifier {
+    async fn send(&self, _user: &User, notification: &OutgoingNotification) -> Result<(), String> {
+        let url = format!("{}/bot{}/sendMessage", self.api_url, self.bot_token);
+        let message = json!({
+            "chat_id": self.chat_id,
+            "text": notification.text(),
+        });
+        post_json("Telegram", &url, &message).await
+    }
+}
+
+pub struct DiscordNotifier {
+    pub webhook_url: String,
+}
+
+#[async_trait]
+impl Notifier for DiscordNotifier {
+    async fn send(&self, _user: &User, notification: &OutgoingNotification) -> Result<(), String> {
+        let content: String = notification
+            .text()
+            .chars()
+            .take(DISCORD_MAX_CONTENT)
+            .collect();
+        post_json("Discord", &self.webhook_url, &json!({ "content": content })).await
+    }
+}
+
+pub struct SlackNotifier {
+    pub webhook_url: String,
+}
+
+#[async_trait]
+impl Notifier for SlackNotifier {
+    async fn send(&self, _user: &User, notification: &OutgoingNotification) -> Result<(), String> {
+        post_json(
+            "Slack",
+            &self.webhook_url,
+            &json!({ "text": notification.text() }),
+        )
+        .await
+    }
+}
+
+pub fn notifier_for(channel: &NotificationChannel, db: &Database) -> Box<dyn Notifier> {
+    match channel {
+        NotificationChannel::Email => Box::new(EmailNotifier { db: db.clone() }),
+        NotificationChannel::Webhook { url, .. } => Box::new(WebhookNotifier {
+            url: url.clone(),
+            db: db.clone(),
+        }),
+        NotificationChannel::Telegram { bot_token, chat_id } => {
+            dotenv().ok();
+            Box::new(TelegramNotifier {
+                api_url: env::var("TELEGRAM_API_URL")
+                    .unwrap_or_else(|_| TELEGRAM_API_URL.to_string()),
+                bot_token: bot_token.clone(),
+                chat_id: chat_id.clone(),
+            })
+        }
+        NotificationChannel::Discord { webhook_url } => Box::new(DiscordNotifier {
+            webhook_url: webhook_url.clone(),
+        }),
+        NotificationChannel::Slack { webhook_url } => Box::new(SlackNotifier {
+            webhook_url: webhook_url.clone(),
+        }),
+    }
+}
+
+/// Sends `notification` through each of `channels` and returns each channel's outcome.
+/// Failures are logged.
+pub async fn notify_user(
+    user: &User,
+    channels: Vec<NotificationChannel>,
+    notification: &OutgoingNotification,
+    db: &Database,
+) -> Vec<(NotificationChannel, Result<(), String>)> {
+    let mut outcomes = Vec::new();
+    for channel in channels {
+        let result = notifier_for(&channel, db).send(user, notification).await;
+        if let Err(err) = &result {
+            println!(
+                "Failed to notify {} via {}: {err}",
+                user.name,
+                channel.kind()
+            );
+        }
+        outcomes.push((channel, result));
+    }
+    outcomes
+}
+
+/// Posts `body` to `url`. Bot tokens and webhook paths are secrets, so errors name the
+/// channel `kind` and never the url.
+async fn post_json(kind: &str, url: &str, body: &serde_json::Value) -> Result<(), String> {
+    let response = http_client()
+        .post(url)
+        .json(body)
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+        .send()
+        .await
+        .map_err(|e| format!("{kind} request failed: {}", e.without_url()))?;
+    if !response.status().is_success() {
+        return Err(format!("{kind} request failed: {:?}", response.status()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::http_stub::HttpStub;
+    use serde_json::Value;
+
+    fn user() -> User {
+        User::new("Ada".to_string(), "ada@example.com".to_string(), Vec::new()).unwrap()
+    }
+
+    fn notification(body: &str) -> OutgoingNotification {
+        OutgoingNotification {
+            subject: "Transaction Notification".to_string(),
+            body: format!("{body}\n"),
+            html: Some("<p>html</p>".to_string()),
+            high_priority: false,
+            data: json!({}),
+        }
+    }
+
+    fn json_body(stub: &HttpStub) -> Value {
+        serde_json::from_str(&stub.next_request().body).unwrap()
+    }
+
+    #[test]
+    fn the_html_part_is_left_out_of_json_payloads() {
+        let payload = serde_json::to_value(notification("sent 1 ETH")).unwrap();
+        assert_eq!(payload["subject"], "Transaction Notification");
+        assert!(payload.get("html").is_none());
+    }
+
+    #[actix_web::test]
+    async fn telegram_posts_to_the_bot_api() {
+        let stub = HttpStub::start(200);
+        let notifier = TelegramNotifier {
+            api_url: stub.url.clone(),
+            bot_token: "123:abc".to_string(),
+            chat_id: "42".to_string(),
+        };
+        notifier
+            .send(&user(), &notification("sent 1 ETH"))
+            .await
+            .unwrap();
+
+        let request = stub.next_request();
+        assert_eq!(
+            request.request_line,
+            "POST /bot123:abc/sendMessage HTTP/1.1"
+        );
+        let body: Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body["chat_id"], "42");
+        assert_eq!(body["text"], "Transaction Notification\nsent 1 ETH");
+    }
+
+    #[actix_web::test]
+    async fn discord_messages_are_cut_to_its_limit() {
+        let stub = HttpStub::start(204);
+        let notifier = DiscordNotifier {
+            webhook_url: stub.url.clone(),
+        };
+        notifier
+            .send(&user(), &notification(&"é".repeat(3000)))
+            .await
+            .unwrap();
+
+        let content = json_body(&stub)["content"].as_str().unwrap().to_string();
+        assert_eq!(content.chars().count(), DISCORD_MAX_CONTENT);
+        assert!(content.starts_with("Transaction Notification\n"));
+    }
+
+    #[actix_web::test]
+    async fn slack_posts_the_text() {
+        let stub = HttpStub::start(200);
+        let notifier = SlackNotifier {
+            webhook_url: stub.url.clone(),
+        };
+        notifier
+            .send(&user(), &notification("received 5 STRK"))
+            .await
+            .unwrap();
+        assert_eq!(
+            json_body(&stub)["text"],
+            "Transaction Notification\nreceived 5 STRK"
+        );
+    }
+
+    #[actix_web::test]
+    async fn error_statuses_fail_the_send() {
+        let stub = HttpStub::start(429);
+        let notifier = SlackNotifier {
+            webhook_url: stub.url.clone(),
+        };
+        let err = notifier
+            .send(&user(), &notification("received 5 STRK"))
+            .await
+            .unwrap_err();
+        assert!(err.contains("429"), "{err}");
+    }
+
+    #[actix_web::test]
+    async fn failed_sends_do_not_leak_tokens_or_webhook_paths() {
+        let stub = HttpStub::start(500);
+        let telegram = TelegramNotifier {
+            api_url: stub.url.clone(),
+            bot_token: "123456:secret-bot-token".to_string(),
+            chat_id: "42".to_string(),
+        };
+        let err = telegram
+            .send(&user(), &notification("sent 1 ETH"))
+            .await
+            .unwrap_err();
+        assert_eq!(err, "Telegram request failed: 500");
+        stub.next_request();
+
+        // Nothing listens on a port that was just released, so the request itself fails.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let discord = DiscordNotifier {
+            webhook_url: format!("{closed}/api/webhooks/1234/secret-webhook-token"),
+        };
+        let err = discord
+            .send(&user(), &notification("sent 1 ETH"))
+            .await
+            .unwrap_err();
+        assert!(err.starts_with("Discord request failed"), "{err}");
+        assert!(!err.contains("secret-webhook-token"), "{err}");
+        assert!(!err.contains(&closed), "{err}");
+    }
+}
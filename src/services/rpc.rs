@@ -0,0 +1,540 @@
+use crate::models::network_model::{Network, NetworkManager, RpcEndpoint};
+use dotenv::dotenv;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::time::{sleep_until, Duration, Instant};
+
+// One connection pool shared by every crawler.
+static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
+
+const BACKOFF_BASE_MS: u64 = 1_000;
+const BACKOFF_MAX_MS: u64 = 300_000;
+// Weight of the latest outcome in an endpoint's health score.
+const HEALTH_SMOOTHING: f64 = 0.2;
+// JSON-RPC error code for a method the endpoint doesn't provide.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+fn http_client() -> Client {
+    HTTP_CLIENT.get_or_init(Client::new).clone()
+}
+
+struct EndpointHealth {
+    /// Moving average of request outcomes, 1.0 when every recent request succeeded.
+    score: f64,
+    consecutive_failures: u32,
+    backoff_until: Option<Instant>,
+    /// Earliest time the next request may start under the endpoint's rate limit.
+    next_request_at: Instant,
+}
+
+struct EndpointState {
+    endpoint: RpcEndpoint,
+    health: Mutex<EndpointHealth>,
+}
+
+impl EndpointState {
+    fn new(endpoint: RpcEndpoint) -> Self {
+        EndpointState {
+            endpoint,
+            health: Mutex::new(EndpointHealth {
+                score: 1.0,
+                consecutive_failures: 0,
+                backoff_until: None,
+                next_request_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// The endpoint's host, for logs. Full URLs often embed an API key.
+    fn host(&self) -> String {
+        reqwest::Url::parse(&self.endpoint.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_else(|| "<invalid url>".to_string())
+    }
+
+    fn is_backing_off(&self, now: Instant) -> bool {
+        let health = self.health.lock().unwrap();
+        health.backoff_until.is_some_and(|until| until > now)
+    }
+
+    fn score(&self) -> f64 {
+        self.health.lock().unwrap().score
+    }
+
+    /// Reserves the next request slot allowed by the rate limit and returns when it starts.
+    fn reserve_slot(&self) -> Instant {
+        let mut health = self.health.lock().unwrap();
+        let slot = health.next_request_at.max(Instant::now());
+        if self.endpoint.requests_per_second > 0 {
+            let interval = Duration::from_secs(1) / self.endpoint.requests_per_second;
+            health.next_request_at = slot + interval;
+        }
+        slot
+    }
+
+    fn record_success(&self) {
+        let mut health = self.health.lock().unwrap();
+        health.score = health.score * (1.0 - HEALTH_SMOOTHING) + HEALTH_SMOOTHING;
+        health.consecutive_failures = 0;
+        health.backoff_until = None;
+    }
+
+    fn record_failure(&self) {
+        let mut health = self.health.lock().unwrap();
+        health.score *= 1.0 - HEALTH_SMOOTHING;
+        health.consecutive_failures += 1;
+        let backoff_ms = BACKOFF_BASE_MS
+            .saturating_mul(2u64.saturating_pow(health.consecutive_failures - 1))
+            .min(BACKOFF_MAX_MS);
+        health.backoff_until = Some(Instant::now() + Duration::from_millis(backoff_ms));
+    }
+}
+
+/// The network's configured endpoints, falling back to its RPC env var.
+fn endpoints_for(network_manager: &NetworkManager) -> Result<Vec<RpcEndpoint>, String> {
+    if !network_manager.rpc_endpoints.is_empty() {
+        return Ok(network_manager.rpc_endpoints.clone());
+    }
+
+    dotenv().ok();
+    let env_key = network_manager.network_type.rpc_env_key();
+    match env::var(env_key) {
+        Ok(rpc_url) => Ok(vec![RpcEndpoint::new(rpc_url)]),
+        Err(_) => Err(format!("no RPC endpoints configured and {env_key} not set")),
+    }
+}
+
+/// A client together with the endpoints it was built from.
+type RegisteredClient = (Vec<RpcEndpoint>, RpcClient);
+
+/// One `RpcClient` per network, shared by its crawler, wallet backfills and the balance
+/// checker so they see the same endpoint health and rate limits. A client is replaced
+/// when the network's endpoints change.
+#[derive(Clone, Default)]
+pub struct RpcRegistry {
+    clients: Arc<Mutex<HashMap<Network, RegisteredClient>>>,
+}
+
+impl RpcRegistry {
+    pub fn new() -> Self {
+        RpcRegistry::default()
+    }
+
+    pub fn client_for(&self, network_manager: &NetworkManager) -> Result<RpcClient, String> {
+        let endpoints = endpoints_for(network_manager)?;
+        let mut clients = self.clients.lock().unwrap();
+        match clients.get(&network_manager.network_type) {
+            Some((configured, client)) if *configured == endpoints => Ok(client.clone()),
+            _ => {
+                let client = RpcClient::new(endpoints.clone());
+                clients.insert(
+                    network_manager.network_type.clone(),
+                    (endpoints, client.clone()),
+                );
+                Ok(client)
+            }
+        }
+    }
+}
+
+/// JSON-RPC client over one or more endpoints. Requests go to the healthiest endpoint that
+/// isn't backing off, and fail over to the next one on transport errors or timeouts.
+/// Clones share the endpoints' health and rate limits.
+#[derive(Clone)]
+pub struct RpcClient {
+    client: Client,
+    endpoints: Arc<Vec<EndpointState>>,
+}
+
+impl RpcClient {
+    pub fn new(endpoints: Vec<RpcEndpoint>) -> Self {
+        RpcClient {
+            client: http_client(),
+            endpoints: Arc::new(endpoints.into_iter().map(EndpointState::new).collect()),
+        }
+    }
+
+    /// Sends a single JSON-RPC request and deserializes its `result`. Transport failures,
+    /// RPC errors and results that don't match `T` are all returned as errors.
+    pub async fn request<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<T, String> {
+        let request_body = json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": 0
+        });
+
+        let response_json = self.post(method, request_body).await?;
+        parse_response(method, response_json)
+    }
+
+    /// Sends one JSON-RPC batch calling `method` once per entry of `params_list`. Results
+    /// are returned in the order of `params_list`; any failed call fails the whole batch.
+    pub async fn batch_request<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params_list: Vec<serde_json::Value>,
+    ) -> Result<Vec<T>, String> {
+        let request_body: Vec<serde_json::Value> = params_list
+            .into_iter()
+            .enumerate()
+            .map(|(id, params)| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "method": method,
+                    "params": params,
+                    "id": id
+                })
+            })
+            .collect();
+        let batch_len = request_body.len();
+
+        let response_json = self.post(method, json!(request_body)).await?;
+        let responses = match response_json {
+            serde_json::Value::Array(responses) => responses,
+            // Some providers answer a whole batch with a single error object.
+            other => return Err(format!("{method} batch failed: {}", other["error"])),
+        };
+        if responses.len() != batch_len {
+            return Err(format!(
+                "{method} batch returned {} results for {batch_len} requests",
+                responses.len()
+            ));
+        }
+
+        // Responses may come back in any order, so each goes to the slot of its id.
+        let mut slots: Vec<Option<serde_json::Value>> = vec![None; batch_len];
+        for response in responses {
+            let id = response["id"].as_u64().map(|id| id as usize);
+            match id.and_then(|id| slots.get_mut(id)) {
+                Some(slot @ None) => *slot = Some(response),
+                Some(Some(_)) => {
+                    return Err(format!(
+                        "{method} batch returned id {} twice",
+                        response["id"]
+                    ))
+                }
+                None => {
+                    return Err(format!(
+                        "{method} batch returned unexpected id {}",
+                        response["id"]
+                    ))
+                }
+            }
+        }
+        slots
+            .into_iter()
+            .map(|response| parse_response(method, response.unwrap_or_default()))
+            .collect()
+    }
+
+    /// Endpoints to try, healthiest first. Endpoints backing off are only tried when
+    /// every endpoint is backing off.
+    fn candidates(&self) -> Vec<&EndpointState> {
+        let now = Instant::now();
+        let mut candidates: Vec<&EndpointState> = self
+            .endpoints
+            .iter()
+            .filter(|endpoint| !endpoint.is_backing_off(now))
+            .collect();
+        if candidates.is_empty() {
+            candidates = self.endpoints.iter().collect();
+        }
+        candidates.sort_by(|a, b| b.score().total_cmp(&a.score()));
+        candidates
+    }
+
+    async fn post(
+        &self,
+        method: &str,
+        request_body: serde_json::Value,
+    ) -> Result<serde_json::Value, String> {
+        let mut last_error = format!("{method} request failed: no RPC endpoints configured");
+
+        for endpoint in self.candidates() {
+            sleep_until(endpoint.reserve_slot()).await;
+            match self.post_to(endpoint, method, &request_body).await {
+                Ok(response_json) => {
+                    endpoint.record_success();
+                    return Ok(response_json);
+                }
+                Err(err) => {
+                    println!("RPC endpoint {} failed: {err}", endpoint.host());
+                    endpoint.record_failure();
+                    last_error = err;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    async fn post_to(
+        &self,
+        endpoint: &EndpointState,
+        method: &str,
+        request_body: &serde_json::Value,
+    ) -> Result<serde_json::Value, String> {
+        let response = self
+            .client
+            .post(&endpoint.endpoint.url)
+            .json(request_body)
+            .header("Content-Type", "application/json")
+            .timeout(Duration::from_millis(endpoint.endpoint.timeout_ms))
+            .send()
+            .await
+            .map_err(|e| format!("{method} request failed: {}", e.without_url()))?;
+
+        if !response.status().is_success() {
+            return Err(format!("{method} request failed: {:?}", response.status()));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|e| format!("Invalid {method} response: {}", e.without_url()))
+    }
+}
+
+/// Whether a request failed because the endpoint doesn't provide the method.
+pub fn is_method_not_found(err: &str) -> bool {
+    err.contains(&format!("\"code\":{METHOD_NOT_FOUND}"))
+}
+
+fn parse_response<T: DeserializeOwned>(
+    method: &str,
+    response_json: serde_json::Value,
+) -> Result<T, String> {
+    if !response_json["error"].is_null() {
+        return Err(format!(
+            "{method} returned an error: {}",
+            response_json["error"]
+        ));
+    }
+    serde_json::from_value(response_json["result"].clone())
+        .map_err(|e| format!("Malformed {method} result: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::http_stub::HttpStub;
+    use serde_json::Value;
+
+    fn client(stub: &HttpStub) -> RpcClient {
+        RpcClient::new(vec![RpcEndpoint::new(stub.url.clone())])
+    }
+
+    /// A node answering batches with `answer(calls)`, whatever they asked for.
+    fn batch_node(answer: fn(Vec<Value>) -> Value) -> HttpStub {
+        HttpStub::serve(move |request| {
+            let calls: Vec<Value> = serde_json::from_str(&request.body).unwrap();
+            (200, answer(calls).to_string())
+        })
+    }
+
+    fn result(id: u64, result: u64) -> Value {
+        json!({"jsonrpc": "2.0", "id": id, "result": result})
+    }
+
+    #[actix_web::test]
+    async fn batch_results_follow_the_request_order() {
+        let node = batch_node(|calls| {
+            let answers: Vec<Value> = (0..calls.len() as u64)
+                .rev()
+                .map(|id| result(id, id * 10))
+                .collect();
+            json!(answers)
+        });
+        let results: Vec<u64> = client(&node)
+            .batch_request("eth_chainId", vec![json!([]); 3])
+            .await
+            .unwrap();
+
+        assert_eq!(results, [0, 10, 20]);
+        let calls: Vec<Value> = serde_json::from_str(&node.next_request().body).unwrap();
+        let ids: Vec<&Value> = calls.iter().map(|call| &call["id"]).collect();
+        assert_eq!(ids, [&json!(0), &json!(1), &json!(2)]);
+    }
+
+    #[actix_web::test]
+    async fn batches_with_missing_results_fail() {
+        let node = batch_node(|_| json!([result(0, 1)]));
+        let err = client(&node)
+            .batch_request::<u64>("eth_chainId", vec![json!([]); 2])
+            .await
+            .unwrap_err();
+
+        assert_eq!(err, "eth_chainId batch returned 1 results for 2 requests");
+    }
+
+    #[actix_web::test]
+    async fn batches_with_repeated_or_unknown_ids_fail() {
+        let repeated = batch_node(|_| json!([result(1, 1), result(1, 1)]));
+        let err = client(&repeated)
+            .batch_request::<u64>("eth_chainId", vec![json!([]); 2])
+            .await
+            .unwrap_err();
+        assert_eq!(err, "eth_chainId batch returned id 1 twice");
+
+        let unknown = batch_node(|_| json!([result(0, 1), result(2, 1)]));
+        let err = client(&unknown)
+            .batch_request::<u64>("eth_chainId", vec![json!([]); 2])
+            .await
+            .unwrap_err();
+        assert_eq!(err, "eth_chainId batch returned unexpected id 2");
+    }
+
+    #[actix_web::test]
+    async fn batches_answered_with_one_error_fail() {
+        let node = batch_node(
+            |_| json!({"jsonrpc": "2.0", "id": null, "error": {"code": -32005, "message": "limit"}}),
+        );
+        let err = client(&node)
+            .batch_request::<u64>("eth_chainId", vec![json!([]); 2])
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            r#"eth_chainId batch failed: {"code":-32005,"message":"limit"}"#
+        );
+    }
+
+    fn backoff_ms(endpoint: &EndpointState, before: Instant) -> u64 {
+        let until = endpoint.health.lock().unwrap().backoff_until.unwrap();
+        (until - before).as_millis() as u64
+    }
+
+    #[actix_web::test]
+    async fn failing_endpoints_are_skipped_until_their_backoff_ends() {
+        let failing = HttpStub::start(500);
+        let healthy = HttpStub::json_rpc(|_, _| Ok(json!("0x1")));
+        let rpc = RpcClient::new(vec![
+            RpcEndpoint::new(failing.url.clone()),
+            RpcEndpoint::new(healthy.url.clone()),
+        ]);
+
+        let chain_id: String = rpc.request("eth_chainId", json!([])).await.unwrap();
+        assert_eq!(chain_id, "0x1");
+        assert_eq!(failing.received().len(), 1);
+        assert_eq!(healthy.received().len(), 1);
+
+        rpc.request::<String>("eth_chainId", json!([]))
+            .await
+            .unwrap();
+        assert!(failing.received().is_empty());
+        assert_eq!(healthy.received().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn endpoints_are_tried_healthiest_first_when_all_are_backing_off() {
+        let first = HttpStub::start(500);
+        let second = HttpStub::start(502);
+        let rpc = RpcClient::new(vec![
+            RpcEndpoint::new(first.url.clone()),
+            RpcEndpoint::new(second.url.clone()),
+        ]);
+        rpc.endpoints[0].record_failure();
+        rpc.endpoints[0].record_failure();
+        rpc.endpoints[1].record_failure();
+
+        let order: Vec<&str> = rpc
+            .candidates()
+            .iter()
+            .map(|endpoint| endpoint.endpoint.url.as_str())
+            .collect();
+        assert_eq!(order, [second.url.as_str(), first.url.as_str()]);
+
+        // The last endpoint's error is the one returned.
+        let err = rpc
+            .request::<String>("eth_chainId", json!([]))
+            .await
+            .unwrap_err();
+        assert_eq!(err, "eth_chainId request failed: 500");
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let endpoint = EndpointState::new(RpcEndpoint::new("http://node".to_string()));
+        let mut expected = Vec::new();
+        let mut delays = Vec::new();
+        for failures in 0..12 {
+            let before = Instant::now();
+            endpoint.record_failure();
+            delays.push(backoff_ms(&endpoint, before));
+            expected.push((BACKOFF_BASE_MS << failures).min(BACKOFF_MAX_MS));
+        }
+
+        for (delay, expected) in delays.iter().zip(&expected) {
+            assert!(
+                (*expected..*expected + 100).contains(delay),
+                "{delay}ms instead of {expected}ms"
+            );
+        }
+        assert_eq!(expected[..3], [1_000, 2_000, 4_000]);
+        assert_eq!(expected[11], BACKOFF_MAX_MS);
+
+        endpoint.record_success();
+        assert!(!endpoint.is_backing_off(Instant::now()));
+        let before = Instant::now();
+        endpoint.record_failure();
+        assert!(backoff_ms(&endpoint, before) < BACKOFF_MAX_MS);
+    }
+
+    #[test]
+    fn scores_move_a_fifth_of_the_way_to_each_outcome() {
+        let endpoint = EndpointState::new(RpcEndpoint::new("http://node".to_string()));
+        assert_eq!(endpoint.score(), 1.0);
+
+        endpoint.record_failure();
+        assert!((endpoint.score() - 0.8).abs() < 1e-9);
+        endpoint.record_failure();
+        assert!((endpoint.score() - 0.64).abs() < 1e-9);
+        endpoint.record_success();
+        assert!((endpoint.score() - 0.712).abs() < 1e-9);
+    }
+
+    #[test]
+    fn slots_are_spaced_by_the_rate_limit() {
+        let mut limited = RpcEndpoint::new("http://node".to_string());
+        limited.requests_per_second = 4;
+        let endpoint = EndpointState::new(limited);
+        let start = Instant::now();
+        let slots: Vec<u128> = (0..3)
+            .map(|_| (endpoint.reserve_slot() - start).as_millis())
+            .collect();
+
+        assert!(slots[0] < 50);
+        assert!((250..300).contains(&(slots[1] - slots[0])));
+        assert!((250..300).contains(&(slots[2] - slots[1])));
+
+        let unlimited = EndpointState::new(RpcEndpoint::new("http://node".to_string()));
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!((unlimited.reserve_slot() - start).as_millis() < 50);
+        }
+    }
+
+    #[actix_web::test]
+    async fn endpoint_urls_stay_out_of_errors_and_logs() {
+        let url = "http://127.0.0.1:9/v2/secret-api-key".to_string();
+        let endpoint = EndpointState::new(RpcEndpoint::new(url.clone()));
+        assert_eq!(endpoint.host(), "127.0.0.1");
+
+        let err = RpcClient::new(vec![RpcEndpoint::new(url)])
+            .request::<String>("eth_chainId", json!([]))
+            .await
+            .unwrap_err();
+        assert!(err.starts_with("eth_chainId request failed: "), "{err}");
+        assert!(!err.contains("secret-api-key"), "{err}");
+    }
+}
@@ -0,0 +1,163 @@
+use futures_util::{SinkExt, StreamExt};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::sync::Notify;
+use tokio::time::{sleep, Duration};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+const RECONNECT_BASE_SECS: u64 = 1;
+const RECONNECT_MAX_SECS: u64 = 60;
+
+/// Keeps a new heads subscription open on `ws_url` and wakes `new_head` for every
+/// notification. Reconnects with exponential backoff whenever the socket drops; callers
+/// keep polling on their own schedule in the meantime.
+pub async fn subscribe_new_heads(
+    ws_url: String,
+    method: &'static str,
+    params: serde_json::Value,
+    new_head: Arc<Notify>,
+) {
+    let request = json!({
+        "jsonrpc": "2.0",
+        "method": method,
+        "params": params,
+        "id": 1
+    });
+    let mut failures: u32 = 0;
+
+    loop {
+        match listen(&ws_url, &request, &new_head).await {
+            Ok(()) => {
+                println!("New heads subscription on {ws_url} closed");
+                failures = 0;
+            }
+            Err(err) => {
+                println!("New heads subscription on {ws_url} failed: {err}");
+                failures += 1;
+            }
+        }
+
+        let delay = (RECONNECT_BASE_SECS << failures.min(6)).min(RECONNECT_MAX_SECS);
+        println!("Falling back to polling, reconnecting in {delay} seconds");
+        sleep(Duration::from_secs(delay)).await;
+    }
+}
+
+async fn listen(
+    ws_url: &str,
+    request: &serde_json::Value,
+    new_head: &Notify,
+) -> Result<(), String> {
+    let (mut socket, _) = connect_async(ws_url)
+        .await
+        .map_err(|e| format!("connection failed: {e}"))?;
+    socket
+        .send(Message::Text(request.to_string()))
+        .await
+        .map_err(|e| format!("subscribe request failed: {e}"))?;
+
+    while let Some(message) = socket.next().await {
+        let text = match message.map_err(|e| format!("socket error: {e}"))? {
+            Message::Text(text) => text,
+            Message::Close(_) => return Ok(()),
+            _ => continue,
+        };
+        let message_json: serde_json::Value =
+            serde_json::from_str(&text).map_err(|e| format!("invalid message: {e}"))?;
+
+        if !message_json["error"].is_null() {
+            return Err(format!("subscription rejected: {}", message_json["error"]));
+        }
+        // The subscribe call is answered with `result`; new heads arrive as notifications.
+        if !message_json["params"].is_null() {
+            new_head.notify_one();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio::time::timeout;
+    use tokio_tungstenite::accept_async;
+
+    /// Accepts one socket, returns the subscribe request it sent and replies with `replies`.
+    async fn serve(replies: Vec<Message>) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = accept_async(stream).await.unwrap();
+            let request = socket.next().await.unwrap().unwrap().into_text().unwrap();
+            for reply in replies {
+                socket.send(reply).await.unwrap();
+            }
+            request
+        });
+        (url, server)
+    }
+
+    fn request() -> serde_json::Value {
+        json!({"jsonrpc": "2.0", "method": "eth_subscribe", "params": ["newHeads"], "id": 1})
+    }
+
+    #[actix_web::test]
+    async fn notifications_wake_the_crawler() {
+        let (url, server) = serve(vec![
+            Message::Text(r#"{"jsonrpc":"2.0","id":1,"result":"0x9ce59a13"}"#.to_string()),
+            Message::Text(
+                r#"{"jsonrpc":"2.0","method":"eth_subscription","params":{"subscription":"0x9ce59a13","result":{"number":"0x1b4"}}}"#
+                    .to_string(),
+            ),
+            Message::Close(None),
+        ])
+        .await;
+        let new_head = Notify::new();
+
+        assert_eq!(listen(&url, &request(), &new_head).await, Ok(()));
+        let sent: serde_json::Value = serde_json::from_str(&server.await.unwrap()).unwrap();
+        assert_eq!(sent, request());
+        timeout(Duration::from_secs(1), new_head.notified())
+            .await
+            .expect("new head was not signalled");
+    }
+
+    #[actix_web::test]
+    async fn subscribe_result_alone_does_not_wake() {
+        let (url, _server) = serve(vec![
+            Message::Text(r#"{"jsonrpc":"2.0","id":1,"result":"0x9ce59a13"}"#.to_string()),
+            Message::Close(None),
+        ])
+        .await;
+        let new_head = Notify::new();
+
+        assert_eq!(listen(&url, &request(), &new_head).await, Ok(()));
+        assert!(timeout(Duration::from_millis(50), new_head.notified())
+            .await
+            .is_err());
+    }
+
+    #[actix_web::test]
+    async fn rejected_subscriptions_fail() {
+        let (url, _server) = serve(vec![Message::Text(
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32601,"message":"method not found"}}"#
+                .to_string(),
+        )])
+        .await;
+
+        let err = listen(&url, &request(), &Notify::new()).await.unwrap_err();
+        assert!(err.starts_with("subscription rejected"), "{err}");
+    }
+
+    #[actix_web::test]
+    async fn unreachable_endpoints_fail() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let err = listen(&url, &request(), &Notify::new()).await.unwrap_err();
+        assert!(err.starts_with("connection failed"), "{err}");
+    }
+}
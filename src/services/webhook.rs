@@ -0,0 +1,282 @@
+use crate::models::webhook_model::WebhookDelivery;
+use crate::services::db::Database;
+use crate::services::notifier::http_client;
+use dotenv::dotenv;
+use hmac::{Hmac, Mac};
+use mongodb::bson::DateTime;
+use sha2::Sha256;
+use std::env;
+use std::sync::OnceLock;
+use tokio::sync::Notify;
+use tokio::time::{sleep, timeout, Duration};
+
+const DEFAULT_WEBHOOK_WORKERS: usize = 2;
+// With the backoff below, a delivery is retried for about four hours before it is
+// dead-lettered, enough to ride out a receiver's restart or outage.
+const MAX_ATTEMPTS: u32 = 10;
+const RETRY_BASE_MS: i64 = 30_000;
+const RETRY_MAX_MS: i64 = 2 * 60 * 60 * 1_000;
+// 30s << 8 already passes the cap; larger shifts would only risk overflowing.
+const RETRY_MAX_SHIFT: u32 = 8;
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+// Long enough for an attempt to time out before another worker may claim the delivery.
+const CLAIM_LEASE_SECS: i64 = 60;
+// Fallback poll for retries coming due and deliveries queued by another instance.
+const POLL_INTERVAL_SECS: u64 = 2;
+
+static WEBHOOK_QUEUED: OnceLock<Notify> = OnceLock::new();
+
+fn webhook_queued() -> &'static Notify {
+    WEBHOOK_QUEUED.get_or_init(Notify::new)
+}
+
+/// `sha256=` and the hex HMAC-SHA256 of `{timestamp}.{payload}` under `secret`. Receivers
+/// recompute it from the `X-Kharon-Timestamp` header and the raw body.
+pub fn sign_payload(secret: &str, timestamp: i64, payload: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{timestamp}.{payload}").as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Posts the delivery once, signed when the webhook has a secret.
+pub async fn attempt_delivery(
+    delivery: &WebhookDelivery,
+    secret: Option<&str>,
+) -> Result<(), String> {
+    let timestamp = DateTime::now().timestamp_millis() / 1000;
+    let mut request = http_client()
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header("X-Kharon-Delivery", &delivery.delivery_id)
+        .header("X-Kharon-Timestamp", timestamp.to_string())
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+        .body(delivery.payload.clone());
+    if let Some(secret) = secret {
+        request = request.header(
+            "X-Kharon-Signature",
+            sign_payload(secret, timestamp, &delivery.payload),
+        );
+    }
+
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("request to {} failed: {e}", delivery.url))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "request to {} failed: {:?}",
+            delivery.url,
+            response.status()
+        ));
+    }
+    Ok(())
+}
+
+/// Queues the delivery for the webhook workers, which retry it with exponential backoff
+/// and park it in the dead-letter collection after `MAX_ATTEMPTS`.
+pub async fn queue_delivery(db: &Database, delivery: WebhookDelivery) -> Result<(), String> {
+    db.enqueue_webhook_delivery(delivery)
+        .await
+        .map_err(|e| format!("Could not queue webhook delivery: {}", e.message))?;
+    webhook_queued().notify_one();
+    Ok(())
+}
+
+/// Starts `WEBHOOK_WORKERS` workers (default 2). Deliveries left pending by a previous run
+/// are picked up once due, or once their claim's lease has run out.
+pub fn start_webhook_workers(db: Database) {
+    dotenv().ok();
+    let workers = env::var("WEBHOOK_WORKERS")
+        .ok()
+        .and_then(|workers| workers.parse::<usize>().ok())
+        .filter(|workers| *workers > 0)
+        .unwrap_or(DEFAULT_WEBHOOK_WORKERS);
+    for worker in 0..workers {
+        tokio::spawn(run_webhook_worker(worker, db.clone()));
+    }
+}
+
+async fn run_webhook_worker(worker: usize, db: Database) {
+    loop {
+        match db.claim_next_webhook_delivery(CLAIM_LEASE_SECS).await {
+            Ok(Some(delivery)) => deliver(worker, delivery, &db).await,
+            Ok(None) => {
+                let _ = timeout(
+                    Duration::from_secs(POLL_INTERVAL_SECS),
+                    webhook_queued().notified(),
+                )
+                .await;
+            }
+            Err(e) => {
+                println!("Webhook worker {worker}: error claiming delivery: {:?}", e);
+                sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+            }
+        }
+    }
+}
+
+async fn deliver(worker: usize, mut delivery: WebhookDelivery, db: &Database) {
+    // The secret is looked up on every attempt so a rotated secret applies to retries.
+    let result = match db.get_user_via_id(delivery.user_uuid.clone()).await {
+        Ok(user) => match user.webhook_secret(&delivery.url) {
+            Some(secret) => attempt_delivery(&delivery, secret.as_deref()).await,
+            None => {
+                println!(
+                    "Webhook worker {worker}: {} is no longer registered, dropping delivery {}",
+                    delivery.url, delivery.delivery_id
+                );
+                return finish(worker, db, delivery.delivery_id).await;
+            }
+        },
+        Err(e) => Err(format!("could not load the webhook's user: {}", e.message)),
+    };
+
+    let err = match result {
+        Ok(()) => return finish(worker, db, delivery.delivery_id).await,
+        Err(err) => err,
+    };
+    delivery.record_failure(err);
+    delivery.claimed_at = None;
+    match retry_at(delivery.attempts) {
+        Some(retry_at) => {
+            println!(
+                "Webhook worker {worker}: delivery {} failed (attempt {}/{MAX_ATTEMPTS}), retrying",
+                delivery.delivery_id, delivery.attempts
+            );
+            delivery.retry_at = retry_at;
+            if let Err(e) = db.reschedule_webhook_delivery(delivery).await {
+                println!(
+                    "Webhook worker {worker}: error rescheduling delivery: {:?}",
+                    e
+                );
+            }
+        }
+        None => {
+            println!(
+                "Webhook worker {worker}: delivery {} failed {} times, moving it to the dead-letter queue",
+                delivery.delivery_id, delivery.attempts
+            );
+            let delivery_id = delivery.delivery_id.clone();
+            match db.insert_dead_letter(delivery).await {
+                Ok(_) => finish(worker, db, delivery_id).await,
+                Err(e) => println!(
+                    "Webhook worker {worker}: error recording failed delivery: {:?}",
+                    e
+                ),
+            }
+        }
+    }
+}
+
+async fn finish(worker: usize, db: &Database, delivery_id: String) {
+    if let Err(e) = db.delete_webhook_delivery(delivery_id).await {
+        println!("Webhook worker {worker}: error removing delivery: {:?}", e);
+    }
+}
+
+/// When to retry after `attempts` failed attempts, or `None` once they are used up.
+fn retry_at(attempts: u32) -> Option<DateTime> {
+    let delay = retry_delay_ms(attempts)?;
+    Some(DateTime::from_millis(
+        DateTime::now().timestamp_millis() + delay,
+    ))
+}
+
+/// Doubles from `RETRY_BASE_MS` with each failed attempt, up to `RETRY_MAX_MS`.
+fn retry_delay_ms(attempts: u32) -> Option<i64> {
+    if attempts >= MAX_ATTEMPTS {
+        return None;
+    }
+    let shift = attempts.saturating_sub(1).min(RETRY_MAX_SHIFT);
+    Some((RETRY_BASE_MS << shift).min(RETRY_MAX_MS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::http_stub::HttpStub;
+
+    #[test]
+    fn sign_payload_is_an_hmac_of_the_timestamp_and_body() {
+        // Computed independently with Python's hmac module.
+        assert_eq!(
+            sign_payload("whsec_test", 1_700_000_000, r#"{"subject":"hi"}"#),
+            "sha256=c02fb6b931b1eab2b4597bb562150867182632c45b53a79e3b7cf2d5dab57d46"
+        );
+    }
+
+    #[test]
+    fn sign_payload_depends_on_every_input() {
+        let signature = sign_payload("secret", 1, "{}");
+        assert_ne!(signature, sign_payload("other", 1, "{}"));
+        assert_ne!(signature, sign_payload("secret", 2, "{}"));
+        assert_ne!(signature, sign_payload("secret", 1, "{ }"));
+    }
+
+    #[actix_web::test]
+    async fn attempt_delivery_posts_the_signed_payload() {
+        let stub = HttpStub::start(200);
+        let delivery = WebhookDelivery::new(
+            "user".to_string(),
+            format!("{}/hook", stub.url),
+            r#"{"subject":"hi"}"#.to_string(),
+        );
+        attempt_delivery(&delivery, Some("whsec_test"))
+            .await
+            .unwrap();
+
+        let request = stub.next_request();
+        assert_eq!(request.request_line, "POST /hook HTTP/1.1");
+        assert_eq!(request.body, delivery.payload);
+        assert_eq!(
+            request.header("X-Kharon-Delivery"),
+            Some(delivery.delivery_id.as_str())
+        );
+        let timestamp: i64 = request
+            .header("X-Kharon-Timestamp")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(
+            request.header("X-Kharon-Signature"),
+            Some(sign_payload("whsec_test", timestamp, &delivery.payload).as_str())
+        );
+    }
+
+    #[actix_web::test]
+    async fn attempt_delivery_skips_the_signature_without_a_secret() {
+        let stub = HttpStub::start(204);
+        let delivery = WebhookDelivery::new("user".to_string(), stub.url.clone(), "{}".to_string());
+        attempt_delivery(&delivery, None).await.unwrap();
+        assert_eq!(stub.next_request().header("X-Kharon-Signature"), None);
+    }
+
+    #[actix_web::test]
+    async fn attempt_delivery_fails_on_error_statuses() {
+        let stub = HttpStub::start(500);
+        let delivery = WebhookDelivery::new("user".to_string(), stub.url.clone(), "{}".to_string());
+        assert!(attempt_delivery(&delivery, None).await.is_err());
+    }
+
+    #[test]
+    fn retries_back_off_until_the_attempts_are_used_up() {
+        assert_eq!(retry_delay_ms(1), Some(30_000));
+        assert_eq!(retry_delay_ms(2), Some(60_000));
+        assert_eq!(retry_delay_ms(MAX_ATTEMPTS - 1), Some(RETRY_MAX_MS));
+        assert_eq!(retry_delay_ms(MAX_ATTEMPTS), None);
+
+        let first = retry_at(1).unwrap().timestamp_millis() - DateTime::now().timestamp_millis();
+        assert!((29_900..=30_000).contains(&first));
+    }
+
+    #[test]
+    fn deliveries_are_retried_for_hours_before_dead_lettering() {
+        let delays: Vec<i64> = (1..).map_while(retry_delay_ms).collect();
+        assert_eq!(delays.len() as u32, MAX_ATTEMPTS - 1);
+        assert!(delays.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let hours = delays.iter().sum::<i64>() as f64 / 3_600_000.0;
+        assert!((4.0..5.0).contains(&hours), "retried for {hours} hours");
+    }
+}
@@ -1,7 +1,7 @@
-use crate::services::db::DatabaseResponse;
 use actix_web::{body::BoxBody, http::StatusCode, web, HttpRequest, HttpResponse, Responder};
 
 pub struct ApiResponse {
+    #[allow(dead_code)]
     pub status_code: u16,
     pub body: String,
     pub response_code: StatusCode,
@@ -29,7 +29,7 @@ impl ApiResponse {
 impl Responder for ApiResponse {
     type Body = BoxBody;
 
-    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+    fn respond_to(self, _req: &HttpRequest) -> HttpResponse<Self::Body> {
         let body = BoxBody::new(web::BytesMut::from(self.body.as_bytes()));
         HttpResponse::new(self.response_code).set_body(body)
     }
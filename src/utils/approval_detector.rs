@@ -0,0 +1,171 @@
+use crate::models::activity_model::{AllowanceRisk, Approval};
+use crate::models::starknet_model::{felt_to_u256, ContractCall};
+use crate::utils::calldata_decoder::{known_function_name, known_token};
+use alloy::primitives::U256;
+
+const APPROVAL_FUNCTIONS: [&str; 3] = ["approve", "increase_allowance", "increaseAllowance"];
+
+// 4-byte selectors of `approve(address,uint256)` and `increaseAllowance(address,uint256)`.
+const EVM_APPROVAL_SELECTORS: [&str; 2] = ["095ea7b3", "39509351"];
+
+/// Wallets approve "forever" with `u256::MAX` or, on Starknet, often `u128::MAX`; anything
+/// from `u128::MAX` up counts as unlimited. Large allowances can only be judged for tokens
+/// whose decimals are known.
+pub fn assess_allowance(token_address: &str, amount: U256) -> Option<AllowanceRisk> {
+    if amount >= U256::from(u128::MAX) {
+        return Some(AllowanceRisk::Unlimited);
+    }
+    let token = known_token(token_address)?;
+    (amount >= U256::from(token.large_allowance)).then_some(AllowanceRisk::Large)
+}
+
+/// Returns the approval when the allowance is risky enough to alert on.
+pub fn risky_approval(token_address: &str, spender: &str, amount: U256) -> Option<Approval> {
+    let risk = assess_allowance(token_address, amount)?;
+    Some(Approval {
+        token_address: token_address.to_string(),
+        spender: spender.to_string(),
+        amount: amount.to_string(),
+        risk,
+    })
+}
+
+/// Flags a Starknet `approve`/`increase_allowance` call granting a risky allowance.
+pub fn approval_from_call(call: &ContractCall) -> Option<Approval> {
+    let function_name = known_function_name(&call.selector)?;
+    if !APPROVAL_FUNCTIONS.contains(&function_name) {
+        return None;
+    }
+
+    let spender = call.calldata.first()?;
+    let low = felt_to_u256(call.calldata.get(1)?)?;
+    let high = felt_to_u256(call.calldata.get(2)?)?;
+    risky_approval(&call.to, spender, low + (high << 128))
+}
+
+/// Flags an EVM `approve`/`increaseAllowance` call to `token_address` granting a risky
+/// allowance, from the transaction's ABI-encoded `input`.
+pub fn approval_from_evm_input(token_address: &str, input: &str) -> Option<Approval> {
+    let input = input.trim_start_matches("0x");
+    if !EVM_APPROVAL_SELECTORS.contains(&input.get(..8)?) {
+        return None;
+    }
+
+    // Each argument is a 32 byte word; the address is the low 20 bytes of the first one.
+    let spender = format!("0x{}", input.get(32..72)?.to_lowercase());
+    let amount = U256::from_str_radix(input.get(72..136)?, 16).ok()?;
+    risky_approval(token_address, &spender, amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::starknet_model::starknet_keccak;
+
+    const USDC: &str = "0x053c91253bc9682c04929ca02ed00b3e423f6710d2ee7e0d5ebb06f3ecf368a8";
+    const UNKNOWN_TOKEN: &str =
+        "0x0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+    const SPENDER: &str = "0x0213c67ed78bc280887234fe5ed5e77272465317978ae86c25a71531d9332a2d";
+
+    fn starknet_call(name: &str, calldata: &[&str]) -> ContractCall {
+        ContractCall {
+            to: USDC.to_string(),
+            selector: format!("{:#x}", starknet_keccak(name)),
+            calldata: calldata.iter().map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn u128_max_and_above_are_unlimited_for_any_token() {
+        for token in [USDC, UNKNOWN_TOKEN] {
+            assert_eq!(
+                assess_allowance(token, U256::from(u128::MAX)),
+                Some(AllowanceRisk::Unlimited)
+            );
+            assert_eq!(
+                assess_allowance(token, U256::MAX),
+                Some(AllowanceRisk::Unlimited)
+            );
+        }
+    }
+
+    #[test]
+    fn large_allowances_are_judged_against_the_known_token() {
+        // USDC's threshold is 10,000 USDC.
+        assert_eq!(
+            assess_allowance(USDC, U256::from(10_000_000_000u64)),
+            Some(AllowanceRisk::Large)
+        );
+        assert_eq!(assess_allowance(USDC, U256::from(9_999_999_999u64)), None);
+        assert_eq!(assess_allowance(USDC, U256::ZERO), None);
+    }
+
+    #[test]
+    fn unknown_tokens_are_only_flagged_when_unlimited() {
+        assert_eq!(
+            assess_allowance(UNKNOWN_TOKEN, U256::from(u128::MAX - 1)),
+            None
+        );
+    }
+
+    #[test]
+    fn risky_approval_keeps_the_raw_amount() {
+        let approval = risky_approval(USDC, SPENDER, U256::MAX).unwrap();
+        assert_eq!(approval.amount, U256::MAX.to_string());
+        assert_eq!(approval.spender, SPENDER);
+        assert_eq!(approval.risk, AllowanceRisk::Unlimited);
+        assert_eq!(risky_approval(USDC, SPENDER, U256::from(1u64)), None);
+    }
+
+    #[test]
+    fn starknet_approve_calls_read_the_u256_amount() {
+        let max = format!("{:#x}", u128::MAX);
+        let unlimited = starknet_call("approve", &[SPENDER, &max, &max]);
+        assert_eq!(
+            approval_from_call(&unlimited).map(|approval| approval.risk),
+            Some(AllowanceRisk::Unlimited)
+        );
+
+        let increase = starknet_call("increase_allowance", &[SPENDER, "0x2540be400", "0x0"]);
+        assert_eq!(
+            approval_from_call(&increase).map(|approval| approval.risk),
+            Some(AllowanceRisk::Large)
+        );
+
+        let small = starknet_call("approve", &[SPENDER, "0x1", "0x0"]);
+        assert_eq!(approval_from_call(&small), None);
+    }
+
+    #[test]
+    fn other_starknet_calls_are_ignored() {
+        let max = format!("{:#x}", u128::MAX);
+        assert_eq!(
+            approval_from_call(&starknet_call("transfer", &[SPENDER, &max, &max])),
+            None
+        );
+        assert_eq!(
+            approval_from_call(&starknet_call("approve", &[SPENDER])),
+            None
+        );
+    }
+
+    #[test]
+    fn evm_approve_input_is_decoded() {
+        let spender = "fb6916095ca1df60bb79ce92ce3ea74c37c5d359";
+        let input = format!("0x095ea7b3{:0>64}{}", spender, "f".repeat(64));
+        let approval = approval_from_evm_input(UNKNOWN_TOKEN, &input).unwrap();
+        assert_eq!(approval.spender, format!("0x{spender}"));
+        assert_eq!(approval.risk, AllowanceRisk::Unlimited);
+
+        let small = format!("0x39509351{:0>64}{:0>64}", spender, "1");
+        assert_eq!(approval_from_evm_input(UNKNOWN_TOKEN, &small), None);
+    }
+
+    #[test]
+    fn other_evm_inputs_are_ignored() {
+        let transfer = format!("0xa9059cbb{:0>64}{}", "ab", "f".repeat(64));
+        assert_eq!(approval_from_evm_input(UNKNOWN_TOKEN, &transfer), None);
+        assert_eq!(approval_from_evm_input(UNKNOWN_TOKEN, "0x095ea7b3"), None);
+        assert_eq!(approval_from_evm_input(UNKNOWN_TOKEN, "0x"), None);
+    }
+}
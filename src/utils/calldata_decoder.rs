@@ -0,0 +1,388 @@
+use crate::models::activity_model::{Activity, DecodedCall, Direction, InteractionKind};
+use crate::models::starknet_model::{felt_to_u256, starknet_keccak, ContractCall};
+use alloy::primitives::U256;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Entry points common enough to name without fetching the contract's ABI.
+const KNOWN_FUNCTIONS: [&str; 22] = [
+    "transfer",
+    "transfer_from",
+    "transferFrom",
+    "approve",
+    "increase_allowance",
+    "increaseAllowance",
+    "decrease_allowance",
+    "decreaseAllowance",
+    "set_approval_for_all",
+    "setApprovalForAll",
+    "safe_transfer_from",
+    "safeTransferFrom",
+    "swap",
+    "multi_route_swap",
+    "swap_exact_token_to",
+    "swap_exact_tokens_for_tokens",
+    "deposit",
+    "withdraw",
+    "mint",
+    "burn",
+    "claim",
+    "stake",
+];
+
+pub struct KnownToken {
+    pub address: &'static str,
+    pub symbol: &'static str,
+    pub decimals: u8,
+    /// Allowances from this amount (raw units) up are flagged as large.
+    pub large_allowance: u128,
+}
+
+/// Tokens whose amounts are rendered with their symbol and decimals.
+pub const KNOWN_TOKENS: [KnownToken; 5] = [
+    KnownToken {
+        address: "0x049d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7",
+        symbol: "ETH",
+        decimals: 18,
+        large_allowance: 5_000_000_000_000_000_000,
+    },
+    KnownToken {
+        address: "0x04718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d",
+        symbol: "STRK",
+        decimals: 18,
+        large_allowance: 10_000_000_000_000_000_000_000,
+    },
+    KnownToken {
+        address: "0x053c91253bc9682c04929ca02ed00b3e423f6710d2ee7e0d5ebb06f3ecf368a8",
+        symbol: "USDC",
+        decimals: 6,
+        large_allowance: 10_000_000_000,
+    },
+    KnownToken {
+        address: "0x068f5c6a61780768455de69077e07e89787839bf8166decfbf92b645209c0fb8",
+        symbol: "USDT",
+        decimals: 6,
+        large_allowance: 10_000_000_000,
+    },
+    KnownToken {
+        address: "0x03fe2b97c1fd336e750087d68b9b867997fd64a2661ff3ca5a7c771641e8e7ac",
+        symbol: "WBTC",
+        decimals: 8,
+        large_allowance: 20_000_000,
+    },
+];
+
+static KNOWN_SELECTORS: OnceLock<HashMap<U256, &'static str>> = OnceLock::new();
+
+pub fn known_function_name(selector: &str) -> Option<&'static str> {
+    let selectors = KNOWN_SELECTORS.get_or_init(|| {
+        KNOWN_FUNCTIONS
+            .iter()
+            .map(|name| (starknet_keccak(name), *name))
+            .collect()
+    });
+    selectors.get(&felt_to_u256(selector)?).copied()
+}
+
+pub fn known_token(address: &str) -> Option<&'static KnownToken> {
+    let address = felt_to_u256(address)?;
+    KNOWN_TOKENS
+        .iter()
+        .find(|token| felt_to_u256(token.address) == Some(address))
+}
+
+/// Decodes `call` for display, naming it with `function_name` when the caller resolved one.
+pub fn decode_call(call: &ContractCall, function_name: Option<String>) -> DecodedCall {
+    let summary = summarize_call(call, function_name.as_deref());
+    DecodedCall {
+        contract_address: call.to.clone(),
+        selector: call.selector.clone(),
+        function_name,
+        calldata: call.calldata.clone(),
+        summary,
+    }
+}
+
+fn summarize_call(call: &ContractCall, function_name: Option<&str>) -> String {
+    let args = &call.calldata;
+    let amount_at = |index: usize| -> Option<String> {
+        let low = felt_to_u256(args.get(index)?)?;
+        let high = felt_to_u256(args.get(index + 1)?)?;
+        Some(format_amount(low + (high << 128), &call.to))
+    };
+
+    let summary = match function_name {
+        Some("transfer") => {
+            amount_at(1).map(|amount| format!("transfer {} to {}", amount, short_felt(&args[0])))
+        }
+        Some("transfer_from" | "transferFrom") => amount_at(2).map(|amount| {
+            format!(
+                "transfer {} from {} to {}",
+                amount,
+                short_felt(&args[0]),
+                short_felt(&args[1])
+            )
+        }),
+        Some("approve" | "increase_allowance" | "increaseAllowance") => {
+            amount_at(1).map(|amount| format!("approve {} to {}", amount, short_felt(&args[0])))
+        }
+        _ => None,
+    };
+
+    summary.unwrap_or_else(|| match function_name {
+        Some(name) => format!("call {} on {}", name, short_felt(&call.to)),
+        None => format!(
+            "call {} on {}",
+            short_felt(&call.selector),
+            short_felt(&call.to)
+        ),
+    })
+}
+
+/// One line describing `activity` from the watched wallet's or contract's side.
+pub fn summarize_activity(activity: &Activity) -> String {
+    if !activity.calls.is_empty() {
+        let summaries: Vec<&str> = activity
+            .calls
+            .iter()
+            .map(|call| call.summary.as_str())
+            .collect();
+        return summaries.join("; ");
+    }
+    let amount = |amount: &str| U256::from_str_radix(amount, 10).unwrap_or_default();
+    if let Some(approval) = &activity.approval {
+        return format!(
+            "approve {} to {}",
+            format_amount(amount(&approval.amount), &approval.token_address),
+            short_felt(&approval.spender)
+        );
+    }
+
+    match (
+        &activity.interaction,
+        &activity.direction,
+        &activity.transfer,
+    ) {
+        (Some(interaction), _, _) => match interaction.kind {
+            InteractionKind::Call => format!(
+                "called by {}",
+                interaction
+                    .caller
+                    .as_deref()
+                    .map(short_felt)
+                    .unwrap_or("an unknown account".to_string())
+            ),
+            InteractionKind::Event => {
+                format!("emitted event {}", short_felt(&interaction.selector))
+            }
+        },
+        (None, Direction::Outgoing, Some(transfer)) => format!(
+            "sent {} to {}",
+            format_amount(amount(&transfer.amount), &transfer.token_address),
+            short_felt(&transfer.counterparty)
+        ),
+        (None, Direction::Incoming, Some(transfer)) => format!(
+            "received {} from {}",
+            format_amount(amount(&transfer.amount), &transfer.token_address),
+            short_felt(&transfer.counterparty)
+        ),
+        (None, Direction::Outgoing, None) => "sent a transaction".to_string(),
+        (None, Direction::Incoming, None) => "received a transaction".to_string(),
+    }
+}
+
+/// Renders `amount` of `token_address` with its symbol when the token is known, and in raw
+/// units otherwise.
+pub fn format_amount(amount: U256, token_address: &str) -> String {
+    let token = match known_token(token_address) {
+        Some(token) => token,
+        None => return format!("{} units of {}", amount, short_felt(token_address)),
+    };
+
+    let scale = U256::from(10).pow(U256::from(token.decimals));
+    let whole = amount / scale;
+    let fraction = amount % scale;
+    if fraction.is_zero() {
+        return format!("{} {}", whole, token.symbol);
+    }
+    let fraction = format!("{:0>width$}", fraction, width = token.decimals as usize);
+    format!(
+        "{}.{} {}",
+        whole,
+        fraction.trim_end_matches('0'),
+        token.symbol
+    )
+}
+
+/// Shortens a felt or address to `0x0123…cdef`.
+pub fn short_felt(felt: &str) -> String {
+    let digits = felt.trim_start_matches("0x");
+    if digits.len() <= 8 {
+        return felt.to_string();
+    }
+    format!("0x{}…{}", &digits[..4], &digits[digits.len() - 4..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::activity_model::{AllowanceRisk, Approval, TokenTransfer};
+    use crate::models::network_model::Network;
+
+    const USDC: &str = "0x053c91253bc9682c04929ca02ed00b3e423f6710d2ee7e0d5ebb06f3ecf368a8";
+    const UNKNOWN_TOKEN: &str =
+        "0x0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+    const RECIPIENT: &str = "0x0213c67ed78bc280887234fe5ed5e77272465317978ae86c25a71531d9332a2d";
+
+    fn selector(name: &str) -> String {
+        format!("{:#x}", starknet_keccak(name))
+    }
+
+    fn call(to: &str, name: &str, calldata: &[&str]) -> ContractCall {
+        ContractCall {
+            to: to.to_string(),
+            selector: selector(name),
+            calldata: calldata.iter().map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn known_functions_are_named_from_their_selector() {
+        assert_eq!(known_function_name(&selector("transfer")), Some("transfer"));
+        assert_eq!(known_function_name(&selector("approve")), Some("approve"));
+        assert_eq!(known_function_name(&selector("rug_pull")), None);
+        assert_eq!(known_function_name("not a felt"), None);
+    }
+
+    #[test]
+    fn format_amount_uses_the_token_decimals() {
+        assert_eq!(format_amount(U256::from(1_500_000u64), USDC), "1.5 USDC");
+        assert_eq!(format_amount(U256::from(2_000_000u64), USDC), "2 USDC");
+        assert_eq!(format_amount(U256::from(1u64), USDC), "0.000001 USDC");
+        // Known tokens are found whatever the address' padding or case.
+        let unpadded = USDC
+            .replacen("0x0", "0x", 1)
+            .to_uppercase()
+            .replacen("0X", "0x", 1);
+        assert_eq!(
+            format_amount(U256::from(250_000_000u64), &unpadded),
+            "250 USDC"
+        );
+    }
+
+    #[test]
+    fn format_amount_falls_back_to_raw_units() {
+        assert_eq!(
+            format_amount(U256::from(42u64), UNKNOWN_TOKEN),
+            "42 units of 0x0123…cdef"
+        );
+    }
+
+    #[test]
+    fn summarize_call_describes_transfers_and_approvals() {
+        let transfer = call(USDC, "transfer", &[RECIPIENT, "0x16e360", "0x0"]);
+        assert_eq!(
+            summarize_call(&transfer, Some("transfer")),
+            "transfer 1.5 USDC to 0x0213…2a2d"
+        );
+
+        let transfer_from = call(
+            USDC,
+            "transfer_from",
+            &[RECIPIENT, UNKNOWN_TOKEN, "0xf4240", "0x0"],
+        );
+        assert_eq!(
+            summarize_call(&transfer_from, Some("transfer_from")),
+            "transfer 1 USDC from 0x0213…2a2d to 0x0123…cdef"
+        );
+
+        // The u256 high word counts for 2^128 raw units.
+        let approve = call(USDC, "approve", &[RECIPIENT, "0x0", "0x1"]);
+        assert_eq!(
+            summarize_call(&approve, Some("approve")),
+            format!(
+                "approve {} to 0x0213…2a2d",
+                format_amount(U256::from(1) << 128, USDC)
+            )
+        );
+    }
+
+    #[test]
+    fn summarize_call_falls_back_to_the_function_or_selector() {
+        // Too few arguments to read an amount.
+        let truncated = call(USDC, "transfer", &[RECIPIENT]);
+        assert_eq!(
+            summarize_call(&truncated, Some("transfer")),
+            "call transfer on 0x053c…68a8"
+        );
+
+        let unnamed = call(USDC, "rug_pull", &[]);
+        let expected = format!("call {} on 0x053c…68a8", short_felt(&selector("rug_pull")));
+        assert_eq!(summarize_call(&unnamed, None), expected);
+    }
+
+    #[test]
+    fn decode_call_keeps_the_call_and_its_summary() {
+        let transfer = call(USDC, "transfer", &[RECIPIENT, "0xf4240", "0x0"]);
+        let decoded = decode_call(&transfer, Some("transfer".to_string()));
+        assert_eq!(decoded.contract_address, USDC);
+        assert_eq!(decoded.function_name.as_deref(), Some("transfer"));
+        assert_eq!(decoded.calldata, transfer.calldata);
+        assert_eq!(decoded.summary, "transfer 1 USDC to 0x0213…2a2d");
+    }
+
+    #[test]
+    fn summarize_activity_prefers_calls_then_approvals_then_transfers() {
+        let activity = Activity::new(
+            Network::Starknet,
+            1,
+            "0x1".to_string(),
+            RECIPIENT.to_string(),
+            Direction::Incoming,
+        );
+        assert_eq!(summarize_activity(&activity), "received a transaction");
+
+        let received = activity.clone().with_transfer(
+            0,
+            TokenTransfer {
+                token_address: USDC.to_string(),
+                amount: "3000000".to_string(),
+                counterparty: UNKNOWN_TOKEN.to_string(),
+            },
+        );
+        assert_eq!(
+            summarize_activity(&received),
+            "received 3 USDC from 0x0123…cdef"
+        );
+
+        let approved = received.clone().with_approval(Approval {
+            token_address: USDC.to_string(),
+            spender: UNKNOWN_TOKEN.to_string(),
+            amount: "1000000".to_string(),
+            risk: AllowanceRisk::Large,
+        });
+        assert_eq!(
+            summarize_activity(&approved),
+            "approve 1 USDC to 0x0123…cdef"
+        );
+
+        let mut called = approved;
+        called.calls = vec![
+            decode_call(
+                &call(USDC, "approve", &[RECIPIENT, "0x1", "0x0"]),
+                Some("approve".to_string()),
+            ),
+            decode_call(&call(USDC, "rug_pull", &[]), Some("rug_pull".to_string())),
+        ];
+        assert_eq!(
+            summarize_activity(&called),
+            "approve 0.000001 USDC to 0x0213…2a2d; call rug_pull on 0x053c…68a8"
+        );
+    }
+
+    #[test]
+    fn short_felt_keeps_short_values() {
+        assert_eq!(short_felt("0x1234"), "0x1234");
+        assert_eq!(short_felt(RECIPIENT), "0x0213…2a2d");
+    }
+}
@@ -0,0 +1,122 @@
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+
+/// A request received by an `HttpStub`.
+#[derive(Debug)]
+pub struct RecordedRequest {
+    /// e.g. `POST /hook HTTP/1.1`.
+    pub request_line: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// An HTTP server on a local port that answers every request with `status` and records
+/// it, for testing outgoing requests.
+pub struct HttpStub {
+    pub url: String,
+    requests: Receiver<RecordedRequest>,
+}
+
+impl HttpStub {
+    pub fn start(status: u16) -> Self {
+        HttpStub::serve(move |_| (status, String::new()))
+    }
+
+    /// A JSON-RPC endpoint answering each call, single or batched, with `handler(method,
+    /// params)`: `Ok` becomes the call's `result` and `Err` its `error`.
+    pub fn json_rpc(
+        handler: impl Fn(&str, &Value) -> Result<Value, Value> + Send + 'static,
+    ) -> Self {
+        HttpStub::serve(move |request| {
+            let answer = |call: &Value| {
+                let method = call["method"].as_str().unwrap_or_default();
+                match handler(method, &call["params"]) {
+                    Ok(result) => json!({"jsonrpc": "2.0", "id": call["id"], "result": result}),
+                    Err(error) => json!({"jsonrpc": "2.0", "id": call["id"], "error": error}),
+                }
+            };
+            let body = match serde_json::from_str(&request.body) {
+                Ok(Value::Array(calls)) => Value::Array(calls.iter().map(answer).collect()),
+                Ok(call) => answer(&call),
+                Err(_) => return (400, String::new()),
+            };
+            (200, body.to_string())
+        })
+    }
+
+    /// Answers each request with the status and body `handler` returns for it.
+    pub fn serve(handler: impl Fn(&RecordedRequest) -> (u16, String) + Send + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind the stub");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, requests) = channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+
+                let mut headers = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        headers.push((name.trim().to_string(), value.trim().to_string()));
+                    }
+                }
+                let length = headers
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                    .and_then(|(_, value)| value.parse::<usize>().ok())
+                    .unwrap_or(0);
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let request = RecordedRequest {
+                    request_line: request_line.trim_end().to_string(),
+                    headers,
+                    body: String::from_utf8_lossy(&body).to_string(),
+                };
+                let (status, body) = handler(&request);
+                // Recorded before answering, so a client that got its response sees it.
+                let _ = sender.send(request);
+                let response = format!(
+                    "HTTP/1.1 {status} STUB\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        HttpStub { url, requests }
+    }
+
+    /// The next request received, waiting up to five seconds for it.
+    pub fn next_request(&self) -> RecordedRequest {
+        self.requests
+            .recv_timeout(Duration::from_secs(5))
+            .expect("the stub received no request")
+    }
+
+    /// The requests received so far, without waiting.
+    pub fn received(&self) -> Vec<RecordedRequest> {
+        self.requests.try_iter().collect()
+    }
+}
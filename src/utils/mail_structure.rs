@@ -1,27 +1,458 @@
-pub fn write_mail(
-    name: String,
-    user_email: String,
-    blockchain: String,
-    transaction_url: String,
-    wallet_address: String,
-) -> String {
-    let address_structure: String = format!(
-        "{}.....{}",
-        wallet_address[0..7].to_string(),
-        wallet_address[wallet_address.len() - 7..wallet_address.len()].to_string()
-    );
-
-    let body = format!(
-        "
-        Dear {},
-        Your wallet address {}, Just trigered a transaction on {}, Find more details about this transaction by accessing starkscan through this link:
-        {}.
-
-        You've received this mail because you subscribed for notifications to this email {}, on Kharon. 
-        Thank you. 
-    
-        ", name, address_structure, blockchain, transaction_url, user_email
-    );
-
-    return body;
+use crate::models::activity_model::{
+    Activity, ActivityKind, AllowanceRisk, Approval, ContractInteraction, Direction,
+    ExecutionDetails, Fee, InteractionKind, TokenTransfer,
+};
+use crate::models::address_model::display_address;
+use crate::models::balance_model::{BalanceCrossing, BalanceWatch};
+use crate::models::evm_model::APPROVAL_TOPIC;
+use crate::models::network_model::Network;
+use crate::models::notification_model::{Notification, NotificationStage};
+use crate::models::starknet_model::APPROVAL_EVENT_SELECTOR;
+use crate::models::user_model::{Language, User};
+use crate::utils::calldata_decoder::{format_amount, short_felt, KNOWN_TOKENS};
+use alloy::primitives::U256;
+use dotenv::dotenv;
+use serde::Serialize;
+use std::env;
+use std::error::Error;
+use std::sync::OnceLock;
+use tera::{Context, Tera};
+
+const DEFAULT_TEMPLATES_DIR: &str = "templates";
+
+static TEMPLATES: OnceLock<Tera> = OnceLock::new();
+
+/// A notification rendered from its templates: `{language}/{template}.subject`, `.txt`
+/// and `.html` under `mail/` in `MAIL_TEMPLATES_DIR`.
+#[derive(Serialize, Debug, Clone)]
+pub struct RenderedMail {
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+/// What the activity was, worded by the templates.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MailAction {
+    Called {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        caller: Option<String>,
+    },
+    Emitted {
+        selector: String,
+    },
+    Sent {
+        amount: String,
+        counterparty: String,
+    },
+    Received {
+        amount: String,
+        counterparty: String,
+    },
+    Outgoing,
+    Incoming,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MailExecution {
+    pub status: String,
+    pub finality: Option<String>,
+    pub fee: Option<String>,
+    pub revert_reason: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MailApproval {
+    pub unlimited: bool,
+    /// The allowance, for large but limited ones.
+    pub amount: Option<String>,
+    pub token: String,
+    pub spender: String,
+}
+
+/// Context of the `activity` templates.
+#[derive(Serialize, Debug, Clone)]
+pub struct ActivityMail {
+    pub name: String,
+    pub email: String,
+    pub network: String,
+    pub explorer_name: String,
+    pub explorer_url: String,
+    /// `wallet` or `contract`.
+    pub watched: &'static str,
+    pub address: String,
+    pub action: MailAction,
+    pub calls: Vec<String>,
+    pub execution: Option<MailExecution>,
+    pub stage: NotificationStage,
+    pub follow_up: bool,
+    pub reference: String,
+    pub approval: Option<MailApproval>,
+}
+
+impl ActivityMail {
+    pub fn new(user: &User, activity: &Activity, notification: &Notification) -> Self {
+        let network = &activity.network;
+
+        let action = match (
+            &activity.interaction,
+            &activity.direction,
+            &activity.transfer,
+        ) {
+            (Some(interaction), _, _) => match interaction.kind {
+                InteractionKind::Call => MailAction::Called {
+                    caller: interaction.caller.as_deref().map(short_felt),
+                },
+                InteractionKind::Event => MailAction::Emitted {
+                    selector: short_felt(&interaction.selector),
+                },
+            },
+            (None, Direction::Outgoing, None) => MailAction::Outgoing,
+            (None, Direction::Incoming, None) => MailAction::Incoming,
+            (None, Direction::Outgoing, Some(transfer)) => MailAction::Sent {
+                amount: transfer_amount(transfer),
+                counterparty: display_address(network, &transfer.counterparty),
+            },
+            (None, Direction::Incoming, Some(transfer)) => MailAction::Received {
+                amount: transfer_amount(transfer),
+                counterparty: display_address(network, &transfer.counterparty),
+            },
+        };
+
+        // A dropped transaction's stored activity only describes its pending state.
+        let execution = match (&activity.execution, &notification.stage) {
+            (_, NotificationStage::Dropped) | (None, _) => None,
+            (Some(execution), _) => Some(MailExecution {
+                status: execution.execution_status.clone(),
+                finality: execution.finality_status.clone(),
+                fee: execution
+                    .actual_fee
+                    .as_ref()
+                    .map(|fee| format!("{} {}", fee.amount, fee.unit)),
+                revert_reason: execution.revert_reason.clone(),
+            }),
+        };
+
+        let approval = activity.approval.as_ref().map(|approval| MailApproval {
+            unlimited: approval.risk == AllowanceRisk::Unlimited,
+            amount: match approval.risk {
+                AllowanceRisk::Unlimited => None,
+                AllowanceRisk::Large => {
+                    let amount = U256::from_str_radix(&approval.amount, 10).unwrap_or_default();
+                    Some(format_amount(amount, &approval.token_address))
+                }
+            },
+            token: short_felt(&approval.token_address),
+            spender: display_address(network, &approval.spender),
+        });
+
+        ActivityMail {
+            name: user.name.clone(),
+            email: user.email.clone(),
+            network: network.as_str().unwrap_or("Starknet".to_string()),
+            explorer_name: network.explorer_name(),
+            explorer_url: network.tx_explorer_url(&activity.transaction_hash),
+            watched: if activity.interaction.is_some() {
+                "contract"
+            } else {
+                "wallet"
+            },
+            address: display_address(network, &activity.wallet_address),
+            action,
+            calls: activity
+                .calls
+                .iter()
+                .map(|call| call.summary.clone())
+                .collect(),
+            execution,
+            stage: notification.stage.clone(),
+            follow_up: notification.follow_up,
+            reference: notification.notification_id.clone(),
+            approval,
+        }
+    }
+}
+
+fn transfer_amount(transfer: &TokenTransfer) -> String {
+    let amount = U256::from_str_radix(&transfer.amount, 10).unwrap_or_default();
+    format_amount(amount, &transfer.token_address)
+}
+
+/// Context of the `balance` templates.
+#[derive(Serialize, Debug, Clone)]
+pub struct BalanceMail {
+    pub name: String,
+    pub email: String,
+    pub network: String,
+    pub address: String,
+    pub token: String,
+    /// `below` or `above`.
+    pub crossing: &'static str,
+    pub threshold: String,
+    pub balance: String,
+}
+
+impl BalanceMail {
+    pub fn new(
+        user: &User,
+        watch: &BalanceWatch,
+        balance: U256,
+        crossing: &BalanceCrossing,
+    ) -> Self {
+        let token = &watch.token_address;
+        let (crossing, threshold) = match crossing {
+            BalanceCrossing::FellBelow(threshold) => ("below", threshold),
+            BalanceCrossing::RoseAbove(threshold) => ("above", threshold),
+        };
+        BalanceMail {
+            name: user.name.clone(),
+            email: user.email.clone(),
+            network: watch.network.as_str().unwrap_or("Starknet".to_string()),
+            address: display_address(&watch.network, &watch.wallet_address),
+            token: short_felt(token),
+            crossing,
+            threshold: format_amount(*threshold, token),
+            balance: format_amount(balance, token),
+        }
+    }
+}
+
+/// Templates are loaded once, on first use, so a broken template is reported per mail
+/// until it is fixed and the server restarted.
+fn templates() -> Result<&'static Tera, String> {
+    if let Some(templates) = TEMPLATES.get() {
+        return Ok(templates);
+    }
+    dotenv().ok();
+    let dir = env::var("MAIL_TEMPLATES_DIR").unwrap_or_else(|_| DEFAULT_TEMPLATES_DIR.to_string());
+    let templates =
+        Tera::new(&format!("{}/mail/**/*", dir.trim_end_matches('/'))).map_err(|e| {
+            format!(
+                "Could not load mail templates from {dir}: {}",
+                error_chain(&e)
+            )
+        })?;
+    Ok(TEMPLATES.get_or_init(|| templates))
+}
+
+// Tera puts the actual cause, e.g. the missing variable, in the error's source.
+fn error_chain(error: &tera::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+    message
+}
+
+/// Renders `template` in the user's language, falling back to English for missing files.
+pub fn render_mail<T: Serialize>(
+    template: &str,
+    language: &Language,
+    mail: &T,
+) -> Result<RenderedMail, String> {
+    let templates = templates()?;
+    let context = Context::from_serialize(mail).map_err(|e| error_chain(&e))?;
+    let render = |extension: &str| -> Result<String, String> {
+        let localized = format!("{}/{}.{}", language.code(), template, extension);
+        let name = if templates.get_template_names().any(|name| name == localized) {
+            localized
+        } else {
+            format!("{}/{}.{}", Language::English.code(), template, extension)
+        };
+        templates
+            .render(&name, &context)
+            .map_err(|e| format!("Could not render {name}: {}", error_chain(&e)))
+    };
+
+    Ok(RenderedMail {
+        subject: render("subject")?.trim().to_string(),
+        text: render("txt")?,
+        html: render("html")?,
+    })
+}
+
+/// Renders `template` with sample data, for checking templates without waiting for
+/// matching activity. `kind` picks the sample activity of the `activity` template and
+/// `follow_up` whether it follows up on an earlier pending alert.
+pub fn preview_mail(
+    template: &str,
+    language: &Language,
+    network: &Network,
+    stage: NotificationStage,
+    kind: ActivityKind,
+    follow_up: bool,
+) -> Result<RenderedMail, String> {
+    let user = User::new(
+        "Ada Lovelace".to_string(),
+        "ada@example.com".to_string(),
+        Vec::new(),
+    )?;
+    let (wallet, counterparty, token, approval_selector) = match network {
+        Network::Starknet => (
+            "0x07b649b20453b7efd8168056287540fbae24da819348689a7592e2ea55d0680d",
+            "0x0213c67ed78bc280887234fe5ed5e77272465317978ae86c25a71531d9332a2d",
+            KNOWN_TOKENS[2].address,
+            APPROVAL_EVENT_SELECTOR,
+        ),
+        _ => (
+            "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed",
+            "0xfb6916095ca1df60bb79ce92ce3ea74c37c5d359",
+            "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+            APPROVAL_TOPIC,
+        ),
+    };
+
+    match template {
+        "activity" => {
+            let sample = |watched: &str, direction: Direction| {
+                Activity::new(
+                    network.clone(),
+                    1_024_512,
+                    "0x04a1c3f5e6b7d8e9f0a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f607"
+                        .to_string(),
+                    watched.to_string(),
+                    direction,
+                )
+            };
+            let mut activity = match kind {
+                ActivityKind::Transaction => sample(wallet, Direction::Outgoing),
+                ActivityKind::Transfer => sample(wallet, Direction::Outgoing).with_transfer(
+                    0,
+                    TokenTransfer {
+                        token_address: token.to_string(),
+                        amount: "250000000".to_string(),
+                        counterparty: counterparty.to_string(),
+                    },
+                ),
+                ActivityKind::Approval => sample(wallet, Direction::Outgoing).with_approval_event(
+                    0,
+                    Approval {
+                        token_address: token.to_string(),
+                        spender: counterparty.to_string(),
+                        amount: U256::MAX.to_string(),
+                        risk: AllowanceRisk::Unlimited,
+                    },
+                ),
+                ActivityKind::Interaction => sample(token, Direction::Incoming).with_interaction(
+                    Some(0),
+                    ContractInteraction {
+                        kind: InteractionKind::Event,
+                        selector: approval_selector.to_string(),
+                        caller: Some(wallet.to_string()),
+                    },
+                ),
+            };
+            if stage != NotificationStage::PreConfirmed {
+                let reverted = stage == NotificationStage::Reverted;
+                activity = activity.with_execution(ExecutionDetails {
+                    execution_status: if reverted { "REVERTED" } else { "SUCCEEDED" }.to_string(),
+                    finality_status: None,
+                    actual_fee: Some(Fee {
+                        amount: "21000000000000".to_string(),
+                        unit: "WEI".to_string(),
+                    }),
+                    revert_reason: reverted.then(|| "Insufficient balance".to_string()),
+                });
+            }
+            let mut notification = Notification::new(&activity, user.user_uuid.clone(), stage);
+            notification.follow_up = follow_up;
+            render_mail(
+                template,
+                language,
+                &ActivityMail::new(&user, &activity, &notification),
+            )
+        }
+        "balance" => {
+            let watch = BalanceWatch::new(
+                wallet.to_string(),
+                network.as_str()?,
+                token.to_string(),
+                Some("1000000000".to_string()),
+                None,
+            )?;
+            let mail = BalanceMail::new(
+                &user,
+                &watch,
+                U256::from(250_000_000u64),
+                &BalanceCrossing::FellBelow(U256::from(1_000_000_000u64)),
+            );
+            render_mail(template, language, &mail)
+        }
+        _ => Err("Invalid template, expected activity or balance".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preview(language: Language, stage: NotificationStage, follow_up: bool) -> RenderedMail {
+        preview_mail(
+            "activity",
+            &language,
+            &Network::Starknet,
+            stage,
+            ActivityKind::Transfer,
+            follow_up,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn confirmed_previews_are_plain_notifications_by_default() {
+        let mail = preview(Language::English, NotificationStage::Confirmed, false);
+        assert_eq!(mail.subject, "Transaction Notification on Starknet");
+        assert!(!mail.text.contains("Following up"));
+        assert!(!mail.html.contains("Following up"));
+
+        let mail = preview(Language::French, NotificationStage::Confirmed, false);
+        assert_eq!(mail.subject, "Notification de transaction sur Starknet");
+        assert!(!mail.text.contains("Suite à la notification"));
+    }
+
+    #[test]
+    fn follow_up_previews_reference_the_pending_alert() {
+        let mail = preview(Language::English, NotificationStage::Confirmed, true);
+        assert!(mail
+            .text
+            .contains("this transaction has now been confirmed"));
+        assert!(mail
+            .html
+            .contains("this transaction has now been confirmed"));
+
+        let mail = preview(Language::French, NotificationStage::Reverted, true);
+        assert_eq!(mail.subject, "Transaction annulée sur Starknet");
+        assert!(mail.text.contains("Suite à la notification"));
+        assert!(mail.text.contains("cette transaction a été annulée"));
+    }
+
+    #[test]
+    fn reverted_previews_without_follow_up_only_report_the_revert() {
+        let mail = preview(Language::English, NotificationStage::Reverted, false);
+        assert_eq!(mail.subject, "Reverted Transaction on Starknet");
+        assert!(mail.text.contains("This transaction was reverted."));
+        assert!(!mail.text.contains("Following up"));
+    }
+
+    #[test]
+    fn every_template_renders_in_every_language() {
+        for language in [Language::English, Language::French] {
+            for network in [Network::Starknet, Network::Ethereum] {
+                for template in ["activity", "balance"] {
+                    let mail = preview_mail(
+                        template,
+                        &language,
+                        &network,
+                        NotificationStage::PreConfirmed,
+                        ActivityKind::Approval,
+                        false,
+                    );
+                    assert!(mail.is_ok(), "{template} in {language:?}: {mail:?}");
+                }
+            }
+        }
+    }
 }
@@ -1,2 +1,6 @@
 pub mod api_response;
+pub mod approval_detector;
+pub mod calldata_decoder;
+#[cfg(test)]
+pub mod http_stub;
 pub mod mail_structure;